@@ -0,0 +1,137 @@
+use std::time::Duration;
+
+use crate::physics::{body::RigidBody, math::Quaternion, torque::Torque, util::vector::Vector3};
+
+use super::{ControlCommand, Pid, PidGains, Signal};
+
+/// Where the attitude controller is asked to point the drone.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AttitudeSetpoint {
+    pub orientation: Quaternion,
+    /// Collective thrust (N), passed straight through to the command
+    pub thrust: f64,
+}
+
+/// # Overview
+/// The classic cascaded attitude controller, two loops one inside the other:
+///
+/// 1. **Attitude loop**, a P controller on the rotation between where we
+///    point and where we want to point. Its output is how fast we should be
+///    turning to get there, the *rate setpoint*.
+/// 2. **Rate loop**, a PID on the body rates (from the gyro on a real drone)
+///    chasing that rate setpoint, which works out the angular acceleration
+///    we need.
+///
+/// The inner rate loop runs on the quickest signal and sorts out
+/// disturbances before they can tip the drone far, leaving the outer loop
+/// an easy job.
+///
+/// Angular acceleration becomes torque through the body's inertia, plus the
+/// gyroscopic `ω × Iω` the body's own spin needs, so the same gains suit any
+/// size of drone.
+#[derive(Debug, Clone)]
+pub struct AttitudeController {
+    /// Per axis gain (1/s) from attitude error (rad) to rate setpoint (rad/s)
+    pub attitude_gain: Vector3,
+    /// Largest rate (rad/s) the attitude loop will ask for on each axis
+    pub max_rate: Vector3,
+    /// Body rate error (rad/s) to angular acceleration (rad/s²)
+    pub rate_pid: Pid<Vector3>,
+}
+
+impl Default for AttitudeController {
+    fn default() -> Self {
+        // Tuned for the default drone, which is big and slow to turn. The
+        // output limits are about what its rotors can manage, so the rate
+        // loop doesn't wind up asking for more
+        Self {
+            attitude_gain: Vector3::new(3.0, 1.5, 3.0),
+            max_rate: Vector3::new(1.5, 0.5, 1.5),
+            rate_pid: Pid::new(PidGains::uniform(10.0, 2.0, 0.0))
+                .with_output_limits(Vector3::new(-2.0, -0.2, -2.0), Vector3::new(2.0, 0.2, 2.0))
+                .with_integral_limits(Vector3::splat(-1.0), Vector3::splat(1.0)),
+        }
+    }
+}
+
+impl AttitudeController {
+    /// The rate setpoint (rad/s, body frame) that turns `orientation` towards
+    /// `target`.
+    ///
+    /// The error quaternion `q⁻¹ q_target` is the rotation still to go, seen
+    /// from the body. Its vector part is `axis ⋅ sin(θ/2)`, which is about
+    /// `axis ⋅ θ/2` for small errors. Flipping it when `w < 0` makes sure we
+    /// take the short way round.
+    pub fn rate_setpoint(&self, orientation: Quaternion, target: Quaternion) -> Vector3 {
+        let error = orientation.conjugate() * target;
+        let direction = match error.w() < 0.0 {
+            true => -1.0,
+            false => 1.0,
+        };
+        let rotation = error.vector().scalar_mul(2.0 * direction);
+        let limit = self.max_rate;
+        self.attitude_gain
+            .component_mul(rotation)
+            .clamp_between(-limit, limit)
+    }
+
+    pub fn update(
+        &mut self,
+        setpoint: &AttitudeSetpoint,
+        body: &RigidBody,
+        dt: Duration,
+    ) -> ControlCommand {
+        let rates = self.rate_setpoint(*body.orientation, setpoint.orientation);
+        self.update_rates(rates, setpoint.thrust, body, dt)
+    }
+
+    /// Runs only the inner rate loop, for when something else decides the
+    /// rates, e.g. a pilot's sticks in acro mode.
+    pub fn update_rates(
+        &mut self,
+        rates: Vector3,
+        thrust: f64,
+        body: &RigidBody,
+        dt: Duration,
+    ) -> ControlCommand {
+        let omega = body.angular_velocity.0;
+        let angular_acceleration = self.rate_pid.update(rates, omega, dt);
+        let inertia = body.inertia();
+        let gyroscopic = omega.cross(&(inertia * omega));
+        ControlCommand {
+            thrust,
+            torque: Torque(inertia * angular_acceleration + gyroscopic),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.rate_pid.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::harness::{armed, fly, DT};
+
+    #[test]
+    fn test_levels_a_tilted_drone() {
+        let mut drone = armed();
+        *drone.body.orientation = Quaternion::new(25.0, 1.0, 0.0, 0.0);
+        drone.body.angular_velocity.y = 0.2;
+        let mut controller = AttitudeController::default();
+        let setpoint = AttitudeSetpoint {
+            orientation: Quaternion::new(20.0, 0.0, 1.0, 0.0),
+            thrust: drone.body.total_mass() * 9.81,
+        };
+
+        fly(&mut drone, 1200, |drone| {
+            controller.update(&setpoint, &drone.body, DT)
+        });
+
+        let error = drone.body.orientation.conjugate() * setpoint.orientation;
+        let angle = 2.0 * error.vector().magnitude().asin().to_degrees();
+        assert!(angle < 0.5, "still {angle}° off");
+        assert!(drone.body.angular_velocity.magnitude() < 0.01);
+    }
+}
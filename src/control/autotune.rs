@@ -0,0 +1,477 @@
+//! # Overview
+//!
+//! Finding PID gains without tuning them by hand, in two steps.
+//!
+//! # Relay feedback
+//! Swap the PID for a relay, full on below the setpoint and full off above
+//! it, and almost anything worth controlling settles into a steady
+//! oscillation. Its period is the *ultimate period* `Tu`, the period a
+//! P controller on the edge of instability would oscillate at, and from
+//! how big it is we get the *ultimate gain* `Ku`, the gain where that
+//! happens:
+//!
+//! `Ku = 4d / (π a)`
+//!
+//! for a relay switching by `±d` and an oscillation `±a`. The
+//! Ziegler–Nichols rules turn that pair into gains. They're a quick, safe
+//! experiment and a decent start, but famously aggressive.
+//!
+//! # Step response optimisation
+//! From there we polish the gains on simulated step responses, scoring
+//! each by a weighted [`TuningCost`] of overshoot, settling time, tracking
+//! error and control effort, and searching for the lowest score with the
+//! Nelder–Mead simplex method. It needs no gradients, just the score, and
+//! copes with how jumpy settling time is.
+
+use std::time::Duration;
+
+use crate::{EleaError, Result};
+
+use super::{Pid, PidGains};
+
+/// Something a controller can drive, a single input and measurement.
+pub trait Plant {
+    /// Holds `input` for `dt` and returns the measurement afterwards.
+    fn step(&mut self, input: f64, dt: Duration) -> f64;
+}
+
+impl<F: FnMut(f64, Duration) -> f64> Plant for F {
+    fn step(&mut self, input: f64, dt: Duration) -> f64 {
+        self(input, dt)
+    }
+}
+
+/// Ziegler–Nichols style rules from the ultimate gain and period, from
+/// most to least aggressive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TuningRule {
+    /// Ziegler and Nichols' original, quick but with lots of overshoot
+    #[default]
+    Classic,
+    PessenIntegral,
+    SomeOvershoot,
+    NoOvershoot,
+    /// No derivative, for noisy measurements
+    Pi,
+}
+
+impl TuningRule {
+    /// `(kp / Ku, Ti / Tu, Td / Tu)`
+    fn factors(self) -> (f64, f64, f64) {
+        match self {
+            TuningRule::Classic => (0.6, 0.5, 0.125),
+            TuningRule::PessenIntegral => (0.7, 0.4, 0.15),
+            TuningRule::SomeOvershoot => (0.33, 0.5, 0.33),
+            TuningRule::NoOvershoot => (0.2, 0.5, 0.33),
+            TuningRule::Pi => (0.45, 1.0 / 1.2, 0.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RelayExperiment {
+    /// Where the measurement oscillates about
+    pub setpoint: f64,
+    /// Output around which the relay switches, e.g. the hover thrust
+    pub bias: f64,
+    /// `d`, the relay switches to `bias ± amplitude`
+    pub amplitude: f64,
+    /// How far past the setpoint the measurement has to go before the
+    /// relay switches, so noise doesn't chatter it. It also delays the
+    /// switch, shifting the oscillation away from the true ultimate point,
+    /// so keep it no bigger than the noise
+    pub hysteresis: f64,
+    pub dt: Duration,
+    /// Give up if it hasn't settled into an oscillation by then
+    pub max_duration: Duration,
+    /// How many of the last periods to average over, the first few are
+    /// still settling
+    pub cycles: usize,
+}
+
+impl Default for RelayExperiment {
+    fn default() -> Self {
+        Self {
+            setpoint: 0.0,
+            bias: 0.0,
+            amplitude: 1.0,
+            hysteresis: 0.0,
+            dt: Duration::from_millis(10),
+            max_duration: Duration::from_secs(120),
+            cycles: 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RelayResult {
+    /// `Ku`
+    pub ultimate_gain: f64,
+    /// `Tu` (s)
+    pub ultimate_period: f64,
+}
+
+impl RelayResult {
+    pub fn gains(&self, rule: TuningRule) -> PidGains<f64> {
+        let (p, i, d) = rule.factors();
+        let kp = p * self.ultimate_gain;
+        PidGains {
+            kp,
+            ki: kp / (i * self.ultimate_period),
+            kd: kp * d * self.ultimate_period,
+        }
+    }
+}
+
+impl RelayExperiment {
+    /// Errors if `dt` or `cycles` is zero, or if `plant` never settles
+    /// into an oscillation within `max_duration`.
+    pub fn run(&self, plant: &mut impl Plant) -> Result<RelayResult> {
+        if self.dt.is_zero() || self.cycles == 0 {
+            return Err(EleaError::InvalidData(
+                "relay experiment needs a time step and at least one cycle".to_string(),
+            ));
+        }
+        let mut measurement = self.setpoint;
+        let mut high = true;
+        let mut time = 0.0;
+        // Times the measurement crossed the setpoint going up, and the
+        // extremes reached between one and the next
+        let mut crossings = Vec::new();
+        let mut peaks: Vec<(f64, f64)> = Vec::new();
+        let (mut lowest, mut highest) = (f64::INFINITY, f64::NEG_INFINITY);
+
+        while time < self.max_duration.as_secs_f64() {
+            let error = self.setpoint - measurement;
+            if error > self.hysteresis {
+                high = true;
+            } else if error < -self.hysteresis {
+                high = false;
+            }
+            let output = match high {
+                true => self.bias + self.amplitude,
+                false => self.bias - self.amplitude,
+            };
+            let previous = measurement;
+            measurement = plant.step(output, self.dt);
+            time += self.dt.as_secs_f64();
+            lowest = lowest.min(measurement);
+            highest = highest.max(measurement);
+
+            if previous < self.setpoint && measurement >= self.setpoint {
+                // Between the two samples, pro rata
+                let fraction = (self.setpoint - previous) / (measurement - previous);
+                crossings.push(time - self.dt.as_secs_f64() * (1.0 - fraction));
+                peaks.push((lowest, highest));
+                (lowest, highest) = (f64::INFINITY, f64::NEG_INFINITY);
+                // One more than needed, the first cycle is partial
+                if crossings.len() > self.cycles + 1 {
+                    break;
+                }
+            }
+        }
+        if crossings.len() <= self.cycles + 1 {
+            return Err(EleaError::InvalidData(
+                "relay experiment never settled into an oscillation".to_string(),
+            ));
+        }
+
+        let n = self.cycles as f64;
+        let recent = &crossings[crossings.len() - self.cycles - 1..];
+        let period = (recent[self.cycles] - recent[0]) / n;
+        let swing: f64 = peaks[peaks.len() - self.cycles..]
+            .iter()
+            .map(|(low, high)| (high - low) / 2.0)
+            .sum::<f64>()
+            / n;
+        Ok(RelayResult {
+            ultimate_gain: 4.0 * self.amplitude / (std::f64::consts::PI * swing.max(f64::EPSILON)),
+            ultimate_period: period,
+        })
+    }
+}
+
+/// A step from `start` to `target`, with the plant at rest at `start`.
+#[derive(Debug, Clone, Copy)]
+pub struct StepTest {
+    pub start: f64,
+    pub target: f64,
+    /// Output the plant needs to sit still, added to the PID's
+    pub bias: f64,
+    pub duration: Duration,
+    pub dt: Duration,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StepResponse {
+    /// Furthest past the target, as a fraction of the step
+    pub overshoot: f64,
+    /// Time (s) until it stays within 2% of the step of the target, or the
+    /// whole test if it never does
+    pub settling_time: f64,
+    /// `∫ |error| dt`, as a fraction of the step
+    pub integrated_error: f64,
+    /// `∫ (output - bias)² dt`
+    pub effort: f64,
+}
+
+/// Weights on each part of a [`StepResponse`], a higher total is worse.
+#[derive(Debug, Clone, Copy)]
+pub struct TuningCost {
+    pub overshoot: f64,
+    pub settling_time: f64,
+    pub integrated_error: f64,
+    pub effort: f64,
+}
+
+impl Default for TuningCost {
+    fn default() -> Self {
+        Self {
+            overshoot: 10.0,
+            settling_time: 0.5,
+            integrated_error: 1.0,
+            effort: 0.0,
+        }
+    }
+}
+
+impl TuningCost {
+    pub fn of(&self, response: &StepResponse) -> f64 {
+        self.overshoot * response.overshoot
+            + self.settling_time * response.settling_time
+            + self.integrated_error * response.integrated_error
+            + self.effort * response.effort
+    }
+}
+
+impl StepTest {
+    fn check(&self) -> Result<()> {
+        if self.dt.is_zero() {
+            return Err(EleaError::InvalidData(
+                "step test needs a time step".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Runs `pid` (a fresh copy, limits and all) against `plant`. Errors
+    /// if `dt` is zero.
+    pub fn run(&self, pid: &Pid<f64>, mut plant: impl Plant) -> Result<StepResponse> {
+        self.check()?;
+        let mut pid = pid.clone();
+        pid.reset();
+        let dt = self.dt.as_secs_f64();
+        let step = self.target - self.start;
+        let scale = step.abs().max(f64::EPSILON);
+        let mut measurement = self.start;
+        let mut response = StepResponse::default();
+        let mut time = 0.0;
+        let mut last_outside = 0.0;
+
+        while time < self.duration.as_secs_f64() {
+            let output = self.bias + pid.update(self.target, measurement, self.dt);
+            measurement = plant.step(output, self.dt);
+            time += dt;
+
+            let error = (self.target - measurement) / scale;
+            // Overshoot is past the target in the direction of the step
+            let past = -error * step.signum();
+            response.overshoot = response.overshoot.max(past);
+            if error.abs() > 0.02 || !measurement.is_finite() {
+                last_outside = time;
+            }
+            response.integrated_error += error.abs() * dt;
+            response.effort += (output - self.bias).powi(2) * dt;
+        }
+        response.settling_time = last_outside;
+        Ok(response)
+    }
+}
+
+/// # Overview
+/// Polishes `pid`'s gains against step responses from fresh plants made by
+/// `plant`, starting from its current gains and giving up after
+/// `iterations` of Nelder–Mead. Returns the best gains and their response,
+/// or the error if `test` can't be run.
+///
+/// The search works on the logarithm of each gain, so they stay positive
+/// and it moves by a similar fraction whatever their size. Gains that start
+/// at zero stay there, so a PI stays a PI.
+pub fn optimise<P: Plant>(
+    pid: &Pid<f64>,
+    mut plant: impl FnMut() -> P,
+    test: &StepTest,
+    cost: &TuningCost,
+    iterations: usize,
+) -> Result<(PidGains<f64>, StepResponse)> {
+    test.check()?;
+    let start = [pid.gains.kp, pid.gains.ki, pid.gains.kd];
+    let free: Vec<usize> = (0..3).filter(|&i| start[i] > 0.0).collect();
+    let gains_at = |point: &[f64]| {
+        let mut gains = start;
+        for (&i, value) in free.iter().zip(point) {
+            gains[i] = value.exp();
+        }
+        PidGains {
+            kp: gains[0],
+            ki: gains[1],
+            kd: gains[2],
+        }
+    };
+    let mut evaluate = |point: &[f64]| {
+        let mut candidate = pid.clone();
+        candidate.gains = gains_at(point);
+        match test
+            .run(&candidate, plant())
+            .map(|response| cost.of(&response))
+        {
+            Ok(score) if score.is_finite() => score,
+            _ => f64::INFINITY,
+        }
+    };
+
+    // Start from the current gains and a step of about 30% on each
+    let origin: Vec<f64> = free.iter().map(|&i| start[i].ln()).collect();
+    let mut simplex: Vec<(Vec<f64>, f64)> = vec![(origin.clone(), evaluate(&origin))];
+    for axis in 0..origin.len() {
+        let mut point = origin.clone();
+        point[axis] += 0.3;
+        let score = evaluate(&point);
+        simplex.push((point, score));
+    }
+
+    for _ in 0..iterations {
+        simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let (best, worst) = (simplex[0].1, simplex[simplex.len() - 1].1);
+        if simplex.len() < 2 || (worst - best).abs() < 1e-9 {
+            break;
+        }
+
+        // Reflect the worst point through the middle of the rest, then
+        // try going further, or less far, or shrink everything if all else
+        // fails
+        let n = simplex.len() - 1;
+        let centroid: Vec<f64> = (0..origin.len())
+            .map(|axis| simplex[..n].iter().map(|(p, _)| p[axis]).sum::<f64>() / n as f64)
+            .collect();
+        let towards = |factor: f64| -> Vec<f64> {
+            centroid
+                .iter()
+                .zip(&simplex[n].0)
+                .map(|(c, w)| c + factor * (c - w))
+                .collect()
+        };
+        let reflected = towards(1.0);
+        let reflected_score = evaluate(&reflected);
+        if reflected_score < simplex[0].1 {
+            let expanded = towards(2.0);
+            let expanded_score = evaluate(&expanded);
+            simplex[n] = match expanded_score < reflected_score {
+                true => (expanded, expanded_score),
+                false => (reflected, reflected_score),
+            };
+        } else if reflected_score < simplex[n - 1].1 {
+            simplex[n] = (reflected, reflected_score);
+        } else {
+            let contracted = towards(-0.5);
+            let contracted_score = evaluate(&contracted);
+            if contracted_score < simplex[n].1 {
+                simplex[n] = (contracted, contracted_score);
+            } else {
+                let best = simplex[0].0.clone();
+                for (point, score) in &mut simplex[1..] {
+                    for (value, b) in point.iter_mut().zip(&best) {
+                        *value = b + 0.5 * (*value - b);
+                    }
+                    *score = evaluate(point);
+                }
+            }
+        }
+    }
+
+    simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+    let gains = gains_at(&simplex[0].0);
+    let mut best = pid.clone();
+    best.gains = gains;
+    Ok((gains, test.run(&best, plant())?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Three first order lags in a row, `1 / (s + 1)³`, which has
+    /// `Ku = 8` and `Tu = 2π / √3`.
+    fn lags() -> impl Plant {
+        let mut state = [0.0; 3];
+        move |input: f64, dt: Duration| {
+            let dt = dt.as_secs_f64();
+            let mut drive = input;
+            for x in &mut state {
+                *x += (drive - *x) * dt;
+                drive = *x;
+            }
+            drive
+        }
+    }
+
+    #[test]
+    fn test_relay_then_optimise() {
+        let relay = RelayExperiment {
+            setpoint: 1.0,
+            bias: 1.0,
+            dt: Duration::from_millis(5),
+            ..Default::default()
+        };
+        let result = relay.run(&mut lags()).unwrap();
+        // The relay only approximates it, a few percent out is normal
+        let period = 2.0 * std::f64::consts::PI / 3f64.sqrt();
+        assert!((result.ultimate_gain - 8.0).abs() < 8.0 * 0.1, "{result:?}");
+        assert!((result.ultimate_period - period).abs() < period * 0.05);
+
+        let test = StepTest {
+            start: 0.0,
+            target: 1.0,
+            bias: 0.0,
+            duration: Duration::from_secs(30),
+            dt: Duration::from_millis(10),
+        };
+        let cost = TuningCost::default();
+        let pid = Pid::new(result.gains(TuningRule::Classic)).with_output_limits(-20.0, 20.0);
+        let before = test.run(&pid, lags()).unwrap();
+
+        let (_, after) = optimise(&pid, lags, &test, &cost, 100).unwrap();
+        assert!(cost.of(&after) < cost.of(&before) * 0.7);
+        assert!(after.overshoot < before.overshoot);
+        assert!(after.settling_time < before.settling_time);
+    }
+
+    #[test]
+    fn test_rejects_empty_experiments() {
+        let relay = RelayExperiment {
+            dt: Duration::ZERO,
+            ..Default::default()
+        };
+        assert!(relay.run(&mut lags()).is_err());
+        let relay = RelayExperiment {
+            cycles: 0,
+            ..Default::default()
+        };
+        assert!(relay.run(&mut lags()).is_err());
+
+        let test = StepTest {
+            start: 0.0,
+            target: 1.0,
+            bias: 0.0,
+            duration: Duration::from_secs(1),
+            dt: Duration::ZERO,
+        };
+        let pid = Pid::new(PidGains {
+            kp: 1.0,
+            ki: 0.0,
+            kd: 0.0,
+        });
+        assert!(test.run(&pid, lags()).is_err());
+        assert!(optimise(&pid, lags, &test, &TuningCost::default(), 10).is_err());
+    }
+}
@@ -0,0 +1,182 @@
+use std::time::Duration;
+
+use crate::physics::{
+    body::RigidBody, force::EARTH_GRAVITY_ACCELERATION, math::Matrix3, torque::Torque,
+    util::vector::Vector3,
+};
+
+use super::{ControlCommand, PositionSetpoint, Signal};
+
+/// # Overview
+/// The geometric tracking controller on SE(3) from Lee, Leok and McClamroch
+/// (2010). Rather than stacking loops on Euler angles it works on the
+/// rotation matrix itself, so it behaves the same at any attitude, even
+/// upside down, with no singularities to dodge. That makes it the one to
+/// reach for with aggressive manoeuvres, where the cascaded
+/// [`PositionController`](super::PositionController) and
+/// [`AttitudeController`](super::AttitudeController) assume small tilts.
+///
+/// # How it works
+/// 1. From the position and velocity errors work out the force we want,
+///    `F = m (-k_x e_x - k_v e_v + g ŷ + a_d)`.
+/// 2. The thrust can only push along the body's up, so the desired up is
+///    `F / |F|`. Together with the heading from the yaw that pins down a
+///    whole desired rotation `R_d`.
+/// 3. The thrust is `F` projected onto the body's *actual* up.
+/// 4. The attitude error `e_R = ½ (R_dᵀR - RᵀR_d)^∨` and the rate error
+///    `e_Ω` give the torque, along with feed-forward for how fast `R_d` is
+///    itself turning.
+///
+/// Following the paper's axes, but with `y` up rather than `z` down.
+///
+/// # Gains
+/// The force and torque gains are per unit mass and per unit inertia, so
+/// they're natural frequencies squared and damping terms, and the same
+/// gains fly any size of drone.
+#[derive(Debug, Clone)]
+pub struct GeometricController {
+    /// `k_x` (1/s²), position error to acceleration
+    pub position_gain: Vector3,
+    /// `k_v` (1/s), velocity error to acceleration
+    pub velocity_gain: Vector3,
+    /// `k_R` (1/s²) per body axis, attitude error to angular acceleration
+    pub attitude_gain: Vector3,
+    /// `k_Ω` (1/s) per body axis, rate error to angular acceleration
+    pub rate_gain: Vector3,
+    /// Largest angular acceleration (rad/s²) the feedback asks for on each
+    /// body axis, about what the rotors can give
+    pub max_angular_acceleration: Vector3,
+    /// The last desired rotation and rate, to differentiate them
+    previous: Option<(Matrix3, Vector3)>,
+}
+
+impl Default for GeometricController {
+    fn default() -> Self {
+        // Tuned for the default drone. Yaw is kept gentle, its rotors can
+        // barely twist the body round
+        Self {
+            position_gain: Vector3::splat(1.0),
+            velocity_gain: Vector3::splat(1.8),
+            attitude_gain: Vector3::new(9.0, 1.0, 9.0),
+            rate_gain: Vector3::new(6.0, 2.0, 6.0),
+            max_angular_acceleration: Vector3::new(2.0, 0.1, 2.0),
+            previous: None,
+        }
+    }
+}
+
+impl GeometricController {
+    pub fn update(
+        &mut self,
+        setpoint: &PositionSetpoint,
+        body: &RigidBody,
+        dt: Duration,
+    ) -> ControlCommand {
+        let mass = body.total_mass();
+        let position_error = body.position.0 - setpoint.position.0;
+        let velocity_error = body.linear_velocity.0 - setpoint.velocity.0;
+        let acceleration = setpoint.acceleration
+            + Vector3::new(0.0, EARTH_GRAVITY_ACCELERATION, 0.0)
+            - self.position_gain.component_mul(position_error)
+            - self.velocity_gain.component_mul(velocity_error);
+        let force = acceleration.scalar_mul(mass);
+
+        let rotation = Matrix3::from_quaternion(&body.orientation);
+        let target = desired_rotation(force, setpoint.yaw);
+        let thrust = force.dot(&rotation.column(1)).max(0.0);
+
+        // How fast the target itself turns, found by differencing
+        let dt = dt.as_secs_f64();
+        let (target_rate, target_angular_acceleration) = match (self.previous, dt > 0.0) {
+            (Some((previous, previous_rate)), true) => {
+                let rate = log_vee(previous.transpose() * target).scalar_div(dt);
+                (rate, (rate - previous_rate).scalar_div(dt))
+            }
+            _ => (Vector3::default(), Vector3::default()),
+        };
+        self.previous = Some((target, target_rate));
+
+        // Everything below is in the body frame
+        let omega = body.angular_velocity.0;
+        let relative = rotation.transpose() * target;
+        let attitude_error = (relative.transpose() - relative).vee().scalar_mul(0.5);
+        let rate_error = omega - relative * target_rate;
+        let limit = self.max_angular_acceleration;
+        let feedback = (-self.attitude_gain.component_mul(attitude_error)
+            - self.rate_gain.component_mul(rate_error))
+        .clamp_between(-limit, limit);
+        let feed_forward =
+            relative * target_angular_acceleration - omega.cross(&(relative * target_rate));
+
+        let inertia = body.inertia();
+        ControlCommand {
+            thrust,
+            torque: Torque(inertia * (feedback + feed_forward) + omega.cross(&(inertia * omega))),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.previous = None;
+    }
+}
+
+/// The rotation whose up is along `force` and whose `x` axis is as close to
+/// the `yaw` heading as that allows.
+///
+/// `x × y = z` and `y × z = x`, so crossing the heading with the up gives
+/// the `z` axis, and crossing again squares the heading up. Should the
+/// force be zero (free fall) we just stay level.
+fn desired_rotation(force: Vector3, yaw: f64) -> Matrix3 {
+    let up = match force.magnitude() > f64::EPSILON {
+        true => force.scalar_div(force.magnitude()),
+        false => Vector3::new(0.0, 1.0, 0.0),
+    };
+    let heading = Vector3::new(yaw.cos(), 0.0, -yaw.sin());
+    let z = heading.cross(&up);
+    // Heading straight along the force, any `z` at right angles will do
+    let z = match z.magnitude() > 1e-6 {
+        true => z.scalar_div(z.magnitude()),
+        false => Vector3::new(0.0, 0.0, 1.0),
+    };
+    Matrix3::from_columns(up.cross(&z), up, z)
+}
+
+/// The rotation vector (axis times angle) of a rotation matrix.
+///
+/// The angle comes from the trace, `tr R = 1 + 2 cos θ`, and the axis from
+/// the skew symmetric part `R - Rᵀ = 2 sin θ [axis]×`. Good for the small
+/// step between two setpoints, it isn't reliable close to half a turn.
+fn log_vee(rotation: Matrix3) -> Vector3 {
+    let cos = ((rotation.trace() - 1.0) / 2.0).clamp(-1.0, 1.0);
+    let angle = cos.acos();
+    let skew = (rotation - rotation.transpose()).vee();
+    match angle.sin().abs() > 1e-9 {
+        true => skew.scalar_mul(angle / (2.0 * angle.sin())),
+        false => skew.scalar_mul(0.5),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        control::harness::{armed, fly, DT},
+        physics::{math::Quaternion, state::position::Position},
+    };
+
+    #[test]
+    fn test_rights_itself_from_nearly_upside_down() {
+        let mut drone = armed();
+        *drone.body.orientation = Quaternion::new(160.0, 1.0, 0.0, 1.0).normalised();
+        let setpoint = PositionSetpoint::hover(Position::default(), 0.0);
+        let mut controller = GeometricController::default();
+
+        fly(&mut drone, 4000, |drone| {
+            controller.update(&setpoint, &drone.body, DT)
+        });
+
+        let up = drone.body.orientation.rotate(Vector3::new(0.0, 1.0, 0.0));
+        assert!(up.y > 0.9999);
+        assert!(drone.body.position.magnitude() < 0.05);
+    }
+}
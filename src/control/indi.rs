@@ -0,0 +1,154 @@
+use std::time::Duration;
+
+use crate::{
+    drone::Drone,
+    physics::{body::RigidBody, math::Matrix3, torque::Torque, util::vector::Vector3},
+};
+
+use super::{ControlCommand, Signal};
+
+/// # Overview
+/// Incremental nonlinear dynamic inversion, a rate controller that leans on
+/// measurements rather than on a model.
+///
+/// Inverting the full rotational dynamics to find the torque for a wanted
+/// angular acceleration needs everything acting on the body, gusts and
+/// payloads included, which we never know well. INDI sidesteps that. The
+/// rotors are already giving some torque `τ₀`, and the body is already
+/// turning with some angular acceleration `α₀`. Whatever else is going on
+/// is baked into that pair, so to get `α` instead we only have to change
+/// the torque by the difference:
+///
+/// `τ = τ₀ + I (α - α₀)`
+///
+/// The only model left is the inertia `I`, and even that only scales the
+/// step, a wrong `I` makes it slower or quicker to settle but not wrong.
+///
+/// # Measurements
+/// - `τ₀` from the rotor speeds, the motors' own feedback, through the
+///   [`Mixer`](crate::drone::Mixer). A saturated motor shows up here as
+///   the torque it really gave, so nothing winds up.
+/// - `α₀` by differencing the body rates, as a gyro would give them.
+///
+/// Differencing is noisy, so both go through the same low pass filter,
+/// keeping them in step with one another.
+#[derive(Debug, Clone)]
+pub struct Indi {
+    /// Per body axis gain (1/s) from rate error (rad/s) to the angular
+    /// acceleration asked for
+    pub rate_gain: Vector3,
+    /// Largest angular acceleration (rad/s²) asked for on each axis
+    pub max_angular_acceleration: Vector3,
+    /// Our best guess of the inertia (kg⋅m²) in the body frame
+    pub inertia: Matrix3,
+    /// Time constant (s) of the low pass filter on both measurements, `0`
+    /// turns it off
+    pub filter: f64,
+    previous_rates: Option<Vector3>,
+    angular_acceleration: Vector3,
+    torque: Vector3,
+}
+
+impl Indi {
+    pub fn new(inertia: Matrix3) -> Self {
+        // Tuned for the default drone like the other controllers
+        Self {
+            rate_gain: Vector3::new(8.0, 2.0, 8.0),
+            max_angular_acceleration: Vector3::new(2.0, 0.1, 2.0),
+            inertia,
+            filter: 0.03,
+            previous_rates: None,
+            angular_acceleration: Vector3::default(),
+            torque: Vector3::default(),
+        }
+    }
+
+    /// Uses `body`'s inertia as it is now.
+    pub fn for_body(body: &RigidBody) -> Self {
+        Self::new(body.inertia())
+    }
+
+    /// The filtered angular acceleration (rad/s², body frame) from the last
+    /// update.
+    pub fn angular_acceleration(&self) -> Vector3 {
+        self.angular_acceleration
+    }
+
+    /// Chases the body `rates` (rad/s) passing `thrust` (N) straight
+    /// through, e.g. from an [`AttitudeController::rate_setpoint`](super::AttitudeController::rate_setpoint).
+    pub fn update(
+        &mut self,
+        rates: Vector3,
+        thrust: f64,
+        drone: &Drone,
+        dt: Duration,
+    ) -> ControlCommand {
+        let dt = dt.as_secs_f64();
+        let omega = drone.body.angular_velocity.0;
+        let measured_torque = drone.mixer.forces(&drone.propellers).torque.0;
+        let alpha = match self.filter > 0.0 {
+            true => dt / (self.filter + dt),
+            false => 1.0,
+        };
+
+        // No history on the first step, so just take the torque as it is
+        match (self.previous_rates, dt > 0.0) {
+            (Some(previous), true) => {
+                let raw = (omega - previous).scalar_div(dt);
+                self.angular_acceleration =
+                    self.angular_acceleration + (raw - self.angular_acceleration).scalar_mul(alpha);
+                self.torque = self.torque + (measured_torque - self.torque).scalar_mul(alpha);
+            }
+            _ => self.torque = measured_torque,
+        }
+        self.previous_rates = Some(omega);
+
+        let limit = self.max_angular_acceleration;
+        let wanted = self
+            .rate_gain
+            .component_mul(rates - omega)
+            .clamp_between(-limit, limit);
+        ControlCommand {
+            thrust,
+            torque: Torque(self.torque + self.inertia * (wanted - self.angular_acceleration)),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.previous_rates = None;
+        self.angular_acceleration = Vector3::default();
+        self.torque = Vector3::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        control::harness::{armed, fly, DT},
+        physics::force::ForceVector,
+    };
+
+    #[test]
+    fn test_holds_rates_despite_disturbance_and_wrong_inertia() {
+        let mut drone = armed();
+        // Thinks the drone is twice as hard to turn as it is
+        let mut indi = Indi::new(drone.body.inertia().scalar_mul(2.0));
+        let rates = Vector3::default();
+        let thrust = drone.body.total_mass() * 9.81;
+
+        fly(&mut drone, 300, |drone| {
+            let command = indi.update(rates, thrust, drone, DT);
+            // Something heavy hanging off one corner, which the controller
+            // knows nothing about
+            let corner = drone.body.world_point(Vector3::new(5.0, 5.0, 5.0));
+            drone
+                .body
+                .apply_force_at(ForceVector(Vector3::new(0.0, -300.0, 0.0)), corner);
+            command
+        });
+
+        let error = drone.body.angular_velocity.0 - rates;
+        assert!(error.magnitude() < 0.005, "{error:?}");
+    }
+}
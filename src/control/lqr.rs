@@ -0,0 +1,350 @@
+//! # Overview
+//!
+//! The linear quadratic regulator, the *optimal* answer to "how hard should
+//! I push back on each error?" rather than the hand tuned one.
+//!
+//! # Linear model
+//! Close to hover the drone behaves like a linear system `ẋ = A x + B u`.
+//! The state `x` is 12 numbers, the errors in:
+//!
+//! | rows  | state                                  |
+//! |-------|----------------------------------------|
+//! | 0..3  | position (m)                           |
+//! | 3..6  | velocity (m/s)                         |
+//! | 6..9  | attitude, as a rotation vector (rad)   |
+//! | 9..12 | body rates (rad/s)                     |
+//!
+//! and the input `u` is 4, the thrust (N) on top of what holds the drone up
+//! and the body torque (N⋅m). Leaning by a small angle `φ` tips the hover
+//! thrust `m g` sideways, so the only coupling is tilt to horizontal
+//! acceleration: `v̇_x = -g φ_z` and `v̇_z = g φ_x`.
+//!
+//! # Cost
+//! LQR picks the feedback `u = -K x` that minimises
+//! `∫ xᵀQx + uᵀRu dt`, `Q` weighs up the errors and `R` the effort spent
+//! fixing them. The minimum comes from the solution `P` of the *Riccati
+//! equation*, continuous time (CARE) or discrete time (DARE) depending on
+//! the model. If in doubt use [`bryson`] to pick `Q` and `R`.
+
+use std::time::Duration;
+
+use crate::{
+    physics::{
+        body::RigidBody,
+        force::EARTH_GRAVITY_ACCELERATION,
+        math::{Matrix, Quaternion},
+        torque::Torque,
+        util::vector::Vector3,
+    },
+    EleaError, Result,
+};
+
+use super::{ControlCommand, PositionSetpoint};
+
+/// Number of states in the hover model.
+pub const STATES: usize = 12;
+/// Number of inputs in the hover model, thrust then torque.
+pub const INPUTS: usize = 4;
+
+const MAX_ITERATIONS: usize = 100_000;
+const TOLERANCE: f64 = 1e-10;
+
+/// `ẋ = A x + B u` in continuous time, or `x⁺ = A x + B u` once
+/// discretised.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinearModel {
+    pub a: Matrix,
+    pub b: Matrix,
+}
+
+impl LinearModel {
+    /// The body's dynamics linearised about hovering level, facing along
+    /// `x`, see the [module docs](self) for the states and inputs.
+    pub fn hover(body: &RigidBody) -> Result<Self> {
+        let inverse_inertia = body
+            .inertia()
+            .inverse()
+            .ok_or_else(|| EleaError::InvalidData("body has singular inertia".to_string()))?;
+
+        let mut a = Matrix::zeros(STATES, STATES);
+        for i in 0..3 {
+            a[(i, 3 + i)] = 1.0;
+            a[(6 + i, 9 + i)] = 1.0;
+        }
+        a[(3, 8)] = -EARTH_GRAVITY_ACCELERATION;
+        a[(5, 6)] = EARTH_GRAVITY_ACCELERATION;
+
+        let mut b = Matrix::zeros(STATES, INPUTS);
+        b[(4, 0)] = 1.0 / body.total_mass();
+        for i in 0..3 {
+            for j in 0..3 {
+                b[(9 + i, 1 + j)] = inverse_inertia.rows[i][j];
+            }
+        }
+        Ok(Self { a, b })
+    }
+
+    /// The discrete time model for holding each input for `dt`.
+    ///
+    /// Exact for a zero order hold: the exponential of
+    /// `[[A, B], [0, 0]] dt` has the discrete `A` and `B` as its top blocks.
+    pub fn discretise(&self, dt: Duration) -> Self {
+        let (n, m) = (self.a.rows(), self.b.columns());
+        let mut augmented = Matrix::zeros(n + m, n + m);
+        augmented.set_block(0, 0, &self.a);
+        augmented.set_block(0, n, &self.b);
+        let exponential = augmented.scalar_mul(dt.as_secs_f64()).exp();
+        Self {
+            a: exponential.block(0, 0, n, n),
+            b: exponential.block(0, n, n, m),
+        }
+    }
+}
+
+/// Bryson's rule, a diagonal weight of `1 / max²` for each entry, so every
+/// state (or input) costs the same when it reaches the most we'd put up
+/// with.
+pub fn bryson(max: &[f64]) -> Matrix {
+    Matrix::diagonal(&max.iter().map(|max| 1.0 / (max * max)).collect::<Vec<_>>())
+}
+
+/// Solves the continuous algebraic Riccati equation
+/// `AᵀP + PA - PBR⁻¹BᵀP + Q = 0` for the stabilising `P`.
+///
+/// # How
+/// The answer is hidden in the *Hamiltonian* `H = [[A, -BR⁻¹Bᵀ], [-Q, -Aᵀ]]`.
+/// Its stable half, the directions that decay, is spanned by `[I; P]`.
+/// The *matrix sign function* maps the stable half to `-1` and the rest to
+/// `+1`, and is found by iterating `Z ← (Z + Z⁻¹) / 2` from `H`, Newton's
+/// method for a square root of `I`. Then `(sign H + I) [I; P] = 0` is a
+/// linear system for `P`.
+pub fn solve_care(a: &Matrix, b: &Matrix, q: &Matrix, r: &Matrix) -> Result<Matrix> {
+    let n = a.rows();
+    let r_inverse = inverse(r, "R")?;
+    let mut z = Matrix::zeros(2 * n, 2 * n);
+    z.set_block(0, 0, a);
+    z.set_block(0, n, &-&(&(b * &r_inverse) * &b.transpose()));
+    z.set_block(n, 0, &-q);
+    z.set_block(n, n, &-&a.transpose());
+
+    let mut converged = false;
+    for _ in 0..MAX_ITERATIONS {
+        let z_inverse = inverse(&z, "the Hamiltonian")?;
+        // Scaling each step keeps it converging quickly from the start
+        let scale = (z.max_abs() / z_inverse.max_abs()).sqrt();
+        let next = (&z.scalar_mul(1.0 / scale) + &z_inverse.scalar_mul(scale)).scalar_mul(0.5);
+        let change = (&next - &z).max_abs();
+        z = next;
+        if change <= TOLERANCE * z.max_abs() {
+            converged = true;
+            break;
+        }
+    }
+    if !converged {
+        return Err(EleaError::InvalidData(
+            "Riccati sign iteration didn't converge".to_string(),
+        ));
+    }
+
+    // Least squares on [W₁₂; W₂₂ + I] P = -[W₁₁ + I; W₂₁]
+    let identity = Matrix::identity(n);
+    let mut lhs = Matrix::zeros(2 * n, n);
+    lhs.set_block(0, 0, &z.block(0, n, n, n));
+    lhs.set_block(n, 0, &(&z.block(n, n, n, n) + &identity));
+    let mut rhs = Matrix::zeros(2 * n, n);
+    rhs.set_block(0, 0, &-&(&z.block(0, 0, n, n) + &identity));
+    rhs.set_block(n, 0, &-&z.block(n, 0, n, n));
+    let normal = &lhs.transpose() * &lhs;
+    let p = normal
+        .solve(&(&lhs.transpose() * &rhs))
+        .ok_or_else(|| EleaError::InvalidData("system isn't stabilisable".to_string()))?;
+    Ok(p.symmetrised())
+}
+
+/// Solves the discrete algebraic Riccati equation
+/// `P = Q + AᵀPA - AᵀPB (R + BᵀPB)⁻¹ BᵀPA` for the stabilising `P`.
+///
+/// Plain value iteration from `P = Q`, each pass is one more step of
+/// looking ahead, until looking further makes no difference.
+pub fn solve_dare(a: &Matrix, b: &Matrix, q: &Matrix, r: &Matrix) -> Result<Matrix> {
+    let a_transpose = a.transpose();
+    let b_transpose = b.transpose();
+    let mut p = q.clone();
+    for _ in 0..MAX_ITERATIONS {
+        let pa = &p * a;
+        let pb = &p * b;
+        let gain = inverse(&(r + &(&b_transpose * &pb)), "R + BᵀPB")?;
+        let next = &(q + &(&a_transpose * &pa))
+            - &(&(&(&a_transpose * &pb) * &gain) * &(&b_transpose * &pa));
+        let next = next.symmetrised();
+        let change = (&next - &p).max_abs();
+        p = next;
+        if change <= TOLERANCE * p.max_abs().max(1.0) {
+            return Ok(p);
+        }
+    }
+    Err(EleaError::InvalidData(
+        "Riccati iteration didn't converge".to_string(),
+    ))
+}
+
+/// `Q` and `R` for hovering `body`, by Bryson's rule.
+///
+/// We'd put up with a metre out, a metre a second, leaning about 10° and
+/// turning half a radian a second. The rotors can give about half the
+/// weight either side of hover, and twist the body round `y` much more
+/// slowly than they can tip it.
+pub fn hover_weights(body: &RigidBody) -> (Matrix, Matrix) {
+    let q = bryson(&[1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 0.2, 0.2, 0.2, 0.5, 0.5, 0.5]);
+    let weight = body.total_mass() * EARTH_GRAVITY_ACCELERATION;
+    let tip = body.inertia().rows[0][0];
+    let r = bryson(&[0.5 * weight, tip, 0.1 * tip, tip]);
+    (q, r)
+}
+
+fn inverse(matrix: &Matrix, name: &str) -> Result<Matrix> {
+    matrix
+        .inverse()
+        .ok_or_else(|| EleaError::InvalidData(format!("{name} is singular")))
+}
+
+/// # Overview
+/// Full state feedback `u = -K x` on the hover model, flying straight to a
+/// [`PositionSetpoint`] with no cascade of loops in between. Everything the
+/// controller knows is in the gain `K`, so comparing it with a hand tuned
+/// PID is a matter of comparing gains.
+///
+/// The model is only good near level, so large manoeuvres are best left to
+/// the [`GeometricController`](super::GeometricController).
+///
+/// # Yaw
+/// The model is linearised facing along `x`. Facing any other way is the
+/// same problem turned about `y`, so we turn the errors into the frame of
+/// the setpoint's heading before multiplying by `K`.
+#[derive(Debug, Clone)]
+pub struct Lqr {
+    /// `K`, 4 inputs by 12 states
+    pub gain: Matrix,
+}
+
+impl Lqr {
+    /// `K = R⁻¹BᵀP` from the continuous Riccati equation.
+    pub fn continuous(model: &LinearModel, q: &Matrix, r: &Matrix) -> Result<Self> {
+        let p = solve_care(&model.a, &model.b, q, r)?;
+        Ok(Self {
+            gain: &(&inverse(r, "R")? * &model.b.transpose()) * &p,
+        })
+    }
+
+    /// `K = (R + BᵀPB)⁻¹BᵀPA` from the discrete Riccati equation, for a
+    /// model already [discretised](LinearModel::discretise) at the rate the
+    /// controller will run.
+    pub fn discrete(model: &LinearModel, q: &Matrix, r: &Matrix) -> Result<Self> {
+        let p = solve_dare(&model.a, &model.b, q, r)?;
+        let b_transpose = model.b.transpose();
+        let effort = inverse(&(r + &(&(&b_transpose * &p) * &model.b)), "R + BᵀPB")?;
+        Ok(Self {
+            gain: &(&(&effort * &b_transpose) * &p) * &model.a,
+        })
+    }
+
+    /// The continuous LQR for hovering `body`, weighted by [`hover_weights`].
+    pub fn hover(body: &RigidBody) -> Result<Self> {
+        let (q, r) = hover_weights(body);
+        Self::continuous(&LinearModel::hover(body)?, &q, &r)
+    }
+
+    /// The 12 state error vector, in the frame of the setpoint's heading.
+    pub fn state(setpoint: &PositionSetpoint, body: &RigidBody) -> Matrix {
+        let heading = Quaternion::from_rotation_vector(Vector3::new(0.0, setpoint.yaw, 0.0));
+        let unturn = heading.conjugate();
+        let position = unturn.rotate(body.position.0 - setpoint.position.0);
+        let velocity = unturn.rotate(body.linear_velocity.0 - setpoint.velocity.0);
+        let attitude = (unturn * *body.orientation).to_rotation_vector();
+        let rates = body.angular_velocity.0;
+        Matrix::column_vector(
+            &[position, velocity, attitude, rates]
+                .iter()
+                .flat_map(|v| [v.x, v.y, v.z])
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    /// The command that drives `body` towards `setpoint`. Only the vertical
+    /// part of the setpoint's acceleration is fed forward, as extra thrust.
+    pub fn update(&self, setpoint: &PositionSetpoint, body: &RigidBody) -> ControlCommand {
+        let u = -&(&self.gain * &Self::state(setpoint, body));
+        let hover = body.total_mass() * (EARTH_GRAVITY_ACCELERATION + setpoint.acceleration.y);
+        ControlCommand {
+            thrust: (hover + u[(0, 0)]).max(0.0),
+            torque: Torque(Vector3::new(u[(1, 0)], u[(2, 0)], u[(3, 0)])),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        control::harness::{armed, fly},
+        physics::state::{
+            angular_velocity::AngularVelocity, linear_velocity::LinearVelocity, position::Position,
+        },
+    };
+
+    #[test]
+    fn test_riccati_double_integrator() {
+        // Known answer, K = [1, √3]
+        let model = LinearModel {
+            a: Matrix::from_rows(&[&[0.0, 1.0], &[0.0, 0.0]]),
+            b: Matrix::column_vector(&[0.0, 1.0]),
+        };
+        let (q, r) = (Matrix::identity(2), Matrix::identity(1));
+        let lqr = Lqr::continuous(&model, &q, &r).unwrap();
+        let expected = Matrix::from_rows(&[&[1.0, 3f64.sqrt()]]);
+        assert!((&lqr.gain - &expected).max_abs() < 1e-8);
+
+        // A short enough step should come out about the same, with each
+        // step's cost weighted by how long it lasts
+        let dt = Duration::from_millis(1);
+        let (q, r) = (q.scalar_mul(0.001), r.scalar_mul(0.001));
+        let discrete = Lqr::discrete(&model.discretise(dt), &q, &r).unwrap();
+        assert!((&discrete.gain - &expected).max_abs() < 1e-2);
+    }
+
+    #[test]
+    fn test_state_turns_with_the_heading() {
+        let mut body = RigidBody::new(0.1, 0.3, 0.3);
+        body.position = Position(Vector3::new(1.0, 2.0, -3.0));
+        body.linear_velocity = LinearVelocity(Vector3::new(0.5, 0.0, 0.2));
+        *body.orientation = Quaternion::from_rotation_vector(Vector3::new(0.1, 0.0, -0.05));
+        body.angular_velocity = AngularVelocity(Vector3::new(0.0, 0.3, 0.1));
+        let setpoint = PositionSetpoint::hover(Position(Vector3::new(2.0, 2.0, 1.0)), 0.0);
+        let facing_x = Lqr::state(&setpoint, &body);
+
+        // The same again turned about `y`, the errors shouldn't change
+        let yaw = 2.5;
+        let turn = Quaternion::from_rotation_vector(Vector3::new(0.0, yaw, 0.0));
+        body.position = Position(turn.rotate(body.position.0));
+        body.linear_velocity = LinearVelocity(turn.rotate(body.linear_velocity.0));
+        *body.orientation = turn * *body.orientation;
+        let turned = PositionSetpoint::hover(Position(turn.rotate(setpoint.position.0)), yaw);
+
+        assert!((&Lqr::state(&turned, &body) - &facing_x).max_abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_flies_facing_away_from_x() {
+        let mut drone = armed();
+        let yaw = 2.5;
+        *drone.body.orientation = Quaternion::from_rotation_vector(Vector3::new(0.0, yaw, 0.0));
+        let lqr = Lqr::hover(&drone.body).unwrap();
+        let setpoint = PositionSetpoint::hover(Position(Vector3::new(5.0, 3.0, -4.0)), yaw);
+
+        fly(&mut drone, 3000, |drone| lqr.update(&setpoint, &drone.body));
+
+        assert!((drone.body.position - setpoint.position).magnitude() < 0.05);
+        let heading = drone.body.orientation.rotate(Vector3::new(1.0, 0.0, 0.0));
+        assert!((heading.z.atan2(heading.x) + yaw).abs() < 0.01);
+    }
+}
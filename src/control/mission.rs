@@ -0,0 +1,457 @@
+use std::{collections::VecDeque, time::Duration};
+
+use crate::{
+    physics::{
+        body::RigidBody,
+        math::Quaternion,
+        state::{linear_velocity::LinearVelocity, position::Position},
+        util::vector::Vector3,
+    },
+    EleaError, Result,
+};
+
+use super::{AttitudeSetpoint, PositionController, PositionSetpoint};
+
+/// One step of a scripted mission.
+///
+/// Altitudes are relative to the launch point, the drone's position the
+/// first time the mission is updated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MissionItem {
+    /// Climb straight up to `altitude` (m) above launch
+    Takeoff { altitude: f64 },
+    /// Fly to `position` facing `yaw` (rad), done once within
+    /// `acceptance_radius` (m) of it
+    Waypoint {
+        position: Position,
+        yaw: f64,
+        acceptance_radius: f64,
+    },
+    /// Hover where we are for `time`
+    Loiter { time: Duration },
+    /// Cruise speed (m/s) for the waypoints from here on
+    ChangeSpeed { speed: f64 },
+    /// Descend straight down to the launch altitude and stop the motors
+    Land,
+    /// Climb to at least the return altitude, fly back over the launch
+    /// point and land there
+    ReturnToLaunch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissionEvent {
+    /// Index of the item just started
+    Started(usize),
+    /// Index of the item just finished
+    Reached(usize),
+    Landed,
+    /// Every item is done
+    Finished,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MissionStep {
+    /// For the [`AttitudeController`](super::AttitudeController) to chase
+    pub setpoint: AttitudeSetpoint,
+    pub events: Vec<MissionEvent>,
+}
+
+/// The pieces an item breaks down into.
+#[derive(Debug, Clone, Copy)]
+enum Leg {
+    Goto {
+        target: Position,
+        speed: f64,
+        acceptance_radius: f64,
+        landing: bool,
+        /// How long the carrot has sat at the target
+        waited: Duration,
+    },
+    Hold {
+        remaining: Duration,
+    },
+}
+
+/// # Overview
+/// Works through a list of [`MissionItem`]s one after another, driving a
+/// [`PositionController`] along the way.
+///
+/// Rather than handing the controller the next waypoint outright, which
+/// would have it fly there as fast as it's allowed, we slide a *carrot*
+/// setpoint along the straight line towards it at the cruise speed and let
+/// the drone follow that. Changing speed mid mission is then just changing
+/// how quickly the carrot moves.
+///
+/// A waypoint counts as reached once the carrot is there and the drone is
+/// within its acceptance radius.
+///
+/// # Landing
+/// The mission doesn't see the ground itself, so landing means coming down
+/// towards the altitude we took off from. It's over as soon as the drone
+/// reaches it, or the caller reports touching down with
+/// [`MissionExecutor::touch_down`], say on a
+/// [`GearEvent::Touchdown`](crate::drone::landing_gear::GearEvent::Touchdown)
+/// because the ground was higher than the launch point. Should neither
+/// happen, say it's settled a little above, we call it landed anyway once
+/// the carrot's waited `land_timeout` at the bottom. Then the motors are
+/// stopped.
+#[derive(Debug, Clone)]
+pub struct MissionExecutor {
+    pub position_controller: PositionController,
+    /// Speed (m/s) between waypoints until a [`MissionItem::ChangeSpeed`]
+    pub cruise_speed: f64,
+    /// Speed (m/s) taking off and landing
+    pub climb_speed: f64,
+    /// Lowest altitude (m, above launch) to fly home at
+    pub return_altitude: f64,
+    /// Longest to wait at the bottom of a landing for the drone to get there
+    pub land_timeout: Duration,
+    items: Vec<MissionItem>,
+    /// Index of the item being flown, `items.len()` once done
+    current: usize,
+    legs: VecDeque<Leg>,
+    started: bool,
+    launch: Option<Position>,
+    carrot: Position,
+    carrot_velocity: Vector3,
+    yaw: f64,
+    landed: bool,
+    touched_down: bool,
+}
+
+impl MissionExecutor {
+    pub fn new(items: Vec<MissionItem>) -> Result<Self> {
+        for item in &items {
+            let valid = match *item {
+                MissionItem::Takeoff { altitude } => altitude > 0.0,
+                MissionItem::Waypoint {
+                    acceptance_radius, ..
+                } => acceptance_radius > 0.0,
+                MissionItem::ChangeSpeed { speed } => speed > 0.0,
+                _ => true,
+            };
+            if !valid {
+                return Err(EleaError::InvalidData(format!(
+                    "mission item {item:?} needs a positive value"
+                )));
+            }
+        }
+        Ok(Self {
+            position_controller: PositionController::default(),
+            cruise_speed: 3.0,
+            climb_speed: 1.5,
+            return_altitude: 20.0,
+            land_timeout: Duration::from_secs(5),
+            items,
+            current: 0,
+            legs: VecDeque::new(),
+            started: false,
+            launch: None,
+            carrot: Position::default(),
+            carrot_velocity: Vector3::default(),
+            yaw: 0.0,
+            landed: false,
+            touched_down: false,
+        })
+    }
+
+    pub fn items(&self) -> &[MissionItem] {
+        &self.items
+    }
+
+    /// Index of the item being flown, `None` once the mission is over.
+    pub fn current_item(&self) -> Option<usize> {
+        (self.current < self.items.len()).then_some(self.current)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.current >= self.items.len()
+    }
+
+    pub fn launch(&self) -> Option<Position> {
+        self.launch
+    }
+
+    /// Tells the mission the drone has touched the ground, finishing the
+    /// landing on the next update if it's on one. Otherwise it's ignored.
+    pub fn touch_down(&mut self) {
+        self.touched_down = true;
+    }
+
+    /// Moves the mission on by `dt` and works out where the drone should
+    /// be pointing to stay on it.
+    ///
+    /// Errors if `cruise_speed` or `climb_speed` aren't above zero, the
+    /// carrot would never get anywhere.
+    pub fn update(&mut self, body: &RigidBody, dt: Duration) -> Result<MissionStep> {
+        // Written so NaN fails too
+        if !(self.cruise_speed > 0.0 && self.climb_speed > 0.0) {
+            return Err(EleaError::InvalidData(
+                "mission speeds must be above zero".to_string(),
+            ));
+        }
+        let touched_down = std::mem::take(&mut self.touched_down);
+        let launch = match self.launch {
+            Some(launch) => launch,
+            None => {
+                self.carrot = body.position;
+                self.yaw = heading(body);
+                *self.launch.insert(body.position)
+            }
+        };
+        let mut events = Vec::new();
+
+        // Items that finish at once, like changing speed, shouldn't cost
+        // us a step each, so keep going until something needs flying
+        while let Some(&item) = self.items.get(self.current) {
+            if !self.started {
+                self.started = true;
+                events.push(MissionEvent::Started(self.current));
+                self.plan(item, launch);
+            }
+            if let Some(leg) = self.legs.front_mut() {
+                let done = match leg {
+                    Leg::Goto {
+                        target,
+                        speed,
+                        acceptance_radius,
+                        landing,
+                        waited,
+                    } => {
+                        let step = *speed * dt.as_secs_f64();
+                        let remaining = *target - self.carrot;
+                        let distance = remaining.magnitude();
+                        if distance <= step {
+                            self.carrot = *target;
+                            self.carrot_velocity = Vector3::default();
+                        } else {
+                            let direction = remaining.0.scalar_div(distance);
+                            self.carrot += Position(direction.scalar_mul(step));
+                            self.carrot_velocity = direction.scalar_mul(*speed);
+                        }
+                        if distance <= step {
+                            *waited += dt;
+                        }
+                        let within = (body.position - *target).magnitude() <= *acceptance_radius;
+                        let arrived = match *landing {
+                            true => {
+                                touched_down
+                                    || (distance <= step
+                                        && (within || *waited >= self.land_timeout))
+                            }
+                            false => distance <= step && within,
+                        };
+                        if arrived && *landing {
+                            self.landed = true;
+                            events.push(MissionEvent::Landed);
+                        }
+                        arrived
+                    }
+                    Leg::Hold { remaining } => {
+                        *remaining = remaining.saturating_sub(dt);
+                        remaining.is_zero()
+                    }
+                };
+                if done {
+                    self.legs.pop_front();
+                }
+                if !self.legs.is_empty() {
+                    break;
+                }
+            }
+            events.push(MissionEvent::Reached(self.current));
+            self.current += 1;
+            self.started = false;
+            if self.is_finished() {
+                events.push(MissionEvent::Finished);
+            }
+        }
+
+        let setpoint = match self.landed {
+            true => AttitudeSetpoint {
+                orientation: Quaternion::from_rotation_vector(Vector3::new(0.0, self.yaw, 0.0)),
+                thrust: 0.0,
+            },
+            false => {
+                let target = PositionSetpoint {
+                    position: self.carrot,
+                    velocity: LinearVelocity(self.carrot_velocity),
+                    acceleration: Vector3::default(),
+                    yaw: self.yaw,
+                };
+                self.position_controller.update(&target, body, dt)
+            }
+        };
+        Ok(MissionStep { setpoint, events })
+    }
+
+    /// Breaks `item` down into legs, starting from wherever the carrot is.
+    fn plan(&mut self, item: MissionItem, launch: Position) {
+        let goto = |target, speed, acceptance_radius| Leg::Goto {
+            target,
+            speed,
+            acceptance_radius,
+            landing: false,
+            waited: Duration::ZERO,
+        };
+        let land = |from: Position, speed| Leg::Goto {
+            target: Position(Vector3::new(from.x, launch.y, from.z)),
+            speed,
+            acceptance_radius: 0.2,
+            landing: true,
+            waited: Duration::ZERO,
+        };
+        match item {
+            MissionItem::Takeoff { altitude } => {
+                self.landed = false;
+                let above = Vector3::new(self.carrot.x, launch.y + altitude, self.carrot.z);
+                self.legs
+                    .push_back(goto(Position(above), self.climb_speed, 0.5));
+            }
+            MissionItem::Waypoint {
+                position,
+                yaw,
+                acceptance_radius,
+            } => {
+                self.yaw = yaw;
+                self.legs
+                    .push_back(goto(position, self.cruise_speed, acceptance_radius));
+            }
+            MissionItem::Loiter { time } => self.legs.push_back(Leg::Hold { remaining: time }),
+            MissionItem::ChangeSpeed { speed } => self.cruise_speed = speed,
+            MissionItem::Land => self.legs.push_back(land(self.carrot, self.climb_speed)),
+            MissionItem::ReturnToLaunch => {
+                let altitude = self.carrot.y.max(launch.y + self.return_altitude);
+                let up = Position(Vector3::new(self.carrot.x, altitude, self.carrot.z));
+                let over = Position(Vector3::new(launch.x, altitude, launch.z));
+                self.legs.push_back(goto(up, self.climb_speed, 1.0));
+                self.legs.push_back(goto(over, self.cruise_speed, 1.0));
+                self.legs.push_back(land(over, self.climb_speed));
+            }
+        }
+    }
+}
+
+/// The yaw (rad) `body` is facing, as used by [`PositionSetpoint`].
+fn heading(body: &RigidBody) -> f64 {
+    let forward = body.orientation.rotate(Vector3::new(1.0, 0.0, 0.0));
+    -forward.z.atan2(forward.x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::{harness::armed, AttitudeController};
+
+    #[test]
+    fn test_flies_mission_and_returns_home() {
+        let mut drone = armed();
+        let mut mission = MissionExecutor::new(vec![
+            MissionItem::Takeoff { altitude: 10.0 },
+            MissionItem::ChangeSpeed { speed: 4.0 },
+            MissionItem::Waypoint {
+                position: Position(Vector3::new(20.0, 10.0, 0.0)),
+                yaw: 0.5,
+                acceptance_radius: 1.0,
+            },
+            MissionItem::Loiter {
+                time: Duration::from_secs(2),
+            },
+            MissionItem::ReturnToLaunch,
+        ])
+        .unwrap();
+        let mut attitude = AttitudeController::default();
+        let dt = Duration::from_millis(16);
+
+        let mut events = Vec::new();
+        let mut landed_at = None;
+        for _ in 0..10_000 {
+            let step = mission.update(&drone.body, dt).unwrap();
+            if step.events.contains(&MissionEvent::Landed) {
+                landed_at = Some(drone.body.position);
+            }
+            events.extend(step.events);
+            if mission.is_finished() {
+                break;
+            }
+            let command = attitude.update(&step.setpoint, &drone.body, dt);
+            drone.apply_command(&command);
+            drone.update_rotor_forces();
+            drone.body.step(dt, LinearVelocity::default()).unwrap();
+        }
+
+        use MissionEvent::*;
+        assert_eq!(
+            events,
+            [
+                Started(0),
+                Reached(0),
+                Started(1),
+                Reached(1),
+                Started(2),
+                Reached(2),
+                Started(3),
+                Reached(3),
+                Started(4),
+                Landed,
+                Reached(4),
+                Finished
+            ]
+        );
+        let home = landed_at.unwrap() - mission.launch().unwrap();
+        assert!(home.magnitude() < 1.0, "{home:?}");
+    }
+
+    /// Sits `body` a metre above where the mission launched from, partway
+    /// through its landing, and counts the updates until it's landed.
+    fn land_from_above(mission: &mut MissionExecutor, touch_down_after: Option<usize>) -> usize {
+        let mut body = RigidBody::new(0.1, 0.3, 0.3);
+        let dt = Duration::from_millis(16);
+        mission.update(&body, dt).unwrap();
+        body.position.y = 1.0;
+        for count in 1..1000 {
+            if touch_down_after == Some(count) {
+                mission.touch_down();
+            }
+            let step = mission.update(&body, dt).unwrap();
+            if step.events.contains(&MissionEvent::Landed) {
+                assert_eq!(step.setpoint.thrust, 0.0);
+                return count;
+            }
+        }
+        panic!("never landed");
+    }
+
+    #[test]
+    fn test_landing_ends_on_touch_down_or_timeout() {
+        // Loitering first so the drone can be moved up off the launch point
+        let items = vec![
+            MissionItem::Loiter {
+                time: Duration::from_millis(160),
+            },
+            MissionItem::Land,
+        ];
+
+        // Touching down finishes it straight away
+        let mut mission = MissionExecutor::new(items.clone()).unwrap();
+        assert_eq!(land_from_above(&mut mission, Some(10)), 10);
+
+        // Otherwise it gives up waiting once the carrot's been at the
+        // bottom for the timeout
+        let mut mission = MissionExecutor::new(items).unwrap();
+        mission.land_timeout = Duration::from_secs(2);
+        let steps = land_from_above(&mut mission, None);
+        assert!((130..=135).contains(&steps), "{steps}");
+    }
+
+    #[test]
+    fn test_rejects_stopped_speeds() {
+        let body = RigidBody::new(0.1, 0.3, 0.3);
+        let mut mission = MissionExecutor::new(vec![MissionItem::Land]).unwrap();
+        mission.cruise_speed = 0.0;
+        assert!(mission.update(&body, Duration::from_millis(16)).is_err());
+        mission.cruise_speed = 3.0;
+        mission.climb_speed = f64::NAN;
+        assert!(mission.update(&body, Duration::from_millis(16)).is_err());
+    }
+}
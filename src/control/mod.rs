@@ -0,0 +1,101 @@
+//! # Overview
+//!
+//! Flight control, working out what to ask of the drone's motors to get it
+//! where we want it to be.
+//!
+//! The cascaded attitude and position controllers are built from the same
+//! reusable [`Pid`] loop, which works on a single value or on all three
+//! axes of a `Vector3` at once.
+//!
+//! Whatever the controller, what comes out the end is a [`ControlCommand`]
+//! for the drone's [`Mixer`](crate::drone::Mixer) to share out between the
+//! rotors.
+pub mod attitude;
+pub mod autotune;
+pub mod geometric;
+pub mod indi;
+pub mod lqr;
+pub mod mission;
+pub mod mpc;
+pub mod pid;
+pub mod position;
+pub mod qp;
+pub mod trajectory;
+
+pub use attitude::{AttitudeController, AttitudeSetpoint};
+pub use autotune::{
+    optimise, Plant, RelayExperiment, RelayResult, StepResponse, StepTest, TuningCost, TuningRule,
+};
+pub use geometric::GeometricController;
+pub use indi::Indi;
+pub use lqr::{LinearModel, Lqr};
+pub use mission::{MissionEvent, MissionExecutor, MissionItem, MissionStep};
+pub use mpc::Mpc;
+pub use pid::{AntiWindup, DerivativeOn, Pid, PidGains, PidTerms, Signal};
+pub use position::{AltitudeHold, PositionController, PositionSetpoint};
+pub use qp::{QpSolution, QuadraticProgram};
+pub use trajectory::{Smoothness, Trajectory, TrajectoryLimits, TrajectoryPoint, Waypoint};
+
+use crate::physics::torque::Torque;
+
+/// What the flight controller asks of the rotors.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ControlCommand {
+    /// Collective thrust (N) along the body's `y` axis
+    pub thrust: f64,
+    /// Torque about the center of mass, in the body frame
+    pub torque: Torque,
+}
+
+/// The closed loop the controller tests fly, one place for all of them.
+#[cfg(test)]
+pub(crate) mod harness {
+    use std::time::Duration;
+
+    use crate::{
+        drone::Drone,
+        physics::{body::RigidBody, state::linear_velocity::LinearVelocity},
+    };
+
+    use super::{AttitudeController, AttitudeSetpoint, ControlCommand};
+
+    /// How long each step lasts, the simulator's default.
+    pub const DT: Duration = Duration::from_millis(16);
+
+    /// A default drone, armed so its motors will spin. Arm before tipping
+    /// it over, the pre-flight checks won't allow it after.
+    pub fn armed() -> Drone {
+        let mut drone = Drone::default();
+        drone.arm().unwrap();
+        drone
+    }
+
+    /// Flies `drone` in still air for `steps`, asking `controller` for a
+    /// command at the start of each.
+    pub fn fly(
+        drone: &mut Drone,
+        steps: usize,
+        mut controller: impl FnMut(&mut Drone) -> ControlCommand,
+    ) {
+        for _ in 0..steps {
+            let command = controller(drone);
+            drone.apply_command(&command);
+            drone.update_rotor_forces();
+            drone.body.step(DT, LinearVelocity::default()).unwrap();
+        }
+    }
+
+    /// [`fly`] for the outer loops, chasing whichever attitude `setpoint`
+    /// asks for with a default [`AttitudeController`].
+    pub fn fly_attitude(
+        drone: &mut Drone,
+        steps: usize,
+        mut setpoint: impl FnMut(&RigidBody) -> AttitudeSetpoint,
+    ) {
+        let mut attitude = AttitudeController::default();
+        fly(drone, steps, |drone| {
+            let target = setpoint(&drone.body);
+            attitude.update(&target, &drone.body, DT)
+        });
+    }
+}
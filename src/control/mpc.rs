@@ -0,0 +1,289 @@
+//! # Overview
+//!
+//! Model predictive control. Every step we look a short way ahead with the
+//! linear hover model, work out the whole sequence of inputs that tracks
+//! the reference best over that horizon, apply only the first of them, and
+//! do it all again next step from wherever we actually ended up.
+//!
+//! What that buys over [LQR](super::Lqr) is constraints. The plan has to
+//! keep every rotor between stopped and flat out, and never lean further
+//! than `max_tilt`, so it slows down *before* hitting a limit rather than
+//! saturating and hoping for the best. It also sees the reference coming,
+//! so it starts turning for a corner ahead of time, handy for threading a
+//! trajectory between obstacles.
+//!
+//! # The problem
+//! With the states over the horizon written in terms of the start `x₀` and
+//! the inputs `U`, `X = Φ x₀ + Γ U`, the tracking cost is quadratic in `U`
+//! and the limits are linear in it, which is a [quadratic program](super::qp)
+//! to solve each step. The last state is weighted by the discrete Riccati
+//! solution, the LQR's cost of getting home from there, standing in for
+//! everything after the horizon.
+//!
+//! See the [LQR docs](super::lqr) for the states and inputs.
+
+use std::time::Duration;
+
+use crate::{
+    drone::Drone,
+    physics::{
+        body::RigidBody,
+        force::EARTH_GRAVITY_ACCELERATION,
+        math::{Matrix, Quaternion},
+        state::position::Position,
+        torque::Torque,
+        util::vector::Vector3,
+    },
+    EleaError, Result,
+};
+
+use super::{
+    lqr::{hover_weights, solve_dare, INPUTS, STATES},
+    qp::QuadraticProgram,
+    ControlCommand, LinearModel, Lqr, PositionSetpoint,
+};
+
+#[derive(Debug, Clone)]
+pub struct Mpc {
+    /// Furthest (rad) the plan may lean about the horizontal axes, a
+    /// negative one leaves nothing to plan in and [Mpc::update] errors
+    pub max_tilt: f64,
+    horizon: usize,
+    step: Duration,
+    /// `Φ`, each step's state from the start state
+    free: Matrix,
+    /// `Γ`, each step's state from the (scaled) inputs
+    forced: Matrix,
+    /// `ΓᵀQ̄`, with `Q̄` the block diagonal state weights over the horizon
+    /// and the Riccati solution at the end
+    weighted_forced: Matrix,
+    /// Inputs are solved for as fractions of these, so they all come out
+    /// about the same size
+    input_scale: Vec<f64>,
+    allocation: [[f64; 4]; 4],
+    max_rotor_thrust: f64,
+    hover_thrust: f64,
+    qp: QuadraticProgram,
+    predicted: Vec<Position>,
+}
+
+impl Mpc {
+    /// Plans `horizon` steps of `step` ahead, `step` is also how long each
+    /// input is held for.
+    pub fn new(drone: &Drone, step: Duration, horizon: usize) -> Result<Self> {
+        if horizon == 0 || step.is_zero() {
+            return Err(EleaError::InvalidData(
+                "MPC needs a horizon to look over".to_string(),
+            ));
+        }
+        let body = &drone.body;
+        let model = LinearModel::hover(body)?.discretise(step);
+        let (q, r) = hover_weights(body);
+        // The weights are per second, each step only lasts `step`
+        let (q, r) = (
+            q.scalar_mul(step.as_secs_f64()),
+            r.scalar_mul(step.as_secs_f64()),
+        );
+        let terminal = solve_dare(&model.a, &model.b, &q, &r)?;
+        let input_scale: Vec<f64> = (0..INPUTS).map(|i| 1.0 / r[(i, i)].sqrt()).collect();
+        let b = &model.b * &Matrix::diagonal(&input_scale);
+
+        let (n, m) = (STATES, INPUTS);
+        let mut free = Matrix::zeros(n * horizon, n);
+        let mut forced = Matrix::zeros(n * horizon, m * horizon);
+        let mut weights = Matrix::zeros(n * horizon, n * horizon);
+        let mut power = Matrix::identity(n);
+        // A^k B, filled in down each diagonal of Γ
+        let mut powers_b = Vec::with_capacity(horizon);
+        for k in 0..horizon {
+            powers_b.push(&power * &b);
+            power = &power * &model.a;
+            free.set_block(n * k, 0, &power);
+            for j in 0..=k {
+                forced.set_block(n * k, m * j, &powers_b[k - j]);
+            }
+            let weight = match k + 1 == horizon {
+                true => &terminal,
+                false => &q,
+            };
+            weights.set_block(n * k, n * k, weight);
+        }
+
+        // Scaled by `1 / √R` the input cost is just the identity
+        let weighted_forced = &forced.transpose() * &weights;
+        let h = &(&weighted_forced * &forced) + &Matrix::identity(m * horizon);
+
+        let mixer = &drone.mixer;
+        let allocation = mixer.allocation();
+        let mut g = Matrix::zeros(6 * horizon, m * horizon);
+        for k in 0..horizon {
+            for (rotor, row) in allocation.iter().enumerate() {
+                for (input, coefficient) in row.iter().enumerate() {
+                    g[(4 * k + rotor, m * k + input)] = coefficient * input_scale[input];
+                }
+            }
+            for (i, state) in [6, 8].into_iter().enumerate() {
+                for column in 0..m * horizon {
+                    g[(4 * horizon + 2 * k + i, column)] = forced[(n * k + state, column)];
+                }
+            }
+        }
+
+        Ok(Self {
+            max_tilt: 30f64.to_radians(),
+            horizon,
+            step,
+            free,
+            forced,
+            weighted_forced,
+            input_scale,
+            allocation,
+            max_rotor_thrust: mixer.max_rotor_thrust(),
+            hover_thrust: body.total_mass() * EARTH_GRAVITY_ACCELERATION,
+            qp: QuadraticProgram::new(h, g)?,
+            predicted: Vec::new(),
+        })
+    }
+
+    pub fn horizon(&self) -> usize {
+        self.horizon
+    }
+
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    /// Where the last plan expects the drone to be at each step ahead.
+    pub fn predicted_positions(&self) -> &[Position] {
+        &self.predicted
+    }
+
+    /// Plans from `body` to follow `reference`, one setpoint for each step
+    /// from now, and returns the first command of the plan. A reference
+    /// shorter than the horizon holds its last setpoint to the end, so a
+    /// single setpoint means fly there and hover.
+    pub fn update(
+        &mut self,
+        reference: &[PositionSetpoint],
+        body: &RigidBody,
+    ) -> Result<ControlCommand> {
+        let start = reference
+            .first()
+            .ok_or_else(|| EleaError::InvalidData("MPC needs a reference".to_string()))?;
+        let heading = Quaternion::from_rotation_vector(Vector3::new(0.0, start.yaw, 0.0));
+        let unturn = heading.conjugate();
+        let x0 = Lqr::state(&PositionSetpoint::hover(start.position, start.yaw), body);
+
+        let (n, m) = (STATES, INPUTS);
+        let mut target = Matrix::zeros(n * self.horizon, 1);
+        for k in 0..self.horizon {
+            let setpoint = reference[(k + 1).min(reference.len() - 1)];
+            let position = unturn.rotate(setpoint.position.0 - start.position.0);
+            let velocity = unturn.rotate(setpoint.velocity.0);
+            let values = [
+                position,
+                velocity,
+                Vector3::new(0.0, setpoint.yaw - start.yaw, 0.0),
+            ];
+            for (i, value) in values.iter().flat_map(|v| [v.x, v.y, v.z]).enumerate() {
+                target[(n * k + i, 0)] = value;
+            }
+        }
+        let free = &self.free * &x0;
+        let drift = &free - &target;
+        let f = &self.weighted_forced * &drift;
+
+        // Rotor limits around the thrust each already gives at hover, then
+        // the tilt limits around where the drone would drift to anyway
+        let mut lower = Vec::with_capacity(6 * self.horizon);
+        let mut upper = Vec::with_capacity(6 * self.horizon);
+        for _ in 0..self.horizon {
+            for row in &self.allocation {
+                let hover = row[0] * self.hover_thrust;
+                lower.push(-hover);
+                upper.push(self.max_rotor_thrust - hover);
+            }
+        }
+        for k in 0..self.horizon {
+            for state in [6, 8] {
+                lower.push(-self.max_tilt - free[(n * k + state, 0)]);
+                upper.push(self.max_tilt - free[(n * k + state, 0)]);
+            }
+        }
+
+        let plan = self.qp.solve(&f, &lower, &upper)?.x;
+        let states = &free + &(&self.forced * &plan);
+        self.predicted = (0..self.horizon)
+            .map(|k| {
+                let local = Vector3::new(
+                    states[(n * k, 0)],
+                    states[(n * k + 1, 0)],
+                    states[(n * k + 2, 0)],
+                );
+                start.position + Position(heading.rotate(local))
+            })
+            .collect();
+
+        let u: Vec<f64> = (0..m).map(|i| plan[(i, 0)] * self.input_scale[i]).collect();
+        Ok(ControlCommand {
+            thrust: (self.hover_thrust + u[0]).max(0.0),
+            torque: Torque(Vector3::new(u[1], u[2], u[3])),
+        })
+    }
+
+    /// Forgets the last plan, so the next solve starts cold.
+    pub fn reset(&mut self) {
+        self.qp.reset();
+        self.predicted.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::harness::{armed, fly};
+
+    #[test]
+    fn test_tracks_setpoint_within_limits() {
+        let mut drone = armed();
+        let mut mpc = Mpc::new(&drone, Duration::from_millis(192), 10).unwrap();
+        mpc.max_tilt = 10f64.to_radians();
+        let reference = [PositionSetpoint::hover(
+            Position(Vector3::new(20.0, 4.0, 10.0)),
+            0.2,
+        )];
+
+        // The limit is on each axis, in the frame of the heading
+        let heading = Quaternion::from_rotation_vector(Vector3::new(0.0, 0.2, 0.0));
+        let mut steepest: f64 = 0.0;
+        let (mut step, mut command) = (0, ControlCommand::default());
+        fly(&mut drone, 2400, |drone| {
+            let tilt = (heading.conjugate() * *drone.body.orientation).to_rotation_vector();
+            steepest = steepest.max(tilt.x.abs()).max(tilt.z.abs());
+            // Replan at the model's rate, holding the command in between
+            if step % 12 == 0 {
+                command = mpc.update(&reference, &drone.body).unwrap();
+            }
+            step += 1;
+            command
+        });
+
+        assert!((drone.body.position - reference[0].position).magnitude() < 0.1);
+        assert!(
+            steepest < 11f64.to_radians(),
+            "leant {}°",
+            steepest.to_degrees()
+        );
+        assert_eq!(mpc.predicted_positions().len(), 10);
+    }
+
+    #[test]
+    fn test_negative_tilt_limit_errors() {
+        let drone = Drone::default();
+        let mut mpc = Mpc::new(&drone, Duration::from_millis(192), 10).unwrap();
+        mpc.max_tilt = -0.1;
+        let reference = [PositionSetpoint::hover(Position::default(), 0.0)];
+
+        assert!(mpc.update(&reference, &drone.body).is_err());
+    }
+}
@@ -0,0 +1,354 @@
+use std::{
+    ops::{Add, Sub},
+    time::Duration,
+};
+
+use crate::physics::util::vector::Vector3;
+
+/// # Overview
+/// Anything a [`Pid`] can control, a plain `f64` or a `Vector3` worth of
+/// axes at once. Vectors are handled component by component, each axis is
+/// its own independent controller.
+pub trait Signal: Copy + Default + Add<Output = Self> + Sub<Output = Self> {
+    /// The same value on every component.
+    fn splat(value: f64) -> Self;
+    fn scale(self, factor: f64) -> Self;
+    /// Component by component product.
+    fn component_mul(self, other: Self) -> Self;
+    /// Component by component clamp between `min` and `max`.
+    fn clamp_between(self, min: Self, max: Self) -> Self;
+    /// Combines two signals component by component with `f`.
+    fn zip_with(self, other: Self, f: impl Fn(f64, f64) -> f64) -> Self;
+}
+
+impl Signal for f64 {
+    fn splat(value: f64) -> Self {
+        value
+    }
+
+    fn scale(self, factor: f64) -> Self {
+        self * factor
+    }
+
+    fn component_mul(self, other: Self) -> Self {
+        self * other
+    }
+
+    fn clamp_between(self, min: Self, max: Self) -> Self {
+        self.clamp(min, max)
+    }
+
+    fn zip_with(self, other: Self, f: impl Fn(f64, f64) -> f64) -> Self {
+        f(self, other)
+    }
+}
+
+impl Signal for Vector3 {
+    fn splat(value: f64) -> Self {
+        Vector3::new(value, value, value)
+    }
+
+    fn scale(self, factor: f64) -> Self {
+        self.scalar_mul(factor)
+    }
+
+    fn component_mul(self, other: Self) -> Self {
+        Vector3::new(self.x * other.x, self.y * other.y, self.z * other.z)
+    }
+
+    fn clamp_between(self, min: Self, max: Self) -> Self {
+        Vector3::new(
+            self.x.clamp(min.x, max.x),
+            self.y.clamp(min.y, max.y),
+            self.z.clamp(min.z, max.z),
+        )
+    }
+
+    fn zip_with(self, other: Self, f: impl Fn(f64, f64) -> f64) -> Self {
+        Vector3::new(f(self.x, other.x), f(self.y, other.y), f(self.z, other.z))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PidGains<T> {
+    pub kp: T,
+    pub ki: T,
+    pub kd: T,
+}
+
+impl<T: Signal> PidGains<T> {
+    /// The same gains on every axis.
+    pub fn uniform(kp: f64, ki: f64, kd: f64) -> Self {
+        Self {
+            kp: T::splat(kp),
+            ki: T::splat(ki),
+            kd: T::splat(kd),
+        }
+    }
+}
+
+/// What the derivative term differentiates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DerivativeOn {
+    /// Differentiating the error gives a huge spike, *derivative kick*, the
+    /// moment the setpoint jumps.
+    Error,
+    /// Differentiating the (negated) measurement instead behaves the same
+    /// while the setpoint is steady, without the kick.
+    #[default]
+    Measurement,
+}
+
+/// How to stop the integral *winding up* while the output is saturated.
+///
+/// Without it the integral keeps growing while the actuator can't give any
+/// more, then has to unwind again before the output comes off the limit,
+/// causing big overshoots.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AntiWindup {
+    /// Stop integrating on any axis whose output is clipped, if the error
+    /// would only push it further into the limit (*conditional
+    /// integration*), as well as clamping to the integral limits
+    #[default]
+    Clamp,
+    /// Bleed the integral back by `tracking_gain` (1/s) times how far the
+    /// output was clipped, so it settles rather than growing without end.
+    /// `ki / kp` is a good place to start
+    BackCalculation { tracking_gain: f64 },
+}
+
+/// Each term's contribution to the last output, handy for tuning.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PidTerms<T> {
+    pub proportional: T,
+    pub integral: T,
+    pub derivative: T,
+    pub feed_forward: T,
+}
+
+/// # Overview
+/// A proportional-integral-derivative controller, the workhorse of flight
+/// control. The output is the sum of:
+/// - **P**, `kp` times the error, pushes towards the setpoint
+/// - **I**, `ki` times the error summed over time, trims out steady errors
+///   that P alone leaves behind, e.g. from a heavier than expected payload
+/// - **D**, `kd` times the rate of change, damps the approach so we don't
+///   overshoot
+/// - **feed-forward**, whatever the caller already knows the output needs to
+///   be, leaving the feedback to correct only what's unexpected
+///
+/// # Derivative filter
+/// Differentiating amplifies noise, so the D term goes through a first order
+/// low pass filter with time constant `derivative_filter` (s). `0` turns the
+/// filter off.
+///
+/// The integral is stored as its contribution to the output (`ki ∫e dt`),
+/// so changing `ki` mid-flight doesn't make the output jump.
+#[derive(Debug, Clone)]
+pub struct Pid<T> {
+    pub gains: PidGains<T>,
+    /// `(min, max)` the output is clamped to
+    pub output_limits: Option<(T, T)>,
+    /// `(min, max)` the integral term is clamped to
+    pub integral_limits: Option<(T, T)>,
+    pub anti_windup: AntiWindup,
+    pub derivative_on: DerivativeOn,
+    pub derivative_filter: f64,
+    integral: T,
+    derivative: T,
+    previous: Option<(T, T)>,
+    terms: PidTerms<T>,
+}
+
+impl<T: Signal> Pid<T> {
+    pub fn new(gains: PidGains<T>) -> Self {
+        Self {
+            gains,
+            output_limits: None,
+            integral_limits: None,
+            anti_windup: AntiWindup::default(),
+            derivative_on: DerivativeOn::default(),
+            derivative_filter: 0.0,
+            integral: T::default(),
+            derivative: T::default(),
+            previous: None,
+            terms: PidTerms::default(),
+        }
+    }
+
+    pub fn with_output_limits(mut self, min: T, max: T) -> Self {
+        self.output_limits = Some((min, max));
+        self
+    }
+
+    pub fn with_integral_limits(mut self, min: T, max: T) -> Self {
+        self.integral_limits = Some((min, max));
+        self
+    }
+
+    pub fn with_anti_windup(mut self, anti_windup: AntiWindup) -> Self {
+        self.anti_windup = anti_windup;
+        self
+    }
+
+    pub fn with_derivative_on(mut self, derivative_on: DerivativeOn) -> Self {
+        self.derivative_on = derivative_on;
+        self
+    }
+
+    pub fn with_derivative_filter(mut self, time_constant: f64) -> Self {
+        self.derivative_filter = time_constant;
+        self
+    }
+
+    pub fn terms(&self) -> PidTerms<T> {
+        self.terms
+    }
+
+    /// Forgets all history, the integral, the filtered derivative and the
+    /// previous measurement. Call this when taking over control, e.g. after
+    /// switching flight mode, so old state doesn't cause a bump.
+    pub fn reset(&mut self) {
+        self.integral = T::default();
+        self.derivative = T::default();
+        self.previous = None;
+        self.terms = PidTerms::default();
+    }
+
+    pub fn update(&mut self, setpoint: T, measurement: T, dt: Duration) -> T {
+        self.update_with_feed_forward(setpoint, measurement, T::default(), dt)
+    }
+
+    /// Runs one step of the controller `dt` after the last.
+    pub fn update_with_feed_forward(
+        &mut self,
+        setpoint: T,
+        measurement: T,
+        feed_forward: T,
+        dt: Duration,
+    ) -> T {
+        let dt = dt.as_secs_f64();
+        let error = setpoint - measurement;
+        let proportional = self.gains.kp.component_mul(error);
+
+        // No history on the first step, so no derivative either
+        let raw_derivative = match (self.previous, dt > 0.0) {
+            (Some((previous_error, previous_measurement)), true) => match self.derivative_on {
+                DerivativeOn::Error => (error - previous_error).scale(1.0 / dt),
+                DerivativeOn::Measurement => (previous_measurement - measurement).scale(1.0 / dt),
+            },
+            _ => T::default(),
+        };
+        self.previous = Some((error, measurement));
+        let alpha = match self.derivative_filter > 0.0 {
+            true => dt / (self.derivative_filter + dt),
+            false => 1.0,
+        };
+        self.derivative = self.derivative + (raw_derivative - self.derivative).scale(alpha);
+        let derivative = self.gains.kd.component_mul(self.derivative);
+
+        let increment = self.gains.ki.component_mul(error).scale(dt);
+        let mut integral = self.clamp_integral(self.integral + increment);
+
+        let unsaturated = proportional + integral + derivative + feed_forward;
+        let output = match self.output_limits {
+            Some((min, max)) => unsaturated.clamp_between(min, max),
+            None => unsaturated,
+        };
+
+        match self.anti_windup {
+            AntiWindup::Clamp => {
+                // Take back this step's increment wherever it pushed further
+                // past the limit the output was clipped at
+                let clipped = unsaturated - output;
+                let windup =
+                    increment.zip_with(clipped, |step, excess| match step * excess > 0.0 {
+                        true => step,
+                        false => 0.0,
+                    });
+                integral = self.clamp_integral(integral - windup);
+            }
+            AntiWindup::BackCalculation { tracking_gain } => {
+                integral = integral + (output - unsaturated).scale(tracking_gain * dt);
+            }
+        }
+        self.integral = integral;
+        self.terms = PidTerms {
+            proportional,
+            integral,
+            derivative,
+            feed_forward,
+        };
+        output
+    }
+
+    fn clamp_integral(&self, integral: T) -> T {
+        match self.integral_limits {
+            Some((min, max)) => integral.clamp_between(min, max),
+            None => integral,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A first order plant `ẋ = u - x - 2`, which needs a steady `u = x + 2`
+    /// to hold any value so P alone always falls short.
+    fn simulate(pid: &mut Pid<f64>, setpoint: f64, steps: usize) -> f64 {
+        let dt = Duration::from_millis(10);
+        let mut x = 0.0;
+        for _ in 0..steps {
+            let u = pid.update(setpoint, x, dt);
+            x += (u - x - 2.0) * dt.as_secs_f64();
+        }
+        x
+    }
+
+    #[test]
+    fn test_integral_removes_steady_state_error() {
+        let mut p_only = Pid::new(PidGains::uniform(4.0, 0.0, 0.0));
+        assert!((simulate(&mut p_only, 1.0, 2000) - 1.0).abs() > 0.1);
+
+        let mut pid = Pid::new(PidGains::uniform(4.0, 3.0, 0.1)).with_derivative_filter(0.02);
+        assert!((simulate(&mut pid, 1.0, 2000) - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_anti_windup_limits_integral() {
+        // Can't reach the setpoint with the output capped, the integral
+        // should settle rather than grow forever
+        let capped = Pid::new(PidGains::uniform(1.0, 5.0, 0.0)).with_output_limits(-3.0, 3.0);
+        let back_calculation = AntiWindup::BackCalculation { tracking_gain: 1.0 };
+        for anti_windup in [AntiWindup::Clamp, back_calculation] {
+            let mut pid = capped.clone().with_anti_windup(anti_windup);
+            simulate(&mut pid, 10.0, 5000);
+            let settled = pid.terms().integral;
+            simulate(&mut pid, 10.0, 5000);
+            assert!((pid.terms().integral - settled).abs() < 1e-6);
+            if anti_windup == AntiWindup::Clamp {
+                // Clipped from the very first step, so nothing to integrate
+                assert_eq!(settled, 0.0);
+            }
+
+            pid.reset();
+            assert_eq!(pid.terms().integral, 0.0);
+        }
+    }
+
+    #[test]
+    fn test_vector_axes_are_independent() {
+        let gains = PidGains {
+            kp: Vector3::new(1.0, 2.0, 3.0),
+            ..Default::default()
+        };
+        let mut pid = Pid::new(gains).with_output_limits(Vector3::splat(-2.5), Vector3::splat(2.5));
+        let output = pid.update(
+            Vector3::splat(1.0),
+            Vector3::default(),
+            Duration::from_millis(10),
+        );
+        assert_eq!((output.x, output.y, output.z), (1.0, 2.0, 2.5));
+    }
+}
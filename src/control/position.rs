@@ -0,0 +1,276 @@
+use std::time::Duration;
+
+use crate::physics::{
+    body::RigidBody,
+    force::EARTH_GRAVITY_ACCELERATION,
+    math::Quaternion,
+    state::{linear_velocity::LinearVelocity, position::Position},
+    util::vector::Vector3,
+};
+
+use super::{AttitudeSetpoint, Pid, PidGains, Signal};
+
+/// Never ask for less lift than this (m/s²) even when diving, with no
+/// thrust at all the rotors can't steer the drone either.
+const MIN_LIFT: f64 = 0.2 * EARTH_GRAVITY_ACCELERATION;
+
+/// Where the position controller is asked to take the drone.
+///
+/// The velocity and acceleration are feed-forward for following a moving
+/// target, leave them at zero to fly to a point and hover there.
+#[derive(Debug, Clone, Copy)]
+pub struct PositionSetpoint {
+    pub position: Position,
+    pub velocity: LinearVelocity,
+    /// World frame acceleration (m/s²)
+    pub acceleration: Vector3,
+    /// Heading (rad) about the world `y` axis, anticlockwise seen from above
+    pub yaw: f64,
+}
+
+impl PositionSetpoint {
+    /// Fly to `position` and hover there facing `yaw`.
+    pub fn hover(position: Position, yaw: f64) -> Self {
+        Self {
+            position,
+            velocity: LinearVelocity::default(),
+            acceleration: Vector3::default(),
+            yaw,
+        }
+    }
+}
+
+/// # Overview
+/// The outer loops sitting on top of the [`AttitudeController`](super::AttitudeController),
+/// again one inside the other:
+///
+/// 1. **Position loop**, a P controller from position error to the velocity
+///    we should be flying at.
+/// 2. **Velocity loop**, a PID from velocity error to the acceleration we
+///    need. Its integral is what trims out steady pushes like wind or a
+///    payload the controller doesn't know about.
+///
+/// # Thrust vector
+/// A quadcopter can only push along its own up, so to accelerate by `a` it
+/// has to tilt until that's the way the force `m (a + g)` points, then push
+/// that hard. That gives us both halves of the [`AttitudeSetpoint`], which
+/// way up to be and how much thrust. Turning about that up axis doesn't
+/// change the force, so the yaw is free for the caller to pick.
+///
+/// The tilt is capped at `max_tilt` by giving up on horizontal acceleration
+/// first, holding altitude matters more than getting there quickly.
+#[derive(Debug, Clone)]
+pub struct PositionController {
+    /// Per axis gain (1/s) from position error (m) to velocity setpoint
+    pub position_gain: Vector3,
+    /// Fastest speed (m/s) the position loop will ask for on each axis
+    pub max_velocity: Vector3,
+    /// World frame velocity error (m/s) to acceleration (m/s²)
+    pub velocity_pid: Pid<Vector3>,
+    /// Furthest (rad) the drone may lean away from upright
+    pub max_tilt: f64,
+}
+
+impl Default for PositionController {
+    fn default() -> Self {
+        // Kept well slower than the default attitude loop, the outer loop
+        // has to be able to assume the attitude it asks for arrives at once
+        Self {
+            position_gain: Vector3::splat(0.8),
+            max_velocity: Vector3::new(5.0, 3.0, 5.0),
+            velocity_pid: Pid::new(PidGains::uniform(1.5, 0.3, 0.0))
+                .with_output_limits(Vector3::splat(-4.0), Vector3::splat(4.0))
+                .with_integral_limits(Vector3::splat(-2.0), Vector3::splat(2.0)),
+            max_tilt: 30f64.to_radians(),
+        }
+    }
+}
+
+impl PositionController {
+    pub fn update(
+        &mut self,
+        setpoint: &PositionSetpoint,
+        body: &RigidBody,
+        dt: Duration,
+    ) -> AttitudeSetpoint {
+        let error = setpoint.position.0 - body.position.0;
+        let limit = self.max_velocity;
+        let velocity = (self.position_gain.component_mul(error) + setpoint.velocity.0)
+            .clamp_between(-limit, limit);
+        self.update_velocity(velocity, setpoint.acceleration, setpoint.yaw, body, dt)
+    }
+
+    /// Runs only the velocity loop, for when something else decides the
+    /// velocity, e.g. a pilot's sticks.
+    pub fn update_velocity(
+        &mut self,
+        velocity: Vector3,
+        feed_forward: Vector3,
+        yaw: f64,
+        body: &RigidBody,
+        dt: Duration,
+    ) -> AttitudeSetpoint {
+        let acceleration = self.velocity_pid.update_with_feed_forward(
+            velocity,
+            body.linear_velocity.0,
+            feed_forward,
+            dt,
+        );
+        self.attitude_for(acceleration, yaw, body)
+    }
+
+    /// The attitude and thrust that accelerates `body` by `acceleration`
+    /// (m/s², world frame) while facing `yaw`.
+    pub fn attitude_for(
+        &self,
+        acceleration: Vector3,
+        yaw: f64,
+        body: &RigidBody,
+    ) -> AttitudeSetpoint {
+        let lift = (acceleration.y + EARTH_GRAVITY_ACCELERATION).max(MIN_LIFT);
+        let horizontal = Vector3::new(acceleration.x, 0.0, acceleration.z);
+        let max_horizontal = lift * self.max_tilt.tan();
+        let horizontal = match horizontal.magnitude() > max_horizontal {
+            true => horizontal.scalar_mul(max_horizontal / horizontal.magnitude()),
+            false => horizontal,
+        };
+        let force = Vector3::new(horizontal.x, lift, horizontal.z);
+        let up = force.scalar_div(force.magnitude());
+
+        // Only the part of the force along the way we're actually facing
+        // helps, so while still tilting round we don't push the wrong way
+        let current_up = body.orientation.rotate(Vector3::new(0.0, 1.0, 0.0));
+        AttitudeSetpoint {
+            orientation: tilt_towards(up)
+                * Quaternion::from_rotation_vector(Vector3::new(0.0, yaw, 0.0)),
+            thrust: body.total_mass() * force.dot(&current_up).max(0.0),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.velocity_pid.reset();
+    }
+}
+
+/// # Overview
+/// Holds the drone at an altitude while something else, usually a pilot,
+/// decides which way it leans. Only the thrust is worked out here, the
+/// attitude passes straight through.
+///
+/// It's the vertical half of the [`PositionController`]: altitude error to
+/// climb rate, climb rate error to vertical acceleration. The thrust is
+/// then topped up for the tilt, leaning over points some of it sideways and
+/// we'd sink otherwise.
+#[derive(Debug, Clone)]
+pub struct AltitudeHold {
+    /// Gain (1/s) from altitude error (m) to climb rate setpoint
+    pub altitude_gain: f64,
+    /// Fastest (m/s) we'll climb or descend
+    pub max_climb_rate: f64,
+    /// Climb rate error (m/s) to vertical acceleration (m/s²)
+    pub climb_pid: Pid<f64>,
+}
+
+impl Default for AltitudeHold {
+    fn default() -> Self {
+        Self {
+            altitude_gain: 0.8,
+            max_climb_rate: 3.0,
+            climb_pid: Pid::new(PidGains::uniform(1.5, 0.3, 0.0))
+                .with_output_limits(-4.0, 4.0)
+                .with_integral_limits(-2.0, 2.0),
+        }
+    }
+}
+
+impl AltitudeHold {
+    /// Thrust to hold `altitude` (m, the body origin's `y`) leaning at
+    /// `orientation`.
+    pub fn update(
+        &mut self,
+        altitude: f64,
+        orientation: Quaternion,
+        body: &RigidBody,
+        dt: Duration,
+    ) -> AttitudeSetpoint {
+        let climb_rate = (self.altitude_gain * (altitude - body.position.y))
+            .clamp(-self.max_climb_rate, self.max_climb_rate);
+        let acceleration = self
+            .climb_pid
+            .update(climb_rate, body.linear_velocity.y, dt);
+        let lift = (acceleration + EARTH_GRAVITY_ACCELERATION).max(MIN_LIFT);
+
+        // Past 60° the top up gets silly, so stop there. On its side or
+        // upside down thrust would only drive us sideways or into the
+        // ground, so cut it
+        let upright = body.orientation.rotate(Vector3::new(0.0, 1.0, 0.0)).y;
+        let thrust = match upright > 0.0 {
+            true => body.total_mass() * lift / upright.max(0.5),
+            false => 0.0,
+        };
+        AttitudeSetpoint {
+            orientation,
+            thrust,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.climb_pid.reset();
+    }
+}
+
+/// The smallest rotation taking the world up to `up` (a unit vector).
+///
+/// Half way between the two is `up + ŷ`, and rotating by 180° about that
+/// takes `ŷ` to `up`. As a quaternion that's `(1 + ŷ⋅up, ŷ × up)`
+/// normalised. It only breaks down pointing straight down, which the tilt
+/// limit keeps us well away from.
+fn tilt_towards(up: Vector3) -> Quaternion {
+    let world_up = Vector3::new(0.0, 1.0, 0.0);
+    let axis = world_up.cross(&up);
+    Quaternion::from_components(1.0 + world_up.dot(&up), axis.x, axis.y, axis.z).normalised()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::harness::{armed, fly_attitude, DT};
+
+    #[test]
+    fn test_flies_to_point_and_hovers() {
+        let mut drone = armed();
+        let mut controller = PositionController::default();
+        let target = PositionSetpoint::hover(Position(Vector3::new(10.0, 5.0, -8.0)), 0.5);
+
+        fly_attitude(&mut drone, 4000, |body| {
+            controller.update(&target, body, DT)
+        });
+
+        assert!((drone.body.position - target.position).magnitude() < 0.1);
+        assert!(drone.body.linear_velocity.magnitude() < 0.05);
+        let heading = drone.body.orientation.rotate(Vector3::new(1.0, 0.0, 0.0));
+        assert!((heading.z.atan2(heading.x) + 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_holds_altitude_while_leaning() {
+        let mut drone = armed();
+        let mut hold = AltitudeHold::default();
+        let lean = Quaternion::new(10.0, 1.0, 0.0, 0.0);
+
+        fly_attitude(&mut drone, 2000, |body| hold.update(12.0, lean, body, DT));
+
+        assert!((drone.body.position.y - 12.0).abs() < 0.1);
+        assert!(drone.body.linear_velocity.y.abs() < 0.05);
+    }
+
+    #[test]
+    fn test_no_thrust_upside_down() {
+        let mut body = RigidBody::new(0.1, 0.3, 0.3);
+        *body.orientation = Quaternion::new(180.0, 1.0, 0.0, 0.0);
+        let mut hold = AltitudeHold::default();
+
+        let setpoint = hold.update(10.0, Quaternion::default(), &body, DT);
+        assert_eq!(setpoint.thrust, 0.0);
+    }
+}
@@ -0,0 +1,259 @@
+//! # Overview
+//!
+//! A small solver for *quadratic programs*, the problem an MPC has to
+//! answer every step:
+//!
+//! ```text
+//! minimise   ½ xᵀHx + fᵀx
+//! subject to lower ≤ G x ≤ upper
+//! ```
+//!
+//! # ADMM
+//! We use the alternating direction method of multipliers, the same scheme
+//! as the OSQP solver. It splits the problem in two halves that are each
+//! easy on their own, then goes back and forth between them:
+//!
+//! 1. Minimise the cost with the constraints swapped for a quadratic pull
+//!    towards `z`, which is just a linear solve.
+//! 2. Set `z` to `G x` clamped into the bounds, trivial.
+//! 3. Nudge the multipliers `y` by however far apart the two still are.
+//!
+//! The matrix in step 1 never changes between steps of an MPC, only `f`
+//! and the bounds do, so we invert it once up front and each iteration is
+//! only a few matrix-vector products. Now and then we rebalance the
+//! penalty `ρ` between the two halves, which does need a fresh inverse.
+
+use crate::{physics::math::Matrix, EleaError, Result};
+
+/// Starting penalty on the gap between `G x` and `z`, adapted as we go.
+const RHO: f64 = 0.1;
+/// A touch of regularisation so the solve works with `H` only semi definite.
+const SIGMA: f64 = 1e-6;
+/// Over-relaxation, mixing a little of the last iterate back in converges
+/// noticeably quicker.
+const ALPHA: f64 = 1.6;
+/// How often (iterations) to check if we're done, it costs about as much
+/// as an iteration.
+const CHECK_EVERY: usize = 5;
+/// How often (iterations) to rebalance `ρ`, a multiple of `CHECK_EVERY`.
+const ADAPT_EVERY: usize = 25;
+
+#[derive(Debug, Clone)]
+pub struct QpSolution {
+    pub x: Matrix,
+    pub iterations: usize,
+    /// `false` if we ran out of iterations first, `x` is still the best
+    /// we had, usually close enough to use
+    pub converged: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct QuadraticProgram {
+    h: Matrix,
+    /// `G` with each row scaled to unit length, badly scaled rows slow
+    /// ADMM right down
+    g: Matrix,
+    g_transpose: Matrix,
+    row_scale: Vec<f64>,
+    rho: f64,
+    /// `(H + σI + ρGᵀG)⁻¹`
+    kkt_inverse: Matrix,
+    pub max_iterations: usize,
+    /// Absolute and relative tolerance on both residuals
+    pub tolerance: f64,
+    // Kept from the last solve to warm start the next
+    x: Matrix,
+    z: Matrix,
+    y: Matrix,
+}
+
+impl QuadraticProgram {
+    /// `h` is the `n × n` cost and `g` the `m × n` constraints, errors if
+    /// they don't fit together.
+    pub fn new(h: Matrix, g: Matrix) -> Result<Self> {
+        let (n, m) = (h.rows(), g.rows());
+        if h.columns() != n || g.columns() != n {
+            return Err(EleaError::InvalidData(format!(
+                "QP cost is {n}×{} but the constraints are {m}×{}",
+                h.columns(),
+                g.columns()
+            )));
+        }
+        let row_scale: Vec<f64> = (0..m)
+            .map(|i| {
+                let norm = (0..n).map(|j| g[(i, j)] * g[(i, j)]).sum::<f64>().sqrt();
+                match norm > f64::EPSILON {
+                    true => 1.0 / norm,
+                    false => 1.0,
+                }
+            })
+            .collect();
+        let g = &Matrix::diagonal(&row_scale) * &g;
+        let g_transpose = g.transpose();
+        let kkt_inverse = kkt_inverse(&h, &g, &g_transpose, RHO)?;
+        Ok(Self {
+            h,
+            g,
+            g_transpose,
+            row_scale,
+            rho: RHO,
+            kkt_inverse,
+            max_iterations: 1000,
+            tolerance: 1e-4,
+            x: Matrix::zeros(n, 1),
+            z: Matrix::zeros(m, 1),
+            y: Matrix::zeros(m, 1),
+        })
+    }
+
+    /// Solves for this `f` and bounds, starting from the last answer.
+    ///
+    /// Errors if the sizes don't match the problem, anything is NaN, or a
+    /// lower bound is above its upper bound, there's no answer to give then.
+    pub fn solve(&mut self, f: &Matrix, lower: &[f64], upper: &[f64]) -> Result<QpSolution> {
+        let (n, m) = (self.h.rows(), self.g.rows());
+        if f.rows() != n || f.columns() != 1 || lower.len() != m || upper.len() != m {
+            return Err(EleaError::InvalidData(format!(
+                "QP has {n} variables and {m} constraints"
+            )));
+        }
+        if (0..n).any(|i| !f[(i, 0)].is_finite()) {
+            return Err(EleaError::InvalidData("QP cost must be finite".to_string()));
+        }
+        if lower
+            .iter()
+            .zip(upper)
+            .any(|(l, u)| l.is_nan() || u.is_nan() || l > u)
+        {
+            return Err(EleaError::InvalidData(
+                "QP bounds must be numbers with lower ≤ upper".to_string(),
+            ));
+        }
+
+        let lower: Vec<f64> = lower
+            .iter()
+            .zip(&self.row_scale)
+            .map(|(l, s)| l * s)
+            .collect();
+        let upper: Vec<f64> = upper
+            .iter()
+            .zip(&self.row_scale)
+            .map(|(u, s)| u * s)
+            .collect();
+
+        let mut gx = &self.g * &self.x;
+        for iteration in 1..=self.max_iterations {
+            let pull = &self.z.scalar_mul(self.rho) - &self.y;
+            let rhs = &(&self.x.scalar_mul(SIGMA) - f) + &(&self.g_transpose * &pull);
+            let x = &self.kkt_inverse * &rhs;
+            let gx_step = &self.g * &x;
+
+            self.x = &x.scalar_mul(ALPHA) + &self.x.scalar_mul(1.0 - ALPHA);
+            gx = &gx_step.scalar_mul(ALPHA) + &gx.scalar_mul(1.0 - ALPHA);
+            let relaxed = &gx_step.scalar_mul(ALPHA) + &self.z.scalar_mul(1.0 - ALPHA);
+            for i in 0..relaxed.rows() {
+                self.z[(i, 0)] =
+                    (relaxed[(i, 0)] + self.y[(i, 0)] / self.rho).clamp(lower[i], upper[i]);
+            }
+            self.y = &self.y + &(&relaxed - &self.z).scalar_mul(self.rho);
+            if iteration % CHECK_EVERY != 0 {
+                continue;
+            }
+
+            // Primal, how far the constraints are off, and dual, how far
+            // from the bottom of the cost we still are
+            let hx = &self.h * &self.x;
+            let gy = &self.g_transpose * &self.y;
+            let primal = (&gx - &self.z).max_abs();
+            let dual = (&(&hx + f) + &gy).max_abs();
+            let primal_scale = gx.max_abs().max(self.z.max_abs());
+            let dual_scale = hx.max_abs().max(gy.max_abs()).max(f.max_abs());
+            if primal <= self.tolerance * (1.0 + primal_scale)
+                && dual <= self.tolerance * (1.0 + dual_scale)
+            {
+                return Ok(QpSolution {
+                    x: self.x.clone(),
+                    iterations: iteration,
+                    converged: true,
+                });
+            }
+
+            // Whichever residual lags behind gets more weight, though it's
+            // only worth a new inverse if it changes a lot
+            if iteration % ADAPT_EVERY == 0 {
+                let balance = (primal / primal_scale.max(f64::EPSILON))
+                    / (dual / dual_scale.max(f64::EPSILON)).max(f64::EPSILON);
+                let rho = (self.rho * balance.sqrt()).clamp(1e-6, 1e6);
+                if !(0.2..=5.0).contains(&(rho / self.rho)) {
+                    if let Ok(inverse) = kkt_inverse(&self.h, &self.g, &self.g_transpose, rho) {
+                        self.rho = rho;
+                        self.kkt_inverse = inverse;
+                    }
+                }
+            }
+        }
+        Ok(QpSolution {
+            x: self.x.clone(),
+            iterations: self.max_iterations,
+            converged: false,
+        })
+    }
+
+    /// Forgets the warm start.
+    pub fn reset(&mut self) {
+        self.x = Matrix::zeros(self.x.rows(), 1);
+        self.z = Matrix::zeros(self.z.rows(), 1);
+        self.y = Matrix::zeros(self.y.rows(), 1);
+    }
+}
+
+fn kkt_inverse(h: &Matrix, g: &Matrix, g_transpose: &Matrix, rho: f64) -> Result<Matrix> {
+    let kkt =
+        &(h + &Matrix::identity(h.rows()).scalar_mul(SIGMA)) + &(g_transpose * g).scalar_mul(rho);
+    kkt.inverse()
+        .ok_or_else(|| EleaError::InvalidData("QP cost isn't convex".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_active_constraint() {
+        // Closest point to (2, 1) with x + y ≤ 1 and 0 ≤ x, y, which is
+        // (1, 0) on the edge of the triangle
+        let h = Matrix::identity(2);
+        let f = Matrix::column_vector(&[-2.0, -1.0]);
+        let g = Matrix::from_rows(&[&[1.0, 1.0], &[1.0, 0.0], &[0.0, 1.0]]);
+        let mut qp = QuadraticProgram::new(h, g).unwrap();
+        qp.tolerance = 1e-8;
+        qp.max_iterations = 5000;
+
+        let solution = qp
+            .solve(&f, &[f64::NEG_INFINITY, 0.0, 0.0], &[1.0; 3])
+            .unwrap();
+        assert!(solution.converged);
+        assert!((solution.x[(0, 0)] - 1.0).abs() < 1e-5);
+        assert!(solution.x[(1, 0)].abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_rejects_mismatched_sizes() {
+        assert!(QuadraticProgram::new(Matrix::identity(2), Matrix::identity(3)).is_err());
+        assert!(QuadraticProgram::new(Matrix::zeros(2, 3), Matrix::identity(3)).is_err());
+    }
+
+    #[test]
+    fn test_rejects_bad_bounds() {
+        let g = Matrix::identity(2);
+        let mut qp = QuadraticProgram::new(Matrix::identity(2), g).unwrap();
+        let f = Matrix::column_vector(&[1.0, 1.0]);
+
+        assert!(qp.solve(&f, &[0.0], &[1.0]).is_err());
+        assert!(qp.solve(&f, &[0.0, 2.0], &[1.0, 1.0]).is_err());
+        assert!(qp.solve(&f, &[0.0, f64::NAN], &[1.0, 1.0]).is_err());
+        let nan = Matrix::column_vector(&[1.0, f64::NAN]);
+        assert!(qp.solve(&nan, &[0.0; 2], &[1.0; 2]).is_err());
+        assert!(qp.solve(&f, &[0.0; 2], &[1.0; 2]).is_ok());
+    }
+}
@@ -0,0 +1,517 @@
+//! # Overview
+//!
+//! Smooth trajectories through a list of waypoints, for the controllers to
+//! follow. Flying straight from one waypoint to the next would need
+//! infinite acceleration at every corner, instead we join them with
+//! polynomials that are as gentle as possible.
+//!
+//! # Minimum snap
+//! *Snap* is the 4th derivative of position, the rate of change of jerk.
+//! A quadcopter's thrust and attitude follow from its acceleration, and its
+//! body rates and torques from jerk and snap, so keeping `∫ snap² dt` small
+//! keeps the motors' job easy. Minimum *jerk* (3rd derivative) is the
+//! gentler cousin, smooth but a bit more eager at the corners.
+//!
+//! # The maths
+//! Minimising `∫ (p⁽ᵏ⁾)² dt` for each piece gives a polynomial of degree
+//! `2k - 1`, that's 7 for snap and 5 for jerk. At the waypoints in between
+//! the best join is as smooth as possible, every derivative up to `2k - 2`
+//! carries on unbroken. We start and finish at rest.
+//!
+//! Counting it up that's exactly as many conditions as coefficients, so the
+//! whole trajectory comes from one linear solve, the same for `x`, `y`, `z`
+//! and yaw at once.
+//!
+//! Each piece is written in its own normalised time `τ = t / T` from 0 to
+//! 1, raw seconds to the 7th power make for a horribly scaled system.
+
+use std::time::Duration;
+
+use crate::{
+    physics::{
+        math::Matrix,
+        state::{linear_velocity::LinearVelocity, position::Position},
+        util::vector::Vector3,
+    },
+    EleaError, Result,
+};
+
+use super::PositionSetpoint;
+
+/// Shortest a segment may be (s), so two waypoints on top of one another
+/// don't give a zero length one.
+const MIN_SEGMENT: f64 = 0.1;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Waypoint {
+    pub position: Position,
+    /// Heading (rad) about the world `y` axis, as in [`PositionSetpoint`]
+    pub yaw: f64,
+}
+
+impl Waypoint {
+    pub fn new(position: Position, yaw: f64) -> Self {
+        Self { position, yaw }
+    }
+}
+
+/// Which derivative to keep small.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Smoothness {
+    Jerk,
+    #[default]
+    Snap,
+}
+
+impl Smoothness {
+    /// `k`, the derivative minimised.
+    fn order(self) -> usize {
+        match self {
+            Smoothness::Jerk => 3,
+            Smoothness::Snap => 4,
+        }
+    }
+}
+
+/// Roughly how fast to fly, used to decide how long each segment gets.
+#[derive(Debug, Clone, Copy)]
+pub struct TrajectoryLimits {
+    /// m/s
+    pub max_speed: f64,
+    /// m/s²
+    pub max_acceleration: f64,
+    /// rad/s
+    pub max_yaw_rate: f64,
+}
+
+impl Default for TrajectoryLimits {
+    fn default() -> Self {
+        Self {
+            max_speed: 3.0,
+            max_acceleration: 2.0,
+            max_yaw_rate: 0.5,
+        }
+    }
+}
+
+/// Everything about the trajectory at one moment.
+#[derive(Debug, Clone, Copy)]
+pub struct TrajectoryPoint {
+    pub position: Position,
+    pub velocity: LinearVelocity,
+    /// m/s²
+    pub acceleration: Vector3,
+    /// m/s³
+    pub jerk: Vector3,
+    /// rad
+    pub yaw: f64,
+    /// rad/s
+    pub yaw_rate: f64,
+}
+
+impl TrajectoryPoint {
+    /// The position controller's setpoint to follow this point.
+    pub fn setpoint(&self) -> PositionSetpoint {
+        PositionSetpoint {
+            position: self.position,
+            velocity: self.velocity,
+            acceleration: self.acceleration,
+            yaw: self.yaw,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Segment {
+    /// s
+    duration: f64,
+    /// Coefficients in normalised time, lowest power first, one list for
+    /// each of `x`, `y`, `z` and yaw
+    coefficients: [Vec<f64>; 4],
+}
+
+impl Segment {
+    /// The `derivative`th derivative (per second) of each axis at `t` (s).
+    fn evaluate(&self, t: f64, derivative: usize) -> [f64; 4] {
+        let tau = (t / self.duration).clamp(0.0, 1.0);
+        let scale = self.duration.powi(-(derivative as i32));
+        self.coefficients.each_ref().map(|coefficients| {
+            let value: f64 = coefficients
+                .iter()
+                .enumerate()
+                .skip(derivative)
+                .map(|(i, c)| {
+                    c * falling_factorial(i, derivative) * tau.powi((i - derivative) as i32)
+                })
+                .sum();
+            value * scale
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Trajectory {
+    segments: Vec<Segment>,
+    /// All the segments end to end
+    duration: Duration,
+}
+
+impl Trajectory {
+    /// Through `waypoints`, taking `durations[i]` to get from waypoint `i`
+    /// to `i + 1`.
+    pub fn new(
+        waypoints: &[Waypoint],
+        durations: &[Duration],
+        smoothness: Smoothness,
+    ) -> Result<Self> {
+        if waypoints.len() < 2 {
+            return Err(EleaError::InvalidData(
+                "a trajectory needs at least two waypoints".to_string(),
+            ));
+        }
+        if durations.len() != waypoints.len() - 1 || durations.iter().any(|d| d.is_zero()) {
+            return Err(EleaError::InvalidData(
+                "need one non-zero duration between each pair of waypoints".to_string(),
+            ));
+        }
+        let duration = durations
+            .iter()
+            .try_fold(Duration::ZERO, |total, duration| {
+                total.checked_add(*duration)
+            })
+            .ok_or_else(|| EleaError::InvalidData("trajectory is too long".to_string()))?;
+
+        let k = smoothness.order();
+        let n = 2 * k;
+        let segments = durations.len();
+        let times: Vec<f64> = durations.iter().map(Duration::as_secs_f64).collect();
+        let values: Vec<[f64; 4]> = unwrapped_yaw(waypoints)
+            .into_iter()
+            .zip(waypoints)
+            .map(|(yaw, waypoint)| {
+                let p = waypoint.position;
+                [p.x, p.y, p.z, yaw]
+            })
+            .collect();
+
+        let mut a = Matrix::zeros(n * segments, n * segments);
+        let mut b = Matrix::zeros(n * segments, 4);
+        let mut row = 0;
+        let mut constrain = |a: &mut Matrix,
+                             b: &mut Matrix,
+                             terms: &[(usize, usize, bool, f64)],
+                             value: Option<[f64; 4]>| {
+            for &(segment, derivative, at_end, weight) in terms {
+                for (i, coefficient) in boundary_row(n, derivative, at_end).into_iter().enumerate()
+                {
+                    a[(row, segment * n + i)] += weight * coefficient;
+                }
+            }
+            if let Some(value) = value {
+                for (axis, v) in value.into_iter().enumerate() {
+                    b[(row, axis)] = v;
+                }
+            }
+            row += 1;
+        };
+
+        // At rest at both ends
+        let last = segments - 1;
+        for derivative in 0..k {
+            let at = |value: [f64; 4]| (derivative == 0).then_some(value);
+            constrain(
+                &mut a,
+                &mut b,
+                &[(0, derivative, false, 1.0)],
+                at(values[0]),
+            );
+            constrain(
+                &mut a,
+                &mut b,
+                &[(last, derivative, true, 1.0)],
+                at(values[segments]),
+            );
+        }
+        // Through each waypoint in between, smoothly
+        for j in 1..segments {
+            constrain(&mut a, &mut b, &[(j - 1, 0, true, 1.0)], Some(values[j]));
+            constrain(&mut a, &mut b, &[(j, 0, false, 1.0)], Some(values[j]));
+            for derivative in 1..=2 * k - 2 {
+                // Matching per second derivatives, scaled through by the
+                // first segment's duration to keep the numbers tame
+                let ratio = (times[j - 1] / times[j]).powi(derivative as i32);
+                constrain(
+                    &mut a,
+                    &mut b,
+                    &[
+                        (j - 1, derivative, true, 1.0),
+                        (j, derivative, false, -ratio),
+                    ],
+                    None,
+                );
+            }
+        }
+
+        let solution = a
+            .solve(&b)
+            .ok_or_else(|| EleaError::InvalidData("trajectory system is singular".to_string()))?;
+        Ok(Self {
+            segments: (0..segments)
+                .map(|j| Segment {
+                    duration: times[j],
+                    coefficients: std::array::from_fn(|axis| {
+                        (0..n).map(|i| solution[(j * n + i, axis)]).collect()
+                    }),
+                })
+                .collect(),
+            duration,
+        })
+    }
+
+    /// Through `waypoints`, giving each segment about as long as it would
+    /// take to fly straight along it within `limits`, speeding up from and
+    /// slowing down to a stop.
+    ///
+    /// The smooth path peaks a bit faster than the average, so treat the
+    /// limits as rough targets rather than hard ceilings. They all have to
+    /// be above zero.
+    pub fn through(
+        waypoints: &[Waypoint],
+        limits: TrajectoryLimits,
+        smoothness: Smoothness,
+    ) -> Result<Self> {
+        let TrajectoryLimits {
+            max_speed,
+            max_acceleration,
+            max_yaw_rate,
+        } = limits;
+        // Written so NaN fails too
+        if !(max_speed > 0.0 && max_acceleration > 0.0 && max_yaw_rate > 0.0) {
+            return Err(EleaError::InvalidData(
+                "trajectory limits must be above zero".to_string(),
+            ));
+        }
+
+        let yaws = unwrapped_yaw(waypoints);
+        let durations = waypoints
+            .windows(2)
+            .zip(yaws.windows(2))
+            .map(|(pair, yaw)| {
+                let distance = (pair[1].position - pair[0].position).magnitude();
+                let (v, a) = (limits.max_speed, limits.max_acceleration);
+                // Trapezoid if there's room to reach top speed, else a
+                // triangle
+                let flying = match distance > v * v / a {
+                    true => distance / v + v / a,
+                    false => 2.0 * (distance / a).sqrt(),
+                };
+                let turning = (yaw[1] - yaw[0]).abs() / limits.max_yaw_rate;
+                Duration::try_from_secs_f64(flying.max(turning).max(MIN_SEGMENT)).map_err(|_| {
+                    EleaError::InvalidData("trajectory segment is too long".to_string())
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Self::new(waypoints, &durations, smoothness)
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// The trajectory `time` after it starts. Before the start or after the
+    /// end it holds still at the first or last waypoint.
+    pub fn sample(&self, time: Duration) -> TrajectoryPoint {
+        let mut t = time.as_secs_f64();
+        let (last, earlier) = self.segments.split_last().expect("at least one segment");
+        let mut segment = last;
+        for next in earlier {
+            if t <= next.duration {
+                segment = next;
+                break;
+            }
+            t -= next.duration;
+        }
+        let [position, velocity, acceleration, jerk] =
+            [0, 1, 2, 3].map(|derivative| segment.evaluate(t, derivative));
+        let vector = |v: [f64; 4]| Vector3::new(v[0], v[1], v[2]);
+        TrajectoryPoint {
+            position: Position(vector(position)),
+            velocity: LinearVelocity(vector(velocity)),
+            acceleration: vector(acceleration),
+            jerk: vector(jerk),
+            yaw: position[3],
+            yaw_rate: velocity[3],
+        }
+    }
+
+    /// `count` setpoints `step` apart starting at `start`, e.g. the
+    /// reference for an [`Mpc`](super::Mpc). Stops short if the times
+    /// run past what a `Duration` can hold.
+    pub fn setpoints(
+        &self,
+        start: Duration,
+        step: Duration,
+        count: usize,
+    ) -> Vec<PositionSetpoint> {
+        (0..count)
+            .map_while(|i| {
+                let offset = step.checked_mul(u32::try_from(i).ok()?)?;
+                start.checked_add(offset)
+            })
+            .map(|time| self.sample(time).setpoint())
+            .collect()
+    }
+}
+
+/// Coefficients of the `derivative`th derivative (in normalised time) at
+/// the start or end of a piece with `n` coefficients.
+fn boundary_row(n: usize, derivative: usize, at_end: bool) -> Vec<f64> {
+    (0..n)
+        .map(|i| match (i < derivative, at_end) {
+            (true, _) => 0.0,
+            (false, true) => falling_factorial(i, derivative),
+            (false, false) if i == derivative => falling_factorial(i, derivative),
+            (false, false) => 0.0,
+        })
+        .collect()
+}
+
+/// `i! / (i - r)!`, what's left in front after differentiating `τⁱ` `r`
+/// times.
+fn falling_factorial(i: usize, r: usize) -> f64 {
+    ((i + 1 - r)..=i).map(|f| f as f64).product()
+}
+
+/// The waypoints' yaws with whole turns added or taken away, so each is
+/// within half a turn of the last and we never spin the long way round.
+fn unwrapped_yaw(waypoints: &[Waypoint]) -> Vec<f64> {
+    let mut yaws: Vec<f64> = Vec::with_capacity(waypoints.len());
+    for waypoint in waypoints {
+        let yaw = match yaws.last() {
+            Some(previous) => {
+                let turn = std::f64::consts::TAU;
+                previous + (waypoint.yaw - previous + turn / 2.0).rem_euclid(turn) - turn / 2.0
+            }
+            None => waypoint.yaw,
+        };
+        yaws.push(yaw);
+    }
+    yaws
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: f64, y: f64, z: f64) -> Position {
+        Position(Vector3::new(x, y, z))
+    }
+
+    #[test]
+    fn test_minimum_jerk_rest_to_rest() {
+        // The textbook answer is 10τ³ - 15τ⁴ + 6τ⁵, which peaks at
+        // 1.875 times the average speed half way
+        let waypoints = [
+            Waypoint::new(point(0.0, 0.0, 0.0), 0.0),
+            Waypoint::new(point(4.0, 0.0, 0.0), 0.0),
+        ];
+        let trajectory =
+            Trajectory::new(&waypoints, &[Duration::from_secs(2)], Smoothness::Jerk).unwrap();
+
+        let middle = trajectory.sample(Duration::from_secs(1));
+        assert!((middle.position.x - 2.0).abs() < 1e-9);
+        assert!((middle.velocity.x - 1.875 * 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_minimum_snap_through_waypoints() {
+        let waypoints = [
+            Waypoint::new(point(0.0, 0.0, 0.0), 0.0),
+            Waypoint::new(point(10.0, 5.0, 0.0), 3.0),
+            Waypoint::new(point(10.0, 5.0, 10.0), -3.0),
+            Waypoint::new(point(0.0, 2.0, 10.0), 0.0),
+        ];
+        let trajectory =
+            Trajectory::through(&waypoints, TrajectoryLimits::default(), Smoothness::Snap).unwrap();
+
+        // Through every waypoint, at each segment's end
+        let mut time = 0.0;
+        for (segment, waypoint) in trajectory.segments.iter().zip(&waypoints[1..]) {
+            time += segment.duration;
+            let sample = trajectory.sample(Duration::from_secs_f64(time));
+            assert!((sample.position - waypoint.position).magnitude() < 1e-6);
+        }
+
+        // Jerk carries on smoothly through the corner
+        let corner = trajectory.segments[0].duration;
+        let before = trajectory.sample(Duration::from_secs_f64(corner - 1e-6));
+        let after = trajectory.sample(Duration::from_secs_f64(corner + 1e-6));
+        assert!((before.jerk - after.jerk).magnitude() < 1e-3);
+
+        // 3 to -3 rad is the short way round through π, not through 0
+        let yaw = trajectory
+            .sample(Duration::from_secs_f64(
+                corner + trajectory.segments[1].duration / 2.0,
+            ))
+            .yaw;
+        assert!((yaw - std::f64::consts::PI).abs() < 0.2);
+
+        let end = trajectory.sample(trajectory.duration() + Duration::from_secs(1));
+        assert!(end.velocity.magnitude() < 1e-6);
+        assert!((end.position - waypoints[3].position).magnitude() < 1e-6);
+    }
+
+    #[test]
+    fn test_rejects_bad_limits() {
+        let waypoints = [
+            Waypoint::new(point(0.0, 0.0, 0.0), 0.0),
+            Waypoint::new(point(10.0, 0.0, 0.0), 0.0),
+        ];
+        let bad = [
+            TrajectoryLimits {
+                max_speed: 0.0,
+                ..Default::default()
+            },
+            TrajectoryLimits {
+                max_acceleration: -1.0,
+                ..Default::default()
+            },
+            TrajectoryLimits {
+                max_yaw_rate: f64::NAN,
+                ..Default::default()
+            },
+            // Fine on its own, but takes longer than a `Duration` can hold
+            TrajectoryLimits {
+                max_speed: 1e-300,
+                ..Default::default()
+            },
+        ];
+        for limits in bad {
+            assert!(Trajectory::through(&waypoints, limits, Smoothness::Snap).is_err());
+        }
+    }
+
+    #[test]
+    fn test_overlong_times_are_caught() {
+        let waypoints = [
+            Waypoint::new(point(0.0, 0.0, 0.0), 0.0),
+            Waypoint::new(point(1.0, 0.0, 0.0), 0.0),
+            Waypoint::new(point(2.0, 0.0, 0.0), 0.0),
+        ];
+        let durations = [Duration::MAX, Duration::from_secs(1)];
+        assert!(Trajectory::new(&waypoints, &durations, Smoothness::Snap).is_err());
+
+        let trajectory = Trajectory::through(
+            &waypoints[..2],
+            TrajectoryLimits::default(),
+            Smoothness::Jerk,
+        )
+        .unwrap();
+        let setpoints = trajectory.setpoints(
+            Duration::MAX - Duration::from_secs(2),
+            Duration::from_secs(1),
+            5,
+        );
+        assert_eq!(setpoints.len(), 3);
+    }
+}
@@ -0,0 +1,222 @@
+//! # Overview
+//!
+//! Turning raw contacts into a verdict: was that a gentle touchdown, a hard
+//! landing, or did we just crash?
+//!
+//! # Classification
+//! Each new impact is judged on two things:
+//! - how fast the drone was moving into the surface
+//! - its *tilt*, the angle between the drone's up and the surface normal.
+//!   Landing on a flat roof is fine, meeting a wall side on is not.
+//!
+//! Tipping over on the landing gear is always a crash.
+
+use std::time::Duration;
+
+use crate::physics::{
+    body::RigidBody,
+    collision::{GroundContact, HitObject, ObstacleContact, ObstacleId, RESTING_SPEED},
+    state::position::Position,
+    util::vector::Vector3,
+};
+
+use super::landing_gear::{GearEvent, GearReport};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ImpactClass {
+    Touchdown,
+    HardLanding,
+    Crash,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ImpactThresholds {
+    /// Impacts at or above this speed (m/s) are hard landings
+    pub hard_landing_speed: f64,
+    /// Impacts at or above this speed (m/s) are crashes
+    pub crash_speed: f64,
+    /// Tilt from the surface normal (radians) above which any impact is a crash
+    pub max_tilt: f64,
+}
+
+impl Default for ImpactThresholds {
+    fn default() -> Self {
+        Self {
+            hard_landing_speed: 1.0,
+            crash_speed: 3.0,
+            max_tilt: 30f64.to_radians(),
+        }
+    }
+}
+
+impl ImpactThresholds {
+    pub fn classify(&self, impact_speed: f64, tilt: f64) -> ImpactClass {
+        if tilt > self.max_tilt || impact_speed >= self.crash_speed {
+            ImpactClass::Crash
+        } else if impact_speed >= self.hard_landing_speed {
+            ImpactClass::HardLanding
+        } else {
+            ImpactClass::Touchdown
+        }
+    }
+}
+
+/// What to do once a crash has happened.
+#[derive(Debug, Clone, Copy)]
+pub struct CrashPolicy {
+    /// Stop every propeller, as if the crash broke them
+    pub disable_propellers: bool,
+    /// Finish the run, batch runs usually want this so a crash is a fail
+    pub end_run: bool,
+}
+
+impl Default for CrashPolicy {
+    fn default() -> Self {
+        Self {
+            disable_propellers: true,
+            end_run: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ImpactEvent {
+    /// Simulation time of the impact
+    pub time: Duration,
+    pub class: ImpactClass,
+    pub object: HitObject,
+    pub position: Position,
+    pub impact_speed: f64,
+    /// Angle between the drone's up and the surface normal (radians)
+    pub tilt: f64,
+}
+
+/// Watches contacts from step to step, reporting each *new* impact once
+/// rather than every step the drone is sat on the ground.
+#[derive(Debug, Clone, Default)]
+pub struct CrashDetector {
+    pub thresholds: ImpactThresholds,
+    pub policy: CrashPolicy,
+    on_ground: bool,
+    touching: Vec<ObstacleId>,
+    crashed: bool,
+}
+
+impl CrashDetector {
+    pub fn new(thresholds: ImpactThresholds, policy: CrashPolicy) -> Self {
+        Self {
+            thresholds,
+            policy,
+            ..Default::default()
+        }
+    }
+
+    pub fn crashed(&self) -> bool {
+        self.crashed
+    }
+
+    /// Classifies the impacts from the latest step.
+    pub fn update(
+        &mut self,
+        time: Duration,
+        body: &RigidBody,
+        ground: Option<&GroundContact>,
+        gear: Option<&GearReport>,
+        obstacles: &[ObstacleContact],
+    ) -> Vec<ImpactEvent> {
+        let up = body.orientation.rotate(Vector3::new(0.0, 1.0, 0.0));
+        let tilt = |normal: Vector3| up.dot(&normal).clamp(-1.0, 1.0).acos();
+        let mut events = Vec::new();
+        let mut impact = |class: Option<ImpactClass>, object, position, speed, normal| {
+            let tilt = tilt(normal);
+            events.push(ImpactEvent {
+                time,
+                class: class.unwrap_or_else(|| self.thresholds.classify(speed, tilt)),
+                object,
+                position,
+                impact_speed: speed,
+                tilt,
+            });
+        };
+
+        let gear_events = gear.map_or(&[][..], |report| &report.events[..]);
+        let was_on_ground = self.on_ground;
+        for event in gear_events {
+            // The gear tells us when but not how hard, so use how fast the
+            // whole body was coming down
+            let normal = Vector3::new(0.0, 1.0, 0.0);
+            let speed = (-body.linear_velocity.dot(&normal)).max(0.0);
+            let class = match event {
+                GearEvent::Touchdown => None,
+                GearEvent::TipOver => Some(ImpactClass::Crash),
+            };
+            impact(class, HitObject::Ground, body.position, speed, normal);
+        }
+        let gear_on_ground =
+            gear.is_some_and(|report| report.legs.iter().any(|leg| leg.in_contact));
+
+        if let Some(contact) = ground {
+            if (!was_on_ground && !gear_on_ground) || contact.impact_speed >= RESTING_SPEED {
+                impact(
+                    None,
+                    HitObject::Ground,
+                    contact.point,
+                    contact.impact_speed,
+                    contact.normal,
+                );
+            }
+        }
+        self.on_ground = ground.is_some() || gear_on_ground;
+
+        for contact in obstacles {
+            if !self.touching.contains(&contact.obstacle) || contact.impact_speed >= RESTING_SPEED {
+                impact(
+                    None,
+                    HitObject::Obstacle(contact.obstacle),
+                    contact.point,
+                    contact.impact_speed,
+                    contact.normal,
+                );
+            }
+        }
+        self.touching = obstacles.iter().map(|contact| contact.obstacle).collect();
+
+        self.crashed |= events.iter().any(|event| event.class == ImpactClass::Crash);
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::collision::ContactState;
+
+    fn ground_contact(impact_speed: f64) -> GroundContact {
+        GroundContact {
+            point: Position::default(),
+            normal: Vector3::new(0.0, 1.0, 0.0),
+            penetration: 0.01,
+            impact_speed,
+            state: ContactState::Landed,
+        }
+    }
+
+    #[test]
+    fn test_impacts_are_reported_once_and_classified() {
+        let mut detector = CrashDetector::default();
+        let body = RigidBody::default();
+
+        let events = detector.update(Duration::ZERO, &body, Some(&ground_contact(1.5)), None, &[]);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].class, ImpactClass::HardLanding);
+
+        // Still sat on the ground, nothing new happened
+        let events = detector.update(Duration::ZERO, &body, Some(&ground_contact(0.0)), None, &[]);
+        assert!(events.is_empty());
+        assert!(!detector.crashed());
+
+        let events = detector.update(Duration::ZERO, &body, Some(&ground_contact(4.0)), None, &[]);
+        assert_eq!(events[0].class, ImpactClass::Crash);
+        assert!(detector.crashed());
+    }
+}
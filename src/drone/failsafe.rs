@@ -0,0 +1,326 @@
+use std::time::Duration;
+
+use super::{FlightMode, FlightModes};
+
+/// What can go wrong badly enough to take over from the pilot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailsafeTrigger {
+    LowBattery,
+    /// Nothing heard from the ground for longer than the timeout
+    LostLink,
+    GeofenceBreach,
+    /// We no longer know where we are
+    EstimatorFailure,
+}
+
+impl FailsafeTrigger {
+    pub const ALL: [FailsafeTrigger; 4] = [
+        FailsafeTrigger::LowBattery,
+        FailsafeTrigger::LostLink,
+        FailsafeTrigger::GeofenceBreach,
+        FailsafeTrigger::EstimatorFailure,
+    ];
+}
+
+/// What to do about it, from least to most drastic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FailsafeAction {
+    /// Only report it
+    Warn,
+    /// Stop and hover where we are
+    Hold,
+    ReturnToLaunch,
+    /// Come down where we are
+    Land,
+    /// Stop the motors, we'd rather fall than fly on
+    Kill,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FailsafeConfig {
+    /// Battery charge (fraction of full) at or below which it counts as low
+    pub battery_threshold: f64,
+    pub link_timeout: Duration,
+    pub low_battery: FailsafeAction,
+    pub lost_link: FailsafeAction,
+    pub geofence_breach: FailsafeAction,
+    pub estimator_failure: FailsafeAction,
+}
+
+impl Default for FailsafeConfig {
+    fn default() -> Self {
+        Self {
+            battery_threshold: 0.2,
+            link_timeout: Duration::from_secs(3),
+            low_battery: FailsafeAction::ReturnToLaunch,
+            lost_link: FailsafeAction::ReturnToLaunch,
+            geofence_breach: FailsafeAction::ReturnToLaunch,
+            // Going home or holding still both need to know where we are
+            estimator_failure: FailsafeAction::Land,
+        }
+    }
+}
+
+impl FailsafeConfig {
+    pub fn action(&self, trigger: FailsafeTrigger) -> FailsafeAction {
+        match trigger {
+            FailsafeTrigger::LowBattery => self.low_battery,
+            FailsafeTrigger::LostLink => self.lost_link,
+            FailsafeTrigger::GeofenceBreach => self.geofence_breach,
+            FailsafeTrigger::EstimatorFailure => self.estimator_failure,
+        }
+    }
+}
+
+/// The state of everything the failsafes watch, for one step.
+#[derive(Debug, Clone, Copy)]
+pub struct FailsafeInputs {
+    /// Battery charge as a fraction of full
+    pub battery: f64,
+    pub since_last_command: Duration,
+    /// e.g. from [`Geofences::is_breached`](super::Geofences::is_breached)
+    pub geofence_breached: bool,
+    pub estimator_healthy: bool,
+}
+
+impl Default for FailsafeInputs {
+    /// All well.
+    fn default() -> Self {
+        Self {
+            battery: 1.0,
+            since_last_command: Duration::ZERO,
+            geofence_breached: false,
+            estimator_healthy: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FailsafeEvent {
+    pub trigger: FailsafeTrigger,
+    pub action: FailsafeAction,
+    /// `false` if a more drastic failsafe already had hold, or we weren't
+    /// flying
+    pub engaged: bool,
+    /// The mode we ended up in
+    pub mode: FlightMode,
+}
+
+/// # Overview
+/// Watches the [`FailsafeInputs`] every step and switches the drone's
+/// [`FlightModes`] when one of them goes bad.
+///
+/// Each trigger fires once, when it goes bad, rather than every step it
+/// stays bad, so the pilot can take back over afterwards if they think
+/// better of it. It can fire again once it has cleared.
+///
+/// Failsafes only ever escalate. While one has hold, another only takes
+/// over if its action is more drastic, a lost link mustn't turn a landing
+/// for a flat battery back into a long flight home. Once every trigger has
+/// cleared we start afresh.
+///
+/// On the ground nothing is engaged, but the events are still reported.
+/// Anything that went bad there and is still bad once we're flying fires
+/// again then, so taking off with a flat battery doesn't go unnoticed.
+#[derive(Debug, Clone, Default)]
+pub struct FailsafeMonitor {
+    pub config: FailsafeConfig,
+    active: Vec<FailsafeTrigger>,
+    engaged: Option<FailsafeAction>,
+    /// Went bad while we weren't flying, waiting for us to be
+    deferred: Vec<FailsafeTrigger>,
+}
+
+impl FailsafeMonitor {
+    pub fn new(config: FailsafeConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    /// The triggers bad as of the last update.
+    pub fn active(&self) -> &[FailsafeTrigger] {
+        &self.active
+    }
+
+    /// The most drastic action taken since everything was last well.
+    pub fn engaged(&self) -> Option<FailsafeAction> {
+        self.engaged
+    }
+
+    pub fn update(
+        &mut self,
+        inputs: &FailsafeInputs,
+        modes: &mut FlightModes,
+    ) -> Vec<FailsafeEvent> {
+        let active: Vec<FailsafeTrigger> = FailsafeTrigger::ALL
+            .into_iter()
+            .filter(|&trigger| match trigger {
+                FailsafeTrigger::LowBattery => inputs.battery <= self.config.battery_threshold,
+                FailsafeTrigger::LostLink => inputs.since_last_command > self.config.link_timeout,
+                FailsafeTrigger::GeofenceBreach => inputs.geofence_breached,
+                FailsafeTrigger::EstimatorFailure => !inputs.estimator_healthy,
+            })
+            .collect();
+        if active.is_empty() {
+            self.engaged = None;
+        }
+
+        let mut new: Vec<FailsafeTrigger> = active
+            .iter()
+            .copied()
+            .filter(|trigger| !self.active.contains(trigger))
+            .collect();
+        self.deferred.retain(|trigger| active.contains(trigger));
+        if modes.current().is_flying() {
+            new.append(&mut self.deferred);
+        }
+        self.active = active;
+        // Most drastic first, so the rest see it's already in hand
+        new.sort_by_key(|&trigger| std::cmp::Reverse(self.config.action(trigger)));
+
+        new.into_iter()
+            .map(|trigger| {
+                let action = self.config.action(trigger);
+                let engaged = action > FailsafeAction::Warn
+                    && modes.current().is_flying()
+                    && self.engaged.is_none_or(|engaged| action > engaged)
+                    && take(action, modes);
+                if engaged {
+                    self.engaged = Some(action);
+                } else if action > FailsafeAction::Warn && !modes.current().is_flying() {
+                    self.deferred.push(trigger);
+                }
+                FailsafeEvent {
+                    trigger,
+                    action,
+                    engaged,
+                    mode: modes.current(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Switches `modes` for `action`, `false` if it wouldn't.
+fn take(action: FailsafeAction, modes: &mut FlightModes) -> bool {
+    let mode = match action {
+        FailsafeAction::Warn => return false,
+        FailsafeAction::Hold => FlightMode::PositionHold,
+        FailsafeAction::ReturnToLaunch => FlightMode::ReturnToLaunch,
+        FailsafeAction::Land => FlightMode::Land,
+        FailsafeAction::Kill => {
+            modes.kill();
+            return true;
+        }
+    };
+    modes.request(mode).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        control::ControlCommand,
+        drone::Drone,
+        physics::{body::RigidBody, util::vector::Vector3},
+    };
+
+    #[test]
+    fn test_failsafes_escalate() {
+        let mut modes = FlightModes::default();
+        let mut monitor = FailsafeMonitor::default();
+        let mut inputs = FailsafeInputs {
+            since_last_command: Duration::from_secs(5),
+            ..Default::default()
+        };
+
+        // On the ground only reported
+        let events = monitor.update(&inputs, &mut modes);
+        assert!(!events[0].engaged);
+        assert_eq!(modes.current(), FlightMode::Disarmed);
+
+        inputs = FailsafeInputs::default();
+        monitor.update(&inputs, &mut modes);
+        modes.arm(&RigidBody::new(1.0, 1.0, 1.0), &[]).unwrap();
+        modes.request(FlightMode::Takeoff).unwrap();
+        modes.request(FlightMode::Mission).unwrap();
+
+        inputs.since_last_command = Duration::from_secs(5);
+        let events = monitor.update(&inputs, &mut modes);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].trigger, FailsafeTrigger::LostLink);
+        assert_eq!(modes.current(), FlightMode::ReturnToLaunch);
+        // Still lost, but it's already been dealt with
+        assert!(monitor.update(&inputs, &mut modes).is_empty());
+
+        // Landing beats going home
+        inputs.estimator_healthy = false;
+        let events = monitor.update(&inputs, &mut modes);
+        assert!(events[0].engaged);
+        assert_eq!(modes.current(), FlightMode::Land);
+
+        // but going home doesn't beat landing
+        inputs.battery = 0.1;
+        let events = monitor.update(&inputs, &mut modes);
+        assert_eq!(events[0].trigger, FailsafeTrigger::LowBattery);
+        assert!(!events[0].engaged);
+        assert_eq!(modes.current(), FlightMode::Land);
+    }
+
+    #[test]
+    fn test_trigger_from_the_ground_engages_once_flying() {
+        let mut modes = FlightModes::default();
+        let mut monitor = FailsafeMonitor::default();
+        let inputs = FailsafeInputs {
+            battery: 0.1,
+            ..Default::default()
+        };
+
+        let events = monitor.update(&inputs, &mut modes);
+        assert!(!events[0].engaged);
+        modes.arm(&RigidBody::new(1.0, 1.0, 1.0), &[]).unwrap();
+        // Armed on the ground still isn't flying
+        assert!(monitor.update(&inputs, &mut modes).is_empty());
+
+        modes.request(FlightMode::Takeoff).unwrap();
+        let events = monitor.update(&inputs, &mut modes);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].trigger, FailsafeTrigger::LowBattery);
+        assert!(events[0].engaged);
+        assert_eq!(modes.current(), FlightMode::ReturnToLaunch);
+        assert!(monitor.update(&inputs, &mut modes).is_empty());
+    }
+
+    #[test]
+    fn test_kill_stops_the_motors() {
+        let mut drone = Drone::default();
+        let mut monitor = FailsafeMonitor::new(FailsafeConfig {
+            geofence_breach: FailsafeAction::Kill,
+            ..Default::default()
+        });
+        drone.arm().unwrap();
+        drone.modes.request(FlightMode::PositionHold).unwrap();
+        let hover = ControlCommand {
+            thrust: 20.0,
+            ..Default::default()
+        };
+        drone.apply_command(&hover);
+        assert!(drone.propellers.iter().all(|propeller| propeller.rpm > 0));
+
+        let inputs = FailsafeInputs {
+            geofence_breached: true,
+            ..Default::default()
+        };
+        let events = monitor.update(&inputs, &mut drone.modes);
+        assert!(events[0].engaged);
+        assert_eq!(drone.modes.current(), FlightMode::Disarmed);
+
+        // Whatever the controller still asks for
+        drone.apply_command(&hover);
+        drone.update_rotor_forces();
+        assert!(drone.propellers.iter().all(|propeller| propeller.rpm == 0));
+        assert_eq!(drone.body.forces.thrust().0, Vector3::default());
+    }
+}
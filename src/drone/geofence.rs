@@ -0,0 +1,392 @@
+use crate::{
+    physics::{body::RigidBody, state::position::Position},
+    EleaError, Result,
+};
+
+/// Most points [`Geofences::check_path`] will test along one leg, any finer
+/// than this and it's quicker to pick a coarser spacing.
+const MAX_PATH_SAMPLES: f64 = 1e6;
+
+/// The outline of a fence seen from above, on the `x`/`z` ground plane.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FenceShape {
+    /// No sides, only the floor and ceiling count, e.g. a flat altitude
+    /// limit over the whole map
+    Everywhere,
+    Cylinder {
+        /// `(x, z)` (m)
+        center: (f64, f64),
+        /// m
+        radius: f64,
+    },
+    /// A prism standing on this polygon
+    Polygon {
+        /// `(x, z)` (m) corners in order, either way round
+        vertices: Vec<(f64, f64)>,
+    },
+}
+
+impl FenceShape {
+    /// Whether `(x, z)` is inside the outline.
+    fn contains(&self, x: f64, z: f64) -> bool {
+        match self {
+            FenceShape::Everywhere => true,
+            FenceShape::Cylinder { center, radius } => {
+                (x - center.0).hypot(z - center.1) <= *radius
+            }
+            // Count how many edges a line heading off along +x crosses,
+            // odd is inside
+            FenceShape::Polygon { vertices } => {
+                // The fields are public, so this might not have come
+                // through `Geofence::new`
+                let Some(&last) = vertices.last() else {
+                    return false;
+                };
+                let mut inside = false;
+                let mut previous = last;
+                for &current in vertices {
+                    let (a, b) = (current, previous);
+                    if (a.1 > z) != (b.1 > z) && x < a.0 + (z - a.1) * (b.0 - a.0) / (b.1 - a.1) {
+                        inside = !inside;
+                    }
+                    previous = current;
+                }
+                inside
+            }
+        }
+    }
+}
+
+/// Whether the drone has to stay in or keep out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FenceKind {
+    Inclusion,
+    /// A no-fly zone
+    Exclusion,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Geofence {
+    pub shape: FenceShape,
+    pub kind: FenceKind,
+    /// Lowest altitude (m, world `y`) the fence covers
+    pub floor: f64,
+    /// Highest altitude (m, world `y`) the fence covers
+    pub ceiling: f64,
+}
+
+impl Geofence {
+    /// Every number has to be finite, except that the floor may be `-∞`
+    /// and the ceiling `∞` for a fence with no bottom or top.
+    pub fn new(shape: FenceShape, kind: FenceKind, floor: f64, ceiling: f64) -> Result<Self> {
+        let finite = |(x, z): (f64, f64)| x.is_finite() && z.is_finite();
+        let valid_shape = match &shape {
+            FenceShape::Everywhere => true,
+            FenceShape::Cylinder { center, radius } => {
+                finite(*center) && radius.is_finite() && *radius > 0.0
+            }
+            FenceShape::Polygon { vertices } => {
+                vertices.len() >= 3 && vertices.iter().copied().all(finite)
+            }
+        };
+        let valid_floor = floor.is_finite() || floor == f64::NEG_INFINITY;
+        let valid_ceiling = ceiling.is_finite() || ceiling == f64::INFINITY;
+        if !valid_shape || !valid_floor || !valid_ceiling || floor >= ceiling {
+            return Err(EleaError::InvalidData(format!(
+                "geofence {shape:?} from {floor} to {ceiling} m encloses nothing"
+            )));
+        }
+        Ok(Self {
+            shape,
+            kind,
+            floor,
+            ceiling,
+        })
+    }
+
+    /// Stay below `altitude` (m) everywhere.
+    pub fn ceiling(altitude: f64) -> Result<Self> {
+        Self::new(
+            FenceShape::Everywhere,
+            FenceKind::Inclusion,
+            f64::NEG_INFINITY,
+            altitude,
+        )
+    }
+
+    /// Stay above `altitude` (m) everywhere.
+    pub fn floor(altitude: f64) -> Result<Self> {
+        Self::new(
+            FenceShape::Everywhere,
+            FenceKind::Inclusion,
+            altitude,
+            f64::INFINITY,
+        )
+    }
+
+    pub fn contains(&self, point: Position) -> bool {
+        (self.floor..=self.ceiling).contains(&point.y) && self.shape.contains(point.x, point.z)
+    }
+
+    /// Whether being at `point` breaks this fence.
+    pub fn is_breached_by(&self, point: Position) -> bool {
+        match self.kind {
+            FenceKind::Inclusion => !self.contains(point),
+            FenceKind::Exclusion => self.contains(point),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeofenceEvent {
+    /// The drone just broke fence `fence` (its index), at `position`
+    Breached { fence: usize, position: Position },
+    /// The drone is back on the right side of fence `fence`
+    Cleared { fence: usize },
+}
+
+/// # Overview
+/// All the fences for a flight, checked against where the drone is every
+/// step.
+///
+/// A drone has to be inside *every* inclusion fence and outside every
+/// exclusion fence, so e.g. a cylinder around the field with a ceiling
+/// on top is two inclusion fences, and a building in the middle is an
+/// exclusion fence.
+///
+/// Only the changes are reported as events, [`is_breached`](Self::is_breached)
+/// says whether any fence is broken right now, which is what the
+/// [`FailsafeMonitor`](super::FailsafeMonitor) wants.
+#[derive(Debug, Clone, Default)]
+pub struct Geofences {
+    fences: Vec<Geofence>,
+    breached: Vec<bool>,
+}
+
+impl Geofences {
+    pub fn new(fences: Vec<Geofence>) -> Self {
+        Self {
+            breached: vec![false; fences.len()],
+            fences,
+        }
+    }
+
+    pub fn add(&mut self, fence: Geofence) -> usize {
+        self.fences.push(fence);
+        self.breached.push(false);
+        self.fences.len() - 1
+    }
+
+    pub fn fences(&self) -> &[Geofence] {
+        &self.fences
+    }
+
+    /// Whether any fence was broken as of the last update.
+    pub fn is_breached(&self) -> bool {
+        self.breached.contains(&true)
+    }
+
+    /// Checks `body`'s position against every fence.
+    pub fn update(&mut self, body: &RigidBody) -> Vec<GeofenceEvent> {
+        let position = body.position;
+        let mut events = Vec::new();
+        for (fence, (geofence, breached)) in self.fences.iter().zip(&mut self.breached).enumerate()
+        {
+            let now = geofence.is_breached_by(position);
+            match (*breached, now) {
+                (false, true) => events.push(GeofenceEvent::Breached { fence, position }),
+                (true, false) => events.push(GeofenceEvent::Cleared { fence }),
+                _ => {}
+            }
+            *breached = now;
+        }
+        events
+    }
+
+    /// The first place along the straight lines joining `path` where a
+    /// fence would be broken, and which fence, for checking a planned
+    /// mission before flying it. Lines are checked every `spacing` (m),
+    /// so a fence thinner than that can slip between, it has to be above
+    /// zero. Errors too if a point isn't finite, or a leg would take more
+    /// than a million samples.
+    pub fn check_path(&self, path: &[Position], spacing: f64) -> Result<Option<(usize, Position)>> {
+        if spacing.is_nan() || spacing <= 0.0 {
+            return Err(EleaError::InvalidData(format!(
+                "path check spacing {spacing} m must be above zero"
+            )));
+        }
+        if path.iter().any(|point| !point.magnitude().is_finite()) {
+            return Err(EleaError::InvalidData(
+                "path points must be finite".to_string(),
+            ));
+        }
+        let samples = path
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).magnitude() / spacing)
+            .fold(0.0, f64::max);
+        if samples > MAX_PATH_SAMPLES {
+            return Err(EleaError::InvalidData(format!(
+                "a path leg needs {samples:.0} samples at {spacing} m spacing"
+            )));
+        }
+        let breach = |point: Position| {
+            self.fences
+                .iter()
+                .position(|fence| fence.is_breached_by(point))
+                .map(|fence| (fence, point))
+        };
+        if let Some(found) = path.first().copied().and_then(breach) {
+            return Ok(Some(found));
+        }
+        Ok(path.windows(2).find_map(|pair| {
+            let line = pair[1] - pair[0];
+            let steps = (line.magnitude() / spacing).ceil().max(1.0) as usize;
+            (1..=steps).find_map(|step| {
+                let fraction = step as f64 / steps as f64;
+                breach(pair[0] + Position(line.0.scalar_mul(fraction)))
+            })
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::util::vector::Vector3;
+
+    fn at(x: f64, y: f64, z: f64) -> Position {
+        Position(Vector3::new(x, y, z))
+    }
+
+    fn field() -> Geofences {
+        let boundary = FenceShape::Cylinder {
+            center: (0.0, 0.0),
+            radius: 100.0,
+        };
+        // An L shaped building
+        let building = FenceShape::Polygon {
+            vertices: vec![
+                (20.0, 20.0),
+                (40.0, 20.0),
+                (40.0, 30.0),
+                (30.0, 30.0),
+                (30.0, 50.0),
+                (20.0, 50.0),
+            ],
+        };
+        Geofences::new(vec![
+            Geofence::new(
+                boundary,
+                FenceKind::Inclusion,
+                f64::NEG_INFINITY,
+                f64::INFINITY,
+            )
+            .unwrap(),
+            Geofence::ceiling(120.0).unwrap(),
+            Geofence::new(building, FenceKind::Exclusion, 0.0, 25.0).unwrap(),
+        ])
+    }
+
+    #[test]
+    fn test_breach_events() {
+        let mut fences = field();
+        let mut body = RigidBody::new(1.0, 1.0, 1.0);
+        body.position = at(0.0, 10.0, 0.0);
+        assert!(fences.update(&body).is_empty());
+
+        // Inside the notch of the L is fine, inside the L isn't
+        body.position = at(35.0, 10.0, 40.0);
+        assert!(fences.update(&body).is_empty());
+        body.position = at(25.0, 10.0, 40.0);
+        let events = fences.update(&body);
+        assert_eq!(
+            events,
+            [GeofenceEvent::Breached {
+                fence: 2,
+                position: body.position
+            }]
+        );
+        assert!(fences.is_breached());
+
+        // Over the roof
+        body.position = at(25.0, 30.0, 40.0);
+        assert_eq!(fences.update(&body), [GeofenceEvent::Cleared { fence: 2 }]);
+        assert!(!fences.is_breached());
+
+        body.position = at(0.0, 130.0, 0.0);
+        assert_eq!(fences.update(&body).len(), 1);
+        body.position = at(90.0, 10.0, 90.0);
+        assert_eq!(fences.update(&body).len(), 2);
+    }
+
+    #[test]
+    fn test_check_path() {
+        let fences = field();
+        let over = [at(0.0, 10.0, 0.0), at(0.0, 30.0, 0.0), at(50.0, 30.0, 50.0)];
+        assert_eq!(fences.check_path(&over, 1.0).unwrap(), None);
+
+        let through = [at(0.0, 10.0, 0.0), at(50.0, 10.0, 50.0)];
+        let (fence, point) = fences.check_path(&through, 1.0).unwrap().unwrap();
+        assert_eq!(fence, 2);
+        assert!((point.x - 20.0).abs() < 1.0);
+
+        assert!(fences.check_path(&through, 0.0).is_err());
+        assert!(fences.check_path(&through, f64::NAN).is_err());
+        let far = [at(0.0, 10.0, 0.0), at(1e12, 10.0, 0.0)];
+        assert!(fences.check_path(&far, 1.0).is_err());
+        let lost = [at(0.0, 10.0, 0.0), at(f64::INFINITY, 10.0, 0.0)];
+        assert!(fences.check_path(&lost, 1.0).is_err());
+    }
+
+    #[test]
+    fn test_polygon_without_vertices_contains_nothing() {
+        let mut fence = Geofence::ceiling(120.0).unwrap();
+        fence.shape = FenceShape::Polygon {
+            vertices: Vec::new(),
+        };
+        let mut fences = Geofences::new(vec![fence]);
+        let body = RigidBody::new(1.0, 1.0, 1.0);
+        assert_eq!(fences.update(&body).len(), 1);
+    }
+
+    #[test]
+    fn test_simulator_reports_breaches() {
+        let mut simulator = crate::DroneSimulator::new();
+        simulator.geofences.add(Geofence::ceiling(5.0).unwrap());
+        simulator.drone.body.position.y = 10.0;
+        simulator.advance().unwrap();
+
+        assert!(simulator.geofences.is_breached());
+        assert!(matches!(
+            simulator.geofence_events(),
+            [GeofenceEvent::Breached { fence: 0, .. }]
+        ));
+    }
+
+    #[test]
+    fn test_rejects_bad_numbers() {
+        let everywhere = |floor, ceiling| {
+            Geofence::new(FenceShape::Everywhere, FenceKind::Inclusion, floor, ceiling)
+        };
+        assert!(everywhere(f64::NEG_INFINITY, f64::INFINITY).is_ok());
+        assert!(everywhere(f64::NAN, 10.0).is_err());
+        assert!(everywhere(0.0, f64::NAN).is_err());
+        assert!(everywhere(f64::INFINITY, f64::INFINITY).is_err());
+        assert!(everywhere(f64::NEG_INFINITY, f64::NEG_INFINITY).is_err());
+
+        let cylinder = |center, radius| {
+            Geofence::new(
+                FenceShape::Cylinder { center, radius },
+                FenceKind::Exclusion,
+                0.0,
+                10.0,
+            )
+        };
+        assert!(cylinder((0.0, f64::NAN), 5.0).is_err());
+        assert!(cylinder((0.0, 0.0), f64::INFINITY).is_err());
+        let polygon = FenceShape::Polygon {
+            vertices: vec![(0.0, 0.0), (1.0, 0.0), (f64::NAN, 1.0)],
+        };
+        assert!(Geofence::new(polygon, FenceKind::Exclusion, 0.0, 10.0).is_err());
+    }
+}
@@ -0,0 +1,227 @@
+//! # Overview
+//!
+//! Landing gear legs (or skid ends) modelled as contact points fixed to the
+//! drone's body, each pushing back on the ground with a spring-damper.
+//!
+//! Unlike the rigid [`Ground`] impulse response the legs compress, so a
+//! touchdown has a realistic bounce and the drone can rock or tip over onto
+//! its side when landing hard or on a slope.
+
+use crate::physics::{
+    body::RigidBody,
+    collision::{Ground, Surface},
+    force::ForceVector,
+    state::position::Position,
+    util::vector::Vector3,
+};
+
+/// Slip speed (m/s) at which friction reaches its full Coulomb value.
+/// Smoothing the jump from static to sliding friction keeps the legs from
+/// chattering back and forth at our timestep.
+const SLIP_VELOCITY: f64 = 0.05;
+
+/// # Overview
+/// One leg of the landing gear, its foot is at `offset` from the center of
+/// mass in the body frame.
+///
+/// # Normal force
+/// With `d` how far the foot has sunk below the ground and `v` the speed
+/// it is moving into it:
+///
+/// `F = k * d + c * v` (never negative, the ground can only push)
+///
+/// where `k` is the `stiffness` (N/m) and `c` the `damping` (N⋅s/m).
+#[derive(Debug, Clone, Copy)]
+pub struct GearLeg {
+    pub offset: Vector3,
+    pub stiffness: f64,
+    pub damping: f64,
+    pub friction: f64,
+}
+
+/// What a single leg was doing during the last step.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LegContact {
+    pub in_contact: bool,
+    /// How far the foot is below the ground (m)
+    pub compression: f64,
+    /// Force along the ground normal (N)
+    pub normal_force: f64,
+    /// Friction force along the ground, world frame (N)
+    pub friction_force: Vector3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GearEvent {
+    /// The first leg touched the ground after all legs were in the air
+    Touchdown,
+    /// The body leant past the tip over angle while on the ground
+    TipOver,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GearReport {
+    /// One entry per leg, in the same order as [`LandingGear::legs`]
+    pub legs: Vec<LegContact>,
+    pub events: Vec<GearEvent>,
+}
+
+impl GearReport {
+    pub fn total_normal_force(&self) -> f64 {
+        self.legs.iter().map(|leg| leg.normal_force).sum()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LandingGear {
+    pub legs: Vec<GearLeg>,
+    /// Tilt from upright (radians) past which the drone counts as tipped over
+    pub tip_over_angle: f64,
+    on_ground: bool,
+    tipped_over: bool,
+}
+
+impl LandingGear {
+    pub fn new(legs: Vec<GearLeg>, tip_over_angle: f64) -> Self {
+        Self {
+            legs,
+            tip_over_angle,
+            on_ground: false,
+            tipped_over: false,
+        }
+    }
+
+    /// Four identical legs on the corners of a square `span` wide (m),
+    /// with their feet `drop` metres below the center of mass.
+    pub fn quad(span: f64, drop: f64, stiffness: f64, damping: f64, friction: f64) -> Self {
+        let half = span / 2.0;
+        let legs = [(1.0, 1.0), (1.0, -1.0), (-1.0, -1.0), (-1.0, 1.0)]
+            .map(|(x, z)| GearLeg {
+                offset: Vector3::new(x * half, -drop, z * half),
+                stiffness,
+                damping,
+                friction,
+            })
+            .to_vec();
+        // Tips once the center of mass is past the line between two feet
+        Self::new(legs, half.atan2(drop))
+    }
+
+    /// Whether the drone is lying tipped over, clears once it's back
+    /// within the tip over angle.
+    pub fn tipped_over(&self) -> bool {
+        self.tipped_over
+    }
+
+    /// Calculates every leg's contact force against `ground` and applies
+    /// them to `body` for its next step.
+    pub fn apply(&mut self, body: &mut RigidBody, ground: &Ground) -> GearReport {
+        let mut report = GearReport::default();
+
+        for leg in &self.legs {
+            let foot = Position(body.position.0 + body.orientation.rotate(leg.offset));
+            let normal = ground.terrain.normal_at(foot.x, foot.z);
+            // How far below the surface the foot is, measured along the normal
+            let compression = -ground.terrain.altitude_above(foot) * normal.y;
+            if compression <= 0.0 {
+                report.legs.push(LegContact::default());
+                continue;
+            }
+
+            let velocity = body.velocity_at(foot).0;
+            let approach = -velocity.dot(&normal);
+            let normal_force = (leg.stiffness * compression + leg.damping * approach).max(0.0);
+
+            let tangential = velocity + normal.scalar_mul(approach);
+            let slip = tangential.magnitude();
+            let friction_force = match slip > f64::EPSILON {
+                true => tangential.scalar_mul(
+                    -leg.friction * normal_force * (slip / SLIP_VELOCITY).tanh() / slip,
+                ),
+                false => Vector3::default(),
+            };
+
+            body.apply_force_at(
+                ForceVector(normal.scalar_mul(normal_force) + friction_force),
+                foot,
+            );
+            report.legs.push(LegContact {
+                in_contact: true,
+                compression,
+                normal_force,
+                friction_force,
+            });
+        }
+
+        let on_ground = report.legs.iter().any(|leg| leg.in_contact);
+        if on_ground && !self.on_ground {
+            report.events.push(GearEvent::Touchdown);
+        }
+        self.on_ground = on_ground;
+
+        // Tip over is judged against the local ground, leaning into a slope
+        // you're standing on square is fine
+        let normal = ground.terrain.normal_at(body.position.x, body.position.z);
+        let up = body.orientation.rotate(Vector3::new(0.0, 1.0, 0.0));
+        let tilt = up.dot(&normal).clamp(-1.0, 1.0).acos();
+        if on_ground && !self.tipped_over && tilt > self.tip_over_angle {
+            self.tipped_over = true;
+            report.events.push(GearEvent::TipOver);
+        } else if tilt <= self.tip_over_angle {
+            self.tipped_over = false;
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::physics::{math::Quaternion, state::linear_velocity::LinearVelocity};
+
+    #[test]
+    fn test_gear_settles_carrying_the_weight() {
+        let ground = Ground::default();
+        let mut gear = LandingGear::quad(0.4, 0.15, 2000.0, 60.0, 0.8);
+        let mut body = RigidBody::new(0.1, 0.3, 0.3);
+        body.mass = 1.5;
+        body.position.y = 0.5;
+
+        let mut report = GearReport::default();
+        let mut touchdowns = 0;
+        for _ in 0..400 {
+            report = gear.apply(&mut body, &ground);
+            touchdowns += report
+                .events
+                .iter()
+                .filter(|event| **event == GearEvent::Touchdown)
+                .count();
+            body.step(Duration::from_millis(5), LinearVelocity::default())
+                .unwrap();
+        }
+
+        assert!(touchdowns >= 1);
+        assert!(!gear.tipped_over());
+        assert!((report.total_normal_force() - 1.5 * 9.81).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_tip_over_clears_once_upright() {
+        let ground = Ground::default();
+        let mut gear = LandingGear::quad(0.4, 0.15, 2000.0, 60.0, 0.8);
+        let mut body = RigidBody::new(0.1, 0.3, 0.3);
+        body.position.y = 0.1;
+
+        *body.orientation = Quaternion::new(90.0, 1.0, 0.0, 0.0);
+        let report = gear.apply(&mut body, &ground);
+        assert!(report.events.contains(&GearEvent::TipOver));
+        assert!(gear.tipped_over());
+
+        *body.orientation = Quaternion::default();
+        gear.apply(&mut body, &ground);
+        assert!(!gear.tipped_over());
+    }
+}
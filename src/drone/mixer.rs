@@ -0,0 +1,191 @@
+//! # Overview
+//!
+//! The mixer turns what the flight controller wants, a collective thrust
+//! and a torque about each body axis, into a speed for each rotor. It also
+//! goes the other way, from the rotor speeds to the thrust and torque they
+//! actually produce.
+//!
+//! # Layout
+//! Rotors sit in an `X` in the body's `x`/`z` plane, `arm_length` from the
+//! center, all blowing down so thrust is along body `y`:
+//!
+//! ```text
+//!        +z
+//!    1 (CCW)   0 (CW)
+//!          \  /
+//!           \/      +x
+//!           /\
+//!          /  \
+//!    2 (CW)    3 (CCW)
+//! ```
+//!
+//! Diagonal rotors spin the same way, so at equal speeds their reaction
+//! torques cancel out. Spinning the clockwise pair faster than the
+//! anticlockwise pair yaws the body.
+//!
+//! # Rotor model
+//! Thrust grows with the square of rotor speed, `T = k_f ω²`, and so does
+//! the reaction torque from dragging the blades round, `Q = k_m ω²`.
+
+use std::f64::consts::{FRAC_1_SQRT_2, PI};
+
+use crate::{
+    control::ControlCommand,
+    physics::{torque::Torque, util::vector::Vector3},
+};
+
+use super::propeller::{Propeller, RotationDirection};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Mixer {
+    /// Distance from the center to each rotor hub (m)
+    pub arm_length: f64,
+    /// `k_f`, thrust (N) per (rad/s)² of rotor speed
+    pub thrust_coefficient: f64,
+    /// `k_m`, reaction torque (N⋅m) per (rad/s)² of rotor speed
+    pub torque_coefficient: f64,
+    pub max_rpm: f64,
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        // Sized for the default drone, hovering at about 2/3 of max speed
+        // on rotors big enough to yaw it
+        Self {
+            arm_length: 5.0,
+            thrust_coefficient: 1e-3,
+            torque_coefficient: 2e-4,
+            max_rpm: 10_000.0,
+        }
+    }
+}
+
+impl Mixer {
+    /// The way each rotor spins, see the layout above.
+    pub const DIRECTIONS: [RotationDirection; 4] = [
+        RotationDirection::Clockwise,
+        RotationDirection::CounterClockwise,
+        RotationDirection::Clockwise,
+        RotationDirection::CounterClockwise,
+    ];
+
+    /// Where rotor `index` sits in the body frame.
+    pub fn rotor_position(&self, index: usize) -> Vector3 {
+        let a = self.arm_length * FRAC_1_SQRT_2;
+        let (x, z) = [(a, a), (-a, a), (-a, -a), (a, -a)][index % 4];
+        Vector3::new(x, 0.0, z)
+    }
+
+    pub fn rotor_thrust(&self, rpm: f64) -> f64 {
+        self.thrust_coefficient * rpm_to_rad_per_sec(rpm).powi(2)
+    }
+
+    pub fn rpm_for_thrust(&self, thrust: f64) -> f64 {
+        (thrust.max(0.0) / self.thrust_coefficient).sqrt() * 60.0 / (2.0 * PI)
+    }
+
+    pub fn max_rotor_thrust(&self) -> f64 {
+        self.rotor_thrust(self.max_rpm)
+    }
+
+    /// Thrust (N) each rotor needs for `command`, within what the rotors
+    /// can actually give.
+    ///
+    /// Each row of the mixing matrix (thrust, roll, yaw, pitch) is
+    /// orthogonal to the others for this layout, so inverting it is just
+    /// projecting onto each row in turn.
+    pub fn mix(&self, command: &ControlCommand) -> [f64; 4] {
+        let torque = command.torque.0;
+        let wanted = [command.thrust, torque.x, torque.y, torque.z];
+        self.allocation().map(|row| {
+            let thrust: f64 = row.iter().zip(wanted).map(|(k, value)| k * value).sum();
+            thrust.clamp(0.0, self.max_rotor_thrust())
+        })
+    }
+
+    /// The linear map behind [`Mixer::mix`], before clamping. Row `i` gives
+    /// rotor `i`'s thrust from the collective thrust and the `x`, `y` and
+    /// `z` torques, in that order.
+    pub fn allocation(&self) -> [[f64; 4]; 4] {
+        let a = self.arm_length * FRAC_1_SQRT_2;
+        let drag_ratio = self.torque_coefficient / self.thrust_coefficient;
+        std::array::from_fn(|i| {
+            let r = self.rotor_position(i);
+            [
+                0.25,
+                -r.z / (4.0 * a * a),
+                spin(Self::DIRECTIONS[i]) / (4.0 * drag_ratio),
+                r.x / (4.0 * a * a),
+            ]
+        })
+    }
+
+    /// Rotor speeds for `command`, see [`Mixer::mix`].
+    pub fn rpms(&self, command: &ControlCommand) -> [f64; 4] {
+        self.mix(command).map(|thrust| self.rpm_for_thrust(thrust))
+    }
+
+    /// The collective thrust (N, along body `y`) and body frame torque the
+    /// propellers produce spinning as they are. Failed propellers give
+    /// nothing.
+    pub fn forces(&self, propellers: &[Propeller; 4]) -> ControlCommand {
+        let drag_ratio = self.torque_coefficient / self.thrust_coefficient;
+        propellers
+            .iter()
+            .enumerate()
+            .fold(ControlCommand::default(), |total, (i, propeller)| {
+                let thrust = match propeller.failed {
+                    true => 0.0,
+                    false => self.rotor_thrust(propeller.rpm as f64),
+                };
+                let lift = Vector3::new(0.0, thrust, 0.0);
+                let reaction = Vector3::new(
+                    0.0,
+                    spin(propeller.rotation_direction) * drag_ratio * thrust,
+                    0.0,
+                );
+                ControlCommand {
+                    thrust: total.thrust + thrust,
+                    torque: total.torque + Torque(self.rotor_position(i).cross(&lift) + reaction),
+                }
+            })
+    }
+}
+
+/// Sign of the yaw reaction torque. A rotor spinning clockwise seen from
+/// above pushes the body round anticlockwise, which is `+y`.
+fn spin(direction: RotationDirection) -> f64 {
+    match direction {
+        RotationDirection::Clockwise => 1.0,
+        RotationDirection::CounterClockwise => -1.0,
+    }
+}
+
+fn rpm_to_rad_per_sec(rpm: f64) -> f64 {
+    rpm * 2.0 * PI / 60.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mix_round_trips_through_rotor_forces() {
+        let mixer = Mixer::default();
+        let command = ControlCommand {
+            thrust: 2000.0,
+            torque: Torque(Vector3::new(300.0, 5.0, -200.0)),
+        };
+        let mut propellers = Mixer::DIRECTIONS.map(|rotation_direction| Propeller {
+            rotation_direction,
+            ..Default::default()
+        });
+        for (propeller, rpm) in propellers.iter_mut().zip(mixer.rpms(&command)) {
+            propeller.rpm = rpm.round() as usize;
+        }
+
+        let produced = mixer.forces(&propellers);
+        assert!((produced.thrust - command.thrust).abs() < 2.0);
+        assert!((produced.torque - command.torque).magnitude() < 2.0);
+    }
+}
@@ -18,22 +18,87 @@
 //! If we are even slightly slower than actual time in the simulation (due to updates taking *n+1, where *n* is the timestep)
 //! we will fall behind actual real-world time more and more, the longer it goes on.
 //! There are some solutions like frame skipping but that is for a later date. TODO review this!
+pub mod crash;
+pub mod failsafe;
+pub mod geofence;
+pub mod landing_gear;
+pub mod mixer;
+pub mod mode;
 mod propeller;
-pub use propeller::Propeller;
+pub use crash::{CrashDetector, CrashPolicy, ImpactClass, ImpactEvent, ImpactThresholds};
+pub use failsafe::{
+    FailsafeAction, FailsafeConfig, FailsafeEvent, FailsafeInputs, FailsafeMonitor, FailsafeTrigger,
+};
+pub use geofence::{FenceKind, FenceShape, Geofence, GeofenceEvent, Geofences};
+pub use landing_gear::{GearEvent, GearReport, LandingGear};
+pub use mixer::Mixer;
+pub use mode::{FlightMode, FlightModes, ModeEvent};
+pub use propeller::{Propeller, RotationDirection};
 
-use crate::physics::body::RigidBody;
+use crate::{
+    control::ControlCommand,
+    physics::{body::RigidBody, force::ForceVector, util::vector::Vector3},
+};
 
 #[derive(Debug)]
 pub struct Drone {
     pub body: RigidBody,
     pub propellers: [Propeller; 4],
+    pub mixer: Mixer,
+    /// Without landing gear the body's cuboid rests straight on the ground.
+    pub landing_gear: Option<LandingGear>,
+    /// Starts out disarmed
+    pub modes: FlightModes,
 }
 
 impl Default for Drone {
     fn default() -> Self {
         Self {
             body: RigidBody::new(10.0, 10.0, 10.0),
-            propellers: [Propeller::default(); 4],
+            propellers: Mixer::DIRECTIONS.map(|rotation_direction| Propeller {
+                rotation_direction,
+                ..Default::default()
+            }),
+            mixer: Mixer::default(),
+            landing_gear: None,
+            modes: FlightModes::default(),
         }
     }
 }
+
+impl Drone {
+    /// Arms the motors if the drone passes the pre-flight checks, see
+    /// [`FlightModes::arm`].
+    pub fn arm(&mut self) -> crate::Result<()> {
+        self.modes.arm(&self.body, &self.propellers)
+    }
+
+    /// Spins each working propeller up (or down) to the speed the mixer
+    /// works out for `command`. Disarmed, killed included, they all stop
+    /// whatever the command.
+    pub fn apply_command(&mut self, command: &ControlCommand) {
+        let armed = self.modes.is_armed();
+        let rpms = self.mixer.rpms(command);
+        for (propeller, rpm) in self.propellers.iter_mut().zip(rpms) {
+            if !propeller.failed {
+                propeller.rpm = match armed {
+                    true => rpm.round() as usize,
+                    false => 0,
+                };
+            }
+        }
+    }
+
+    /// Sets the body's thrust and torque from how the propellers are
+    /// spinning right now. Thrust points along the body's up, so this has
+    /// to happen every step as the body turns.
+    pub fn update_rotor_forces(&mut self) {
+        let produced = self.mixer.forces(&self.propellers);
+        let thrust = self
+            .body
+            .orientation
+            .rotate(Vector3::new(0.0, produced.thrust, 0.0));
+        self.body.forces.set_thrust(ForceVector(thrust));
+        self.body.torque = produced.torque;
+    }
+}
@@ -0,0 +1,259 @@
+use crate::{
+    physics::{body::RigidBody, state::position::Position, util::vector::Vector3},
+    EleaError, Result,
+};
+
+use super::Propeller;
+
+/// Furthest (rad) from upright the drone may be and still arm.
+const MAX_ARMING_TILT: f64 = 30.0 * std::f64::consts::PI / 180.0;
+/// Fastest (m/s) the drone may be moving and still arm.
+const MAX_ARMING_SPEED: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FlightMode {
+    /// Motors stopped, the only safe mode to handle the drone in
+    #[default]
+    Disarmed,
+    /// Motors live but idle on the ground, waiting for a mode to fly in
+    Armed,
+    /// Climbing away after arming
+    Takeoff,
+    /// The pilot flies the attitude and thrust directly
+    Stabilized,
+    /// The pilot flies the attitude, altitude is held
+    AltitudeHold,
+    /// Hovering in place
+    PositionHold,
+    /// Flying a [`MissionExecutor`](crate::control::MissionExecutor)
+    Mission,
+    /// Coming straight down to land
+    Land,
+    /// Flying home to where we armed, then landing there
+    ReturnToLaunch,
+}
+
+impl FlightMode {
+    /// Off the ground, or trying to be.
+    pub fn is_flying(self) -> bool {
+        !matches!(self, FlightMode::Disarmed | FlightMode::Armed)
+    }
+}
+
+/// Things that happen in flight which move us on to the next mode without
+/// being asked, reported by whatever is flying the current mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModeEvent {
+    TakeoffComplete,
+    MissionComplete,
+    ArrivedHome,
+    Landed,
+}
+
+/// # Overview
+/// Which [`FlightMode`] the drone is in and the rules for moving between
+/// them.
+///
+/// ```text
+/// Disarmed ⇄ Armed → Takeoff → flying modes ⇄ Land → Disarmed
+/// ```
+///
+/// - Arming only happens from `Disarmed`, and only with every propeller
+///   working and the drone near enough upright and still.
+/// - From `Armed` we can take off, or go straight into any flying mode
+///   except landing or going home, neither of which we'd need yet.
+/// - In the air the flying modes switch freely between one another, so a
+///   pilot can always take back over.
+/// - Disarming by hand is only allowed from `Armed`, not in the air.
+///   Landing disarms by itself once it's down, and [`kill`](Self::kill)
+///   stops the motors whatever the mode, for emergencies only.
+///
+/// Nothing here flies the drone, the controllers for each mode report back
+/// through [`ModeEvent`]s, e.g. `ReturnToLaunch` switches to `Land` once
+/// [`ModeEvent::ArrivedHome`] comes in.
+#[derive(Debug, Clone, Default)]
+pub struct FlightModes {
+    current: FlightMode,
+    previous: FlightMode,
+    home: Option<Position>,
+}
+
+impl FlightModes {
+    pub fn current(&self) -> FlightMode {
+        self.current
+    }
+
+    /// The mode before the last change.
+    pub fn previous(&self) -> FlightMode {
+        self.previous
+    }
+
+    /// Where the drone was when it last armed.
+    pub fn home(&self) -> Option<Position> {
+        self.home
+    }
+
+    pub fn is_armed(&self) -> bool {
+        self.current != FlightMode::Disarmed
+    }
+
+    /// Arms the motors, if `body` and `propellers` pass the pre-flight
+    /// checks, and remembers where we are as home.
+    pub fn arm(&mut self, body: &RigidBody, propellers: &[Propeller]) -> Result<()> {
+        if self.current != FlightMode::Disarmed {
+            return Err(refused("already armed"));
+        }
+        if propellers.iter().any(|propeller| propeller.failed) {
+            return Err(refused("a propeller has failed"));
+        }
+        let up = body.orientation.rotate(Vector3::new(0.0, 1.0, 0.0));
+        if up.y.clamp(-1.0, 1.0).acos() > MAX_ARMING_TILT {
+            return Err(refused("not upright"));
+        }
+        if body.linear_velocity.magnitude() > MAX_ARMING_SPEED {
+            return Err(refused("still moving"));
+        }
+        self.home = Some(body.position);
+        self.switch(FlightMode::Armed);
+        Ok(())
+    }
+
+    /// Stops the motors, only on the ground in `Armed`.
+    pub fn disarm(&mut self) -> Result<()> {
+        match self.current {
+            FlightMode::Disarmed => Ok(()),
+            FlightMode::Armed => {
+                self.switch(FlightMode::Disarmed);
+                Ok(())
+            }
+            mode => Err(refused(&format!("can't disarm in the air in {mode:?}"))),
+        }
+    }
+
+    /// Stops the motors now, whatever mode we're in, even in the air.
+    pub fn kill(&mut self) {
+        self.switch(FlightMode::Disarmed);
+    }
+
+    /// Switches to `mode` if it's allowed from the current one. Use
+    /// [`arm`](Self::arm) and [`disarm`](Self::disarm) for those two.
+    pub fn request(&mut self, mode: FlightMode) -> Result<()> {
+        use FlightMode::*;
+
+        if mode == self.current {
+            return Ok(());
+        }
+        let allowed = match (self.current, mode) {
+            (_, Disarmed | Armed) => false,
+            (Disarmed, _) => false,
+            (Armed, Land | ReturnToLaunch) => false,
+            (Armed, _) => true,
+            // Once up there's no taking off again
+            (_, Takeoff) => false,
+            (_, _) => true,
+        };
+        match allowed {
+            true => {
+                self.switch(mode);
+                Ok(())
+            }
+            false => Err(refused(&format!("{:?} to {mode:?}", self.current))),
+        }
+    }
+
+    /// Moves on to whatever mode follows `event` in the current one,
+    /// returning it if the mode changed. Events that mean nothing in this
+    /// mode are ignored.
+    pub fn handle(&mut self, event: ModeEvent) -> Option<FlightMode> {
+        use FlightMode::*;
+
+        let next = match (self.current, event) {
+            (Takeoff, ModeEvent::TakeoffComplete) => PositionHold,
+            (Mission, ModeEvent::MissionComplete) => PositionHold,
+            (ReturnToLaunch, ModeEvent::ArrivedHome) => Land,
+            (Land | Mission | ReturnToLaunch, ModeEvent::Landed) => Disarmed,
+            _ => return None,
+        };
+        self.switch(next);
+        Some(next)
+    }
+
+    fn switch(&mut self, mode: FlightMode) {
+        if mode != self.current {
+            self.previous = self.current;
+            self.current = mode;
+        }
+    }
+}
+
+fn refused(reason: &str) -> EleaError {
+    EleaError::InvalidData(format!("mode change refused: {reason}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{control::ControlCommand, drone::Drone, physics::math::Quaternion};
+
+    #[test]
+    fn test_arming_guards() {
+        let mut drone = Drone::default();
+        *drone.body.orientation = Quaternion::new(45.0, 1.0, 0.0, 0.0);
+        assert!(drone.arm().is_err());
+
+        *drone.body.orientation = Quaternion::default();
+        drone.propellers[2].fail();
+        assert!(drone.arm().is_err());
+
+        drone.propellers[2].failed = false;
+        drone.arm().unwrap();
+        assert_eq!(drone.modes.current(), FlightMode::Armed);
+        assert_eq!(drone.modes.home(), Some(drone.body.position));
+    }
+
+    #[test]
+    fn test_takeoff_to_return_and_land() {
+        use FlightMode::*;
+
+        let mut modes = FlightModes::default();
+        assert!(modes.request(Takeoff).is_err());
+        modes.arm(&RigidBody::new(1.0, 1.0, 1.0), &[]).unwrap();
+        assert!(modes.request(Land).is_err());
+
+        modes.request(Takeoff).unwrap();
+        assert_eq!(modes.handle(ModeEvent::TakeoffComplete), Some(PositionHold));
+        // Nothing to do with this mode
+        assert_eq!(modes.handle(ModeEvent::ArrivedHome), None);
+        assert!(modes.disarm().is_err());
+        assert!(modes.request(Takeoff).is_err());
+
+        modes.request(Mission).unwrap();
+        modes.request(ReturnToLaunch).unwrap();
+        assert_eq!(modes.previous(), Mission);
+        assert_eq!(modes.handle(ModeEvent::ArrivedHome), Some(Land));
+        assert_eq!(modes.handle(ModeEvent::Landed), Some(Disarmed));
+        assert!(!modes.is_armed());
+    }
+
+    #[test]
+    fn test_disarmed_drone_makes_no_thrust() {
+        let mut drone = Drone::default();
+        let command = ControlCommand {
+            thrust: 20.0,
+            ..Default::default()
+        };
+        drone.apply_command(&command);
+        drone.update_rotor_forces();
+        assert!(drone.propellers.iter().all(|propeller| propeller.rpm == 0));
+        assert_eq!(drone.body.forces.thrust().magnitude(), 0.0);
+
+        drone.arm().unwrap();
+        drone.apply_command(&command);
+        assert!(drone.propellers.iter().all(|propeller| propeller.rpm > 0));
+
+        // Killing stops them from the next command on
+        drone.modes.kill();
+        drone.apply_command(&command);
+        assert!(drone.propellers.iter().all(|propeller| propeller.rpm == 0));
+    }
+}
@@ -1,26 +1,21 @@
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default)]
 pub struct Propeller {
     pub rpm: usize,
     pub rotation_direction: RotationDirection,
+    /// A failed propeller no longer spins, whatever it is commanded.
+    pub failed: bool,
 }
 
-impl Default for Propeller {
-    fn default() -> Self {
-        Self {
-            rpm: 0,
-            rotation_direction: RotationDirection::default(),
-        }
+impl Propeller {
+    pub fn fail(&mut self) {
+        self.failed = true;
+        self.rpm = 0;
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default)]
 pub enum RotationDirection {
+    #[default]
     Clockwise,
     CounterClockwise,
 }
-
-impl Default for RotationDirection {
-    fn default() -> Self {
-        RotationDirection::Clockwise
-    }
-}
@@ -1,9 +1,20 @@
 use std::time::{Duration, SystemTime};
 
-use crate::drone::Drone;
+use crate::{
+    drone::{CrashDetector, Drone, GearReport, GeofenceEvent, Geofences, ImpactClass, ImpactEvent},
+    physics::{
+        body::RigidBody,
+        collision::{ContactState, Ground, ObstacleContact, Ray, RayHit, Surface, World},
+        constraint::{Tether, TetherEnd, TetherReport},
+        util::vector::Vector3,
+    },
+    weather::{Weather, WindField},
+};
 
+pub mod control;
 pub mod drone;
 pub mod physics;
+pub mod weather;
 
 mod util;
 pub use util::{EleaError, Result};
@@ -27,6 +38,57 @@ pub struct DroneSimulator {
     /// we will fall behind actual real-world time more and more, the longer it goes on.
     /// There are some solutions like frame skipping but that is for a later date. TODO review this!
     delta_time: Duration,
+
+    /// How much simulated time has passed, this is what scheduled weather
+    /// events are timed against, **not** the wall clock.
+    elapsed: Duration,
+
+    pub weather: Weather,
+
+    pub ground: Ground,
+
+    /// Static obstacles to fly around (or into).
+    pub world: World,
+
+    /// Free bodies besides the drone, e.g. a payload slung underneath it.
+    /// They feel the wind, gravity and the ground like the drone does.
+    pub bodies: Vec<RigidBody>,
+
+    /// Ropes and rods tying the drone to `bodies` or to fixed anchors.
+    pub tethers: Vec<Tether>,
+
+    /// How each of the `tethers` ended the last step.
+    tether_reports: Vec<TetherReport>,
+
+    /// Whether the drone ended the last step resting on the ground.
+    contact_state: ContactState,
+
+    /// Per leg contact forces and events from the last step, if the drone
+    /// has landing gear.
+    gear_report: Option<GearReport>,
+
+    /// Obstacles the drone hit during the last step.
+    obstacle_contacts: Vec<ObstacleContact>,
+
+    pub crash_detector: CrashDetector,
+
+    /// Every impact classified so far this run.
+    impacts: Vec<ImpactEvent>,
+
+    /// Checked against the drone's position every step.
+    pub geofences: Geofences,
+
+    /// Every fence breached or cleared so far this run.
+    geofence_events: Vec<GeofenceEvent>,
+
+    /// Set once the run should stop, e.g. a crash with an ending policy.
+    finished: bool,
+}
+
+impl Default for DroneSimulator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DroneSimulator {
@@ -34,17 +96,172 @@ impl DroneSimulator {
         Self {
             drone: Drone::default(),
             delta_time: Duration::from_millis(physics::DEFAULT_DELTATIME_MS),
+            elapsed: Duration::ZERO,
+            weather: Weather::default(),
+            ground: Ground::default(),
+            contact_state: ContactState::default(),
+            world: World::default(),
+            bodies: Vec::new(),
+            tethers: Vec::new(),
+            tether_reports: Vec::new(),
+            gear_report: None,
+            obstacle_contacts: Vec::new(),
+            crash_detector: CrashDetector::default(),
+            impacts: Vec::new(),
+            geofences: Geofences::default(),
+            geofence_events: Vec::new(),
+            finished: false,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub fn contact_state(&self) -> ContactState {
+        self.contact_state
+    }
+
+    /// Height of the drone above the terrain directly beneath it.
+    pub fn altitude_above_ground(&self) -> f64 {
+        self.ground.terrain.altitude_above(self.drone.body.position)
+    }
+
+    /// The first thing `ray` hits, ground or obstacle, within `max_distance`.
+    pub fn raycast(&self, ray: &Ray, max_distance: f64) -> Option<RayHit> {
+        RayHit::nearest(
+            self.ground.raycast(ray, max_distance),
+            self.world.raycast(ray, max_distance),
+        )
+    }
+
+    pub fn gear_report(&self) -> Option<&GearReport> {
+        self.gear_report.as_ref()
+    }
+
+    pub fn tether_reports(&self) -> &[TetherReport] {
+        &self.tether_reports
+    }
+
+    pub fn obstacle_contacts(&self) -> &[ObstacleContact] {
+        &self.obstacle_contacts
+    }
+
+    pub fn impacts(&self) -> &[ImpactEvent] {
+        &self.impacts
+    }
+
+    pub fn geofence_events(&self) -> &[GeofenceEvent] {
+        &self.geofence_events
+    }
+
+    /// The pass/fail signal for a run, did the drone crash at any point?
+    pub fn crashed(&self) -> bool {
+        self.crash_detector.crashed()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Lets go of the drone's payload `index` (see
+    /// [`RigidBody::attach`]), which carries on as a free body. Returns its
+    /// index in `bodies`, or `None` if the drone has no such payload.
+    pub fn release_payload(&mut self, index: usize) -> Option<usize> {
+        let payload = self.drone.body.release(index)?;
+        self.bodies.push(payload);
+        Some(self.bodies.len() - 1)
+    }
+
+    /// Takes free body `index` out of `bodies` and fixes it to the drone at
+    /// `offset` in the drone's body frame. Any tethers to it are cut, as
+    /// there's nothing left for them to pull on.
+    pub fn pick_up(&mut self, index: usize, offset: Vector3) -> Result<()> {
+        if index >= self.bodies.len() {
+            return Err(EleaError::InvalidData(format!(
+                "no free body {index} to pick up"
+            )));
         }
+        let payload = self.bodies.remove(index);
+        self.tethers.retain(|tether| {
+            !matches!(tether.other, TetherEnd::Body { index: other, .. } if other == index)
+        });
+        // Everything after it in `bodies` has moved down one
+        for tether in &mut self.tethers {
+            if let TetherEnd::Body { index: other, .. } = &mut tether.other {
+                if *other > index {
+                    *other -= 1;
+                }
+            }
+        }
+        self.drone.body.attach(payload, offset);
+        Ok(())
     }
 
     pub fn start(&mut self) -> Result<()> {
         Ok(())
     }
 
+    /// Moves the simulation forward one timestep as fast as possible, with
+    /// none of the real time pacing of [`DroneSimulator::simulation_step`].
+    /// This is what batch runs should call.
+    pub fn advance(&mut self) -> Result<()> {
+        let wind = self.weather.wind_at(self.drone.body.position, self.elapsed);
+        self.drone.update_rotor_forces();
+        let drone = &mut self.drone;
+        self.gear_report = drone
+            .landing_gear
+            .as_mut()
+            .map(|gear| gear.apply(&mut drone.body, &self.ground));
+        self.drone.body.step(self.delta_time, wind)?;
+        for body in &mut self.bodies {
+            let wind = self.weather.wind_at(body.position, self.elapsed);
+            body.step(self.delta_time, wind)?;
+        }
+        self.tether_reports = self
+            .tethers
+            .iter()
+            .map(|tether| tether.resolve(&mut self.drone.body, &mut self.bodies, self.delta_time))
+            .collect::<Result<_>>()?;
+        for body in &mut self.bodies {
+            self.ground.resolve(body);
+            self.world.resolve(body);
+        }
+        let ground_contact = self.ground.resolve(&mut self.drone.body);
+        self.contact_state = ground_contact.map_or(ContactState::Airborne, |contact| contact.state);
+        self.obstacle_contacts = self.world.resolve(&mut self.drone.body);
+        self.elapsed += self.delta_time;
+
+        let impacts = self.crash_detector.update(
+            self.elapsed,
+            &self.drone.body,
+            ground_contact.as_ref(),
+            self.gear_report.as_ref(),
+            &self.obstacle_contacts,
+        );
+        if impacts
+            .iter()
+            .any(|impact| impact.class == ImpactClass::Crash)
+        {
+            let policy = self.crash_detector.policy;
+            if policy.disable_propellers {
+                self.drone
+                    .propellers
+                    .iter_mut()
+                    .for_each(|propeller| propeller.fail());
+            }
+            self.finished |= policy.end_run;
+        }
+        self.impacts.extend(impacts);
+        self.geofence_events
+            .extend(self.geofences.update(&self.drone.body));
+        Ok(())
+    }
+
     pub fn simulation_step(&mut self) -> Result<()> {
         let start_time = SystemTime::now();
 
-        self.drone.body.step(self.delta_time)?;
+        self.advance()?;
 
         // We store this now, as having it pass some conditions that later would have failed
         // (time passing from the match to the true/false blocks) could cause some real fucky bugs
@@ -4,7 +4,7 @@ fn main() -> elea::Result<()> {
     let mut simulator = DroneSimulator::new();
 
     simulator.start()?;
-    loop {
+    while !simulator.is_finished() {
         match simulator.simulation_step() {
             Ok(_) => {}
             Err(e) => {
@@ -13,4 +13,9 @@ fn main() -> elea::Result<()> {
             }
         }
     }
+
+    if simulator.crashed() {
+        eprintln!("Run ended in a crash: {:#?}", simulator.impacts());
+    }
+    Ok(())
 }
@@ -2,63 +2,481 @@ use std::time::Duration;
 
 use crate::{
     physics::{
-        force::Forces,
+        force::{ForceVector, Forces},
+        math::Matrix3,
+        shape::{bounds_from_support, furthest, parallel_axis, Shape, ShapePart},
         state::{
             acceleration::Acceleration, angular_velocity::AngularVelocity,
             linear_velocity::LinearVelocity, orientation::Orientation, position::Position,
         },
-        util::types::Dimensions3D,
+        torque::Torque,
+        util::{types::Kilograms, vector::Vector3},
+        WEIGHT,
     },
     Result,
 };
 
-// TODO FIXME: Currently all bodys are defined as a cuboid, this will not work for more complex stuff further down the road
+/// A solid [`Shape`] of uniform density that can move and rotate freely.
+///
+/// # Frames
+/// `position` is where the body frame's origin is, which is also the origin
+/// of the `shape`. For most shapes that's the center of mass too, but a
+/// compound shape's center of mass can sit off to one side.
+///
+/// `position`, `linear_velocity` and `acceleration` are in the world frame,
+/// `angular_velocity` and `torque` are in the body frame, i.e. they rotate
+/// with the body. `linear_velocity` is that of the center of mass.
+///
+/// # Attachments
+/// Payloads fixed to the body, see [`RigidBody::attach`], move with it as
+/// one. `mass` is only the body's own, [`RigidBody::total_mass`] and the
+/// center of mass and inertia all include whatever it is carrying.
 #[derive(Debug)]
 pub struct RigidBody {
-    pub dimensions: Dimensions3D,
+    pub shape: Shape,
+    pub mass: Kilograms,
+    attachments: Vec<Attachment>,
     pub position: Position,
     pub orientation: Orientation,
     pub linear_velocity: LinearVelocity,
     pub angular_velocity: AngularVelocity,
     pub acceleration: Acceleration,
     pub forces: Forces,
+    /// Torque about the center of mass held from step to step, e.g. what
+    /// the rotors are commanded to produce.
+    pub torque: Torque,
+    /// Torque from forces applied with [`RigidBody::apply_force_at`], only
+    /// lasts for the next step.
+    external_torque: Torque,
 }
 
 impl RigidBody {
+    /// A cuboid body, `width` along body `x`, `height` along body `y` and
+    /// `depth` along body `z`.
     pub fn new(height: f64, width: f64, depth: f64) -> RigidBody {
-        let mut rb = RigidBody::default();
-        rb.dimensions = Dimensions3D::new(height, width, depth);
-        rb
+        Self::with_shape(Shape::cuboid(height, width, depth))
     }
 
-    pub fn step(&self, dt: Duration) -> Result<()> {
-        // Early layout:
+    pub fn with_shape(shape: Shape) -> RigidBody {
+        RigidBody {
+            shape,
+            ..Default::default()
+        }
+    }
+
+    /// Advances the body by `dt` with semi-implicit Euler integration,
+    /// velocities are updated first and the *new* velocities then move the
+    /// body, which is far more stable than plain Euler for the same cost.
+    ///
+    /// `wind` is the velocity of the air mass surrounding the body, the drag
+    /// force acts against the body's velocity *relative* to that air.
+    pub fn step(&mut self, dt: Duration, wind: LinearVelocity) -> Result<()> {
+        let dt = dt.as_secs_f64();
+
         // 1. Calculate and add all forces (done in `Forces.net_force`)
-        let net_force = self.forces.net_force();
+        let air_velocity = self.linear_velocity - wind;
+        let net_force =
+            self.forces
+                .calculate_forces(self.total_mass(), air_velocity, self.frontal_area())?;
+
         // 2. Compute linear and angular acceleration
-        //
+        self.acceleration = Acceleration(net_force.0.scalar_div(self.total_mass()));
+        let angular_acceleration = self.angular_acceleration();
+
         // 3. Update velocities.
-        //
-        // 4. Use velocities to update position and orientation (avoid gimbal lock)
+        self.linear_velocity += LinearVelocity(self.acceleration.0.scalar_mul(dt));
+        self.angular_velocity += AngularVelocity(angular_acceleration.scalar_mul(dt));
+
+        // 4. Use velocities to update position and orientation, the quaternion
+        //    keeps us clear of gimbal lock. The body turns about its center
+        //    of mass, so that's what we move before placing the origin again
+        let center_of_mass =
+            self.center_of_mass() + Position(self.linear_velocity.0.scalar_mul(dt));
+        *self.orientation = self.orientation.integrate(self.angular_velocity.0, dt);
+        self.position =
+            center_of_mass - Position(self.orientation.rotate(self.local_center_of_mass()));
+
+        self.forces.clear_external();
+        self.external_torque = Torque::default();
         Ok(())
     }
-    const fn center_of_mass(&self) -> Position {
-        // for now this IS the position as we have a constant density
-        // rigid body
-        self.position
+
+    /// Pushes on the body with a world frame `force` at `point` for the next
+    /// step. Off the center of mass this also adds the torque `r × F`.
+    pub fn apply_force_at(&mut self, force: ForceVector, point: Position) {
+        let r = point.0 - self.center_of_mass().0;
+        self.forces.add_external(force);
+        self.external_torque += Torque(self.orientation.conjugate().rotate(r.cross(&force.0)));
     }
+
+    /// The body's own mass plus everything attached to it.
+    pub fn total_mass(&self) -> Kilograms {
+        self.mass
+            + self
+                .attachments
+                .iter()
+                .map(|attachment| attachment.mass)
+                .sum::<f64>()
+    }
+
+    pub fn attachments(&self) -> &[Attachment] {
+        &self.attachments
+    }
+
+    /// World position of the center of mass.
+    pub fn center_of_mass(&self) -> Position {
+        self.world_point(self.local_center_of_mass())
+    }
+
+    /// Center of mass in the body frame, the mass weighted average of the
+    /// body's own and each attachment's.
+    fn local_center_of_mass(&self) -> Vector3 {
+        let total = self.total_mass();
+        if total <= 0.0 {
+            return self.shape.centre_of_mass();
+        }
+        self.attachments
+            .iter()
+            .fold(
+                self.shape.centre_of_mass().scalar_mul(self.mass),
+                |sum, attachment| {
+                    sum + attachment.part.centre_of_mass().scalar_mul(attachment.mass)
+                },
+            )
+            .scalar_div(total)
+    }
+
+    /// Inertia tensor about the center of mass in the body frame, see
+    /// [`Shape::inertia`], including anything attached.
+    ///
+    /// This is rotation's version of mass, how hard the body is to spin up
+    /// about each axis.
+    pub fn inertia(&self) -> Matrix3 {
+        let centre = self.local_center_of_mass();
+        let own = parallel_axis(
+            self.shape.inertia(self.mass),
+            self.mass,
+            self.shape.centre_of_mass() - centre,
+        );
+        self.attachments.iter().fold(own, |sum, attachment| {
+            sum + attachment.part.inertia_about(attachment.mass, centre)
+        })
+    }
+
+    /// Fixes `payload` to the body with its origin at `offset` in the body
+    /// frame, keeping the way it's currently turned. Mass, center of mass
+    /// and inertia all change at once.
+    ///
+    /// Both momentum and angular momentum (about the new center of mass)
+    /// are kept, so snatching up a payload at rest slows the body down and
+    /// sets it turning, and a spinning payload passes its spin on.
+    ///
+    /// Whatever the payload was carrying is attached alongside it, as
+    /// attachments of their own, so [`release`](Self::release) lets go of
+    /// each one separately.
+    pub fn attach(&mut self, payload: RigidBody, offset: Vector3) {
+        let (own_mass, payload_mass) = (self.total_mass(), payload.total_mass());
+        let momentum = self.linear_velocity.0.scalar_mul(own_mass)
+            + payload.linear_velocity.0.scalar_mul(payload_mass);
+        let orientation = (self.orientation.conjugate() * *payload.orientation).normalised();
+        // Where each part's center of mass is, and its angular momentum about
+        // it, before they're joined
+        let parts = [
+            (
+                self.center_of_mass(),
+                own_mass,
+                self.linear_velocity,
+                self.spin_momentum(),
+            ),
+            (
+                self.world_point(offset + orientation.rotate(payload.local_center_of_mass())),
+                payload_mass,
+                payload.linear_velocity,
+                payload.spin_momentum(),
+            ),
+        ];
+        let place = |part: ShapePart| ShapePart {
+            offset: offset + orientation.rotate(part.offset),
+            orientation: orientation * part.orientation,
+            shape: part.shape,
+        };
+
+        self.attachments.push(Attachment {
+            part: place(ShapePart::new(payload.shape, Vector3::default())),
+            mass: payload.mass,
+        });
+        // Anything the payload was carrying comes along too
+        self.attachments.extend(
+            payload
+                .attachments
+                .into_iter()
+                .map(|attachment| Attachment {
+                    part: place(attachment.part),
+                    mass: attachment.mass,
+                }),
+        );
+
+        let total = self.total_mass();
+        if total > 0.0 {
+            self.linear_velocity = LinearVelocity(momentum.scalar_div(total));
+        }
+        let centre = self.center_of_mass();
+        let angular_momentum = parts.iter().fold(
+            Vector3::default(),
+            |sum, (part_centre, mass, velocity, spin)| {
+                let orbit = (part_centre.0 - centre.0)
+                    .cross(&(velocity.0 - self.linear_velocity.0))
+                    .scalar_mul(*mass);
+                sum + *spin + orbit
+            },
+        );
+        self.angular_velocity = AngularVelocity(
+            self.inverse_inertia_body(self.orientation.conjugate().rotate(angular_momentum)),
+        );
+    }
+
+    /// World frame angular momentum about the center of mass, `Iω`.
+    fn spin_momentum(&self) -> Vector3 {
+        self.orientation
+            .rotate(self.inertia() * self.angular_velocity.0)
+    }
+
+    /// Lets go of attachment `index`, returning it as a free body moving
+    /// exactly as it was while attached. `None` if there's no such
+    /// attachment.
+    pub fn release(&mut self, index: usize) -> Option<RigidBody> {
+        let attachment = self.attachments.get(index)?;
+        let velocity = self.velocity_at(self.world_point(attachment.part.centre_of_mass()));
+        let old_centre = self.center_of_mass();
+        let attachment = self.attachments.remove(index);
+
+        // What's left keeps turning as before, but about its new center of
+        // mass, which moves at a slightly different speed
+        let omega = self.orientation.rotate(self.angular_velocity.0);
+        self.linear_velocity +=
+            LinearVelocity(omega.cross(&(self.center_of_mass().0 - old_centre.0)));
+
+        let part = attachment.part;
+        let mut payload = RigidBody::with_shape(part.shape);
+        payload.mass = attachment.mass;
+        payload.position = self.world_point(part.offset);
+        *payload.orientation = (*self.orientation * part.orientation).normalised();
+        payload.linear_velocity = velocity;
+        payload.angular_velocity =
+            AngularVelocity(part.orientation.conjugate().rotate(self.angular_velocity.0));
+        Some(payload)
+    }
+
+    /// Euler's rotation equation, `α = I⁻¹(τ - ω × Iω)`. The `ω × Iω` term is
+    /// the gyroscopic effect of the body's own spin.
+    fn angular_acceleration(&self) -> Vector3 {
+        let omega = self.angular_velocity.0;
+        let gyroscopic = omega.cross(&(self.inertia() * omega));
+        self.inverse_inertia_body((self.torque + self.external_torque).0 - gyroscopic)
+    }
+
+    /// Applies the body frame `I⁻¹` to `v`. An axis with no inertia (a flat
+    /// or zero sized body) can't be spun up at all, rather than infinitely.
+    fn inverse_inertia_body(&self, v: Vector3) -> Vector3 {
+        let inertia = self.inertia();
+        if let Some(inverse) = inertia.inverse() {
+            return inverse * v;
+        }
+        let inverse = |value: f64, moment: f64| match moment > 0.0 {
+            true => value / moment,
+            false => 0.0,
+        };
+        Vector3::new(
+            inverse(v.x, inertia.rows[0][0]),
+            inverse(v.y, inertia.rows[1][1]),
+            inverse(v.z, inertia.rows[2][2]),
+        )
+    }
+
+    /// Applies a world frame `I⁻¹` to `v`, rotating into the body frame
+    /// where the inertia is fixed and back out again.
+    pub fn inverse_inertia_world(&self, v: Vector3) -> Vector3 {
+        let body = self.orientation.conjugate().rotate(v);
+        self.orientation.rotate(self.inverse_inertia_body(body))
+    }
+
+    /// World frame velocity of a point fixed to the body, `v + ω × r`.
+    pub fn velocity_at(&self, point: Position) -> LinearVelocity {
+        let r = point.0 - self.center_of_mass().0;
+        let omega = self.orientation.rotate(self.angular_velocity.0);
+        self.linear_velocity + LinearVelocity(omega.cross(&r))
+    }
+
+    /// Instantly changes the body's momentum by a world frame `impulse`
+    /// (N⋅s) acting at `point`, which also spins the body unless the
+    /// impulse passes through the center of mass.
+    pub fn apply_impulse(&mut self, impulse: Vector3, point: Position) {
+        let r = point.0 - self.center_of_mass().0;
+        self.linear_velocity += LinearVelocity(impulse.scalar_div(self.total_mass()));
+        let angular_impulse = self.orientation.conjugate().rotate(r.cross(&impulse));
+        self.angular_velocity += AngularVelocity(self.inverse_inertia_body(angular_impulse));
+    }
+
+    /// The point of the body, or anything attached, furthest along the
+    /// world frame `direction`.
+    pub fn support(&self, direction: Vector3) -> Position {
+        self.world_point(self.local_support(self.to_body(direction)))
+    }
+
+    /// Whether the world `point` is inside the body or anything attached.
+    pub fn contains(&self, point: Position) -> bool {
+        let local = self.to_body(point.0 - self.position.0);
+        self.shape.contains(local)
+            || self
+                .attachments
+                .iter()
+                .any(|attachment| attachment.part.contains(local))
+    }
+
+    /// World positions of the points that could touch a surface lying in the
+    /// world `direction` from the body, see [`Shape::contact_points`].
+    pub fn contact_points(&self, direction: Vector3) -> Vec<Position> {
+        let local = self.to_body(direction);
+        self.shape
+            .contact_points(local)
+            .into_iter()
+            .chain(
+                self.attachments
+                    .iter()
+                    .flat_map(|attachment| attachment.part.contact_points(local)),
+            )
+            .map(|point| self.world_point(point))
+            .collect()
+    }
+
+    /// World axis aligned bounding box of the body, as `(min, max)`.
+    pub fn bounds(&self) -> (Position, Position) {
+        let (min, max) = bounds_from_support(|direction| self.support(direction).0);
+        (Position(min), Position(max))
+    }
+
+    /// Where a point fixed in the body frame is in the world.
+    pub fn world_point(&self, local: Vector3) -> Position {
+        Position(self.position.0 + self.orientation.rotate(local))
+    }
+
+    fn local_support(&self, direction: Vector3) -> Vector3 {
+        let attached = self
+            .attachments
+            .iter()
+            .map(|attachment| attachment.part.support(direction));
+        furthest(
+            std::iter::once(self.shape.support(direction)).chain(attached),
+            direction,
+        )
+    }
+
+    /// A world direction to the body frame.
+    fn to_body(&self, direction: Vector3) -> Vector3 {
+        self.orientation.conjugate().rotate(direction)
+    }
+
+    /// Area the body presents to the oncoming air.
+    ///
+    /// We don't account for orientation yet, so this is simply the
+    /// largest face of the body's bounding box (payloads and all) which
+    /// over-estimates drag a bit.
+    fn frontal_area(&self) -> f64 {
+        let (min, max) = bounds_from_support(|direction| self.local_support(direction));
+        let size = max - min;
+        (size.y * size.x).max(size.y * size.z).max(size.x * size.z)
+    }
+}
+
+/// A payload fixed to a body, see [`RigidBody::attach`].
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    /// The payload's shape, placed in the carrying body's frame
+    pub part: ShapePart,
+    pub mass: Kilograms,
 }
 
 impl Default for RigidBody {
     fn default() -> Self {
         Self {
-            dimensions: Dimensions3D::default(),
+            shape: Shape::default(),
+            mass: WEIGHT,
+            attachments: Vec::new(),
             position: Position::default(),
             linear_velocity: LinearVelocity::default(),
             angular_velocity: AngularVelocity::default(),
             acceleration: Acceleration::default(),
             orientation: Orientation::default(),
             forces: Forces::default(),
+            torque: Torque::default(),
+            external_torque: Torque::default(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attach_and_release_payload() {
+        let mut drone = RigidBody::new(0.2, 0.4, 0.4);
+        drone.mass = 2.0;
+        drone.linear_velocity = LinearVelocity(Vector3::new(3.0, 0.0, 0.0));
+        let bare_inertia = drone.inertia();
+        let mut parcel = RigidBody::new(0.1, 0.1, 0.1);
+        parcel.mass = 1.0;
+
+        // Picked up at rest, a third of the momentum goes into the parcel
+        drone.attach(parcel, Vector3::new(0.0, -0.3, 0.0));
+        assert_eq!(drone.total_mass(), 3.0);
+        assert!((drone.center_of_mass().y + 0.1).abs() < 1e-12);
+        assert!((drone.linear_velocity.x - 2.0).abs() < 1e-12);
+        assert!(drone.inertia().rows[0][0] > bare_inertia.rows[0][0]);
+
+        drone.angular_velocity = AngularVelocity(Vector3::new(0.0, 0.0, 1.0));
+        let parcel = drone.release(0).unwrap();
+        assert_eq!(drone.total_mass(), 2.0);
+        assert!(drone.center_of_mass().0.magnitude() < 1e-12);
+        assert!((drone.inertia().rows[0][0] - bare_inertia.rows[0][0]).abs() < 1e-12);
+        // Swung forward by the spin as it lets go
+        assert!((parcel.position.y + 0.3).abs() < 1e-12);
+        assert!((parcel.linear_velocity.x - (2.0 + 0.2)).abs() < 1e-12);
+        assert!(drone.release(0).is_none());
+    }
+
+    #[test]
+    fn test_attach_keeps_angular_momentum() {
+        let mut drone = RigidBody::new(0.2, 0.4, 0.4);
+        drone.mass = 2.0;
+        drone.angular_velocity = AngularVelocity(Vector3::new(0.0, 2.0, 0.0));
+        let before = drone.spin_momentum();
+
+        // Dropped on dead centre, only the extra inertia slows the spin
+        let mut parcel = RigidBody::new(0.1, 0.1, 0.1);
+        parcel.mass = 1.0;
+        drone.attach(parcel, Vector3::default());
+        assert!((drone.spin_momentum() - before).magnitude() < 1e-12);
+        assert!(drone.angular_velocity.y < 2.0);
+    }
+
+    #[test]
+    fn test_nested_payloads_are_flattened() {
+        let mut pallet = RigidBody::new(0.3, 0.3, 0.3);
+        pallet.mass = 1.0;
+        let mut parcel = RigidBody::new(0.1, 0.1, 0.1);
+        parcel.mass = 0.5;
+        pallet.attach(parcel, Vector3::new(0.0, 0.2, 0.0));
+
+        let mut drone = RigidBody::new(0.2, 0.4, 0.4);
+        drone.mass = 2.0;
+        drone.attach(pallet, Vector3::new(0.0, -0.5, 0.0));
+        assert_eq!(drone.attachments().len(), 2);
+
+        // Letting go of the pallet leaves what was on it behind
+        let released = drone.release(0).unwrap();
+        assert_eq!(released.total_mass(), 1.0);
+        assert_eq!(drone.total_mass(), 2.5);
+        assert!((drone.attachments()[0].part.offset.y + 0.3).abs() < 1e-12);
+    }
+}
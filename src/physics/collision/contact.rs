@@ -0,0 +1,65 @@
+//! The impulse response shared by every kind of contact, ground or obstacle.
+
+use crate::physics::{body::RigidBody, state::position::Position, util::vector::Vector3};
+
+/// Below this approach speed (m/s) a contact is treated as resting, it
+/// doesn't bounce. Without this gravity would make a grounded body jitter
+/// up and down in tiny bounces forever.
+pub(crate) const RESTING_SPEED: f64 = 0.2;
+
+/// Pushes `body` out along `normal` by `penetration`, then applies the
+/// impact and Coulomb friction impulses at `point`.
+///
+/// Returns the contact point moved along with the body and the speed it
+/// was approaching the surface at before the response.
+pub(crate) fn respond(
+    body: &mut RigidBody,
+    point: Position,
+    normal: Vector3,
+    penetration: f64,
+    restitution: f64,
+    friction: f64,
+) -> (Position, f64) {
+    // Positional correction, move the body straight back out of the surface
+    let correction = normal.scalar_mul(penetration);
+    body.position += Position(correction);
+    let point = Position(point.0 + correction);
+
+    let approach = body.velocity_at(point).dot(&normal);
+    let impact_speed = (-approach).max(0.0);
+
+    if approach < 0.0 {
+        let restitution = match impact_speed < RESTING_SPEED {
+            true => 0.0,
+            false => restitution,
+        };
+        let normal_impulse =
+            -(1.0 + restitution) * approach / impulse_denominator(body, point, normal);
+        body.apply_impulse(normal.scalar_mul(normal_impulse), point);
+
+        let velocity = body.velocity_at(point).0;
+        let tangential = velocity - normal.scalar_mul(velocity.dot(&normal));
+        let slip = tangential.magnitude();
+        if slip > f64::EPSILON {
+            let direction = tangential.scalar_div(slip);
+            // Just enough to stop the slip, unless that's more than friction allows
+            let friction_impulse =
+                (slip / impulse_denominator(body, point, direction)).min(friction * normal_impulse);
+            body.apply_impulse(direction.scalar_mul(-friction_impulse), point);
+        }
+    }
+
+    (point, impact_speed)
+}
+
+/// How much a unit impulse along `direction` at `point` changes the
+/// velocity of that point along `direction`:
+///
+/// `1/m + d · ((I⁻¹(r × d)) × r)`
+///
+/// Dividing a wanted change of velocity by this gives the impulse needed.
+pub(crate) fn impulse_denominator(body: &RigidBody, point: Position, direction: Vector3) -> f64 {
+    let r = point.0 - body.center_of_mass().0;
+    let angular = body.inverse_inertia_world(r.cross(&direction)).cross(&r);
+    1.0 / body.total_mass() + direction.dot(&angular)
+}
@@ -0,0 +1,174 @@
+use crate::physics::{body::RigidBody, state::position::Position, util::vector::Vector3};
+
+use super::{
+    contact::{respond, RESTING_SPEED},
+    ray::{HitObject, Ray, RayHit},
+    terrain::{Surface, Terrain},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ContactState {
+    /// Not touching the ground at all
+    #[default]
+    Airborne,
+    /// Touching, but hitting or bouncing off it too hard to call it landed
+    Touching,
+    /// Touching and at rest on it, neither hitting it nor sliding along it
+    Landed,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct GroundContact {
+    /// Where the body touched, the average of every contact point below the ground
+    pub point: Position,
+    /// Unit vector pointing out of the ground
+    pub normal: Vector3,
+    /// How far the body had sunk into the ground before we pushed it out
+    pub penetration: f64,
+    /// Speed the contact point was moving into the ground at, before the response
+    pub impact_speed: f64,
+    pub state: ContactState,
+}
+
+/// # Overview
+/// The ground the simulation sits on, its shape is given by the `terrain`
+/// and the rest describes how contacts with it behave.
+///
+/// # Restitution
+/// How bouncy the impact is, the fraction of the approach speed the body
+/// leaves the ground with. `0` sticks like clay, `1` is a perfect bounce.
+///
+/// # Friction
+/// Coulomb friction, the sideways impulse the ground can push back with is
+/// at most `friction` times the normal impulse. Any more than that and the
+/// body slides.
+#[derive(Debug, Clone)]
+pub struct Ground {
+    pub terrain: Terrain,
+    pub restitution: f64,
+    pub friction: f64,
+}
+
+impl Default for Ground {
+    fn default() -> Self {
+        Self {
+            terrain: Terrain::default(),
+            restitution: 0.3,
+            friction: 0.6,
+        }
+    }
+}
+
+impl Ground {
+    pub fn new(terrain: Terrain) -> Self {
+        Self {
+            terrain,
+            ..Default::default()
+        }
+    }
+
+    /// Where `ray` meets the ground, if within `max_distance`.
+    pub fn raycast(&self, ray: &Ray, max_distance: f64) -> Option<RayHit> {
+        self.terrain
+            .raycast(ray, max_distance)
+            .map(|(distance, normal)| RayHit::new(ray, distance, normal, HitObject::Ground))
+    }
+
+    /// Detects the body's contact points that have gone below the ground,
+    /// pushes the body back out and applies the impact and friction impulses.
+    ///
+    /// Returns `None` when the body isn't touching the ground at all.
+    pub fn resolve(&self, body: &mut RigidBody) -> Option<GroundContact> {
+        let centre = body.center_of_mass();
+        let down = -self.terrain.normal_at(centre.x, centre.z);
+        let touching = body
+            .contact_points(down)
+            .into_iter()
+            .filter(|corner| self.terrain.altitude_above(*corner) < 0.0)
+            .collect::<Vec<_>>();
+        if touching.is_empty() {
+            return None;
+        }
+
+        let point = touching
+            .iter()
+            .fold(Vector3::default(), |sum, corner| sum + corner.0)
+            .scalar_div(touching.len() as f64);
+        let normal = self.terrain.normal_at(point.x, point.z);
+        // Depth measured along the normal, on a slope that's less than the
+        // vertical distance below the surface
+        let penetration = touching
+            .iter()
+            .map(|corner| -self.terrain.altitude_above(*corner) * normal.y)
+            .fold(0.0, f64::max);
+
+        let (point, impact_speed) = respond(
+            body,
+            Position(point),
+            normal,
+            penetration,
+            self.restitution,
+            self.friction,
+        );
+
+        // Whatever slip friction couldn't stop
+        let velocity = body.velocity_at(point).0;
+        let sliding = (velocity - normal.scalar_mul(velocity.dot(&normal))).magnitude();
+
+        Some(GroundContact {
+            point,
+            normal,
+            penetration,
+            impact_speed,
+            state: match impact_speed < RESTING_SPEED && sliding < RESTING_SPEED {
+                true => ContactState::Landed,
+                false => ContactState::Touching,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::physics::state::linear_velocity::LinearVelocity;
+
+    #[test]
+    fn test_dropped_body_comes_to_rest_on_ground() {
+        let ground = Ground::default();
+        let mut body = RigidBody::new(0.2, 0.5, 0.5);
+        body.mass = 1.5;
+        body.position.y = 3.0;
+
+        let mut contact = None;
+        let mut first_contact = None;
+        for _ in 0..500 {
+            body.step(Duration::from_millis(10), LinearVelocity::default())
+                .unwrap();
+            contact = ground.resolve(&mut body);
+            first_contact = first_contact.or(contact);
+        }
+
+        // Hitting the ground at speed is a contact, but not a landing
+        assert_eq!(first_contact.unwrap().state, ContactState::Touching);
+
+        let contact = contact.expect("body should be touching the ground");
+        assert_eq!(contact.state, ContactState::Landed);
+        assert!((body.position.y - 0.1).abs() < 0.01);
+        assert!(body.linear_velocity.magnitude() < 0.1);
+    }
+
+    #[test]
+    fn test_sliding_body_has_not_landed() {
+        let ground = Ground::default();
+        let mut body = RigidBody::new(0.2, 0.5, 0.5);
+        body.position.y = 0.099;
+        body.linear_velocity = LinearVelocity(Vector3::new(5.0, -0.05, 0.0));
+
+        let contact = ground.resolve(&mut body).unwrap();
+        assert!(contact.impact_speed < RESTING_SPEED);
+        assert_eq!(contact.state, ContactState::Touching);
+    }
+}
@@ -0,0 +1,19 @@
+//! # Overview
+//!
+//! Detecting when bodies touch the world around them and responding to it.
+//!
+//! Contacts are resolved with *impulses*: rather than modelling the huge
+//! but very brief force of an impact, we instantly change the body's
+//! momentum by the amount that force would have over the impact. It is the
+//! standard trick for rigid bodies as it stays stable at our fixed timestep.
+mod contact;
+pub mod ground;
+pub mod ray;
+pub mod terrain;
+pub mod world;
+
+pub(crate) use contact::{impulse_denominator, RESTING_SPEED};
+pub use ground::{ContactState, Ground, GroundContact};
+pub use ray::{HitObject, Ray, RayHit};
+pub use terrain::{HeightMap, Surface, Terrain};
+pub use world::{Obstacle, ObstacleContact, ObstacleId, ObstacleShape, World};
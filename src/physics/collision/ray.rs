@@ -0,0 +1,282 @@
+//! # Overview
+//!
+//! Ray casting, firing an infinitely thin line from a point and finding the
+//! first thing it hits. This is what a rangefinder or lidar beam does, and
+//! the same query answers "can A see B?" for line of sight checks.
+
+use crate::physics::{state::position::Position, util::vector::Vector3};
+
+use super::world::ObstacleId;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ray {
+    pub origin: Vector3,
+    /// Always unit length, so distances along the ray are in metres
+    pub direction: Vector3,
+}
+
+impl Ray {
+    pub fn new(origin: Vector3, direction: Vector3) -> Self {
+        Self {
+            origin,
+            direction: direction.unit_vector(),
+        }
+    }
+
+    /// The point `distance` metres along the ray.
+    #[inline]
+    pub fn at(&self, distance: f64) -> Vector3 {
+        self.origin + self.direction.scalar_mul(distance)
+    }
+}
+
+/// What a ray hit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HitObject {
+    /// The ground or terrain
+    Ground,
+    Obstacle(ObstacleId),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RayHit {
+    pub distance: f64,
+    pub point: Position,
+    /// Unit vector out of the surface that was hit
+    pub normal: Vector3,
+    pub object: HitObject,
+}
+
+impl RayHit {
+    pub(crate) fn new(ray: &Ray, distance: f64, normal: Vector3, object: HitObject) -> Self {
+        Self {
+            distance,
+            point: Position(ray.at(distance)),
+            normal,
+            object,
+        }
+    }
+
+    /// Whichever of two optional hits is nearer.
+    pub fn nearest(a: Option<RayHit>, b: Option<RayHit>) -> Option<RayHit> {
+        match (a, b) {
+            (Some(a), Some(b)) => Some(if a.distance <= b.distance { a } else { b }),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+/// Where the ray enters a sphere, if it does at all.
+///
+/// Substituting the ray into `|p - c|² = r²` gives a quadratic in the
+/// distance `t`, the smaller root is the way in.
+pub(crate) fn sphere(ray: &Ray, centre: Vector3, radius: f64) -> Option<(f64, Vector3)> {
+    let m = ray.origin - centre;
+    let b = m.dot(&ray.direction);
+    let c = m.dot(&m) - radius * radius;
+    if c <= 0.0 {
+        // Started inside
+        return Some((0.0, -ray.direction));
+    }
+    let discriminant = b * b - c;
+    if b > 0.0 || discriminant < 0.0 {
+        return None;
+    }
+    let t = -b - discriminant.sqrt();
+    Some((t, (ray.at(t) - centre).unit_vector()))
+}
+
+/// Where the ray enters the curved side of a cylinder of `radius` around
+/// the segment from `a` along unit `axis` for `length`. The ends aren't
+/// included, callers add caps or hemispheres as needed. A ray starting
+/// inside hits at distance `0`, facing back along the ray.
+pub(crate) fn cylinder_side(
+    ray: &Ray,
+    a: Vector3,
+    axis: Vector3,
+    length: f64,
+    radius: f64,
+) -> Option<(f64, Vector3)> {
+    // Flatten everything onto the plane perpendicular to the axis, where
+    // the cylinder is just a circle
+    let flatten = |v: Vector3| v - axis.scalar_mul(v.dot(&axis));
+    let d = flatten(ray.direction);
+    let m = flatten(ray.origin - a);
+    let c = m.dot(&m) - radius * radius;
+    if c <= 0.0 && (0.0..=length).contains(&(ray.origin - a).dot(&axis)) {
+        return Some((0.0, -ray.direction));
+    }
+    let a2 = d.dot(&d);
+    if a2 < f64::EPSILON {
+        return None;
+    }
+    let b = m.dot(&d);
+    let discriminant = b * b - a2 * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let t = ((-b - discriminant.sqrt()) / a2).max(0.0);
+    let along = (ray.at(t) - a).dot(&axis);
+    if !(0.0..=length).contains(&along) || (c > 0.0 && b > 0.0) {
+        return None;
+    }
+    Some((t, direction_of(m + d.scalar_mul(t), ray)))
+}
+
+/// Where the ray enters a box centred on the origin, with the ray already in
+/// the box's frame.
+///
+/// The slab method: along each axis the ray is between the two faces for
+/// some span of distance, the ray is inside the box where all three spans
+/// overlap.
+pub(crate) fn local_box(ray: &Ray, half_extents: Vector3) -> Option<(f64, Vector3)> {
+    let origin = [ray.origin.x, ray.origin.y, ray.origin.z];
+    let direction = [ray.direction.x, ray.direction.y, ray.direction.z];
+    let half = [half_extents.x, half_extents.y, half_extents.z];
+
+    let mut enter = f64::NEG_INFINITY;
+    let mut exit = f64::INFINITY;
+    let mut enter_axis = None;
+    for axis in 0..3 {
+        if direction[axis].abs() < f64::EPSILON {
+            if origin[axis].abs() > half[axis] {
+                return None;
+            }
+            continue;
+        }
+        let t1 = (-half[axis] - origin[axis]) / direction[axis];
+        let t2 = (half[axis] - origin[axis]) / direction[axis];
+        let (near, far) = (t1.min(t2), t1.max(t2));
+        if near > enter {
+            enter = near;
+            enter_axis = Some(axis);
+        }
+        exit = exit.min(far);
+    }
+    if enter > exit || exit < 0.0 {
+        return None;
+    }
+    match enter_axis {
+        Some(axis) if enter >= 0.0 => {
+            let mut normal = [0.0; 3];
+            normal[axis] = -direction[axis].signum();
+            Some((enter, Vector3::new(normal[0], normal[1], normal[2])))
+        }
+        // Started inside
+        _ => Some((0.0, -ray.direction)),
+    }
+}
+
+/// Unit vector along `v`, falling back to facing the ray when `v` vanishes.
+fn direction_of(v: Vector3, ray: &Ray) -> Vector3 {
+    match v.magnitude() > f64::EPSILON {
+        true => v.unit_vector(),
+        false => -ray.direction,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::collision::{HeightMap, ObstacleShape, Terrain, World};
+
+    #[test]
+    fn test_ray_hits_nearest_obstacle() {
+        let mut world = World::new();
+        let far = world.add(ObstacleShape::Sphere {
+            centre: Vector3::new(10.0, 0.0, 0.0),
+            radius: 1.0,
+        });
+        let near = world.add(ObstacleShape::Capsule {
+            a: Vector3::new(5.0, -2.0, 0.0),
+            b: Vector3::new(5.0, 2.0, 0.0),
+            radius: 0.5,
+        });
+        world.add(ObstacleShape::Cylinder {
+            a: Vector3::new(0.0, 3.0, 0.0),
+            b: Vector3::new(20.0, 3.0, 0.0),
+            radius: 0.5,
+        });
+        let ray = Ray::new(Vector3::default(), Vector3::new(1.0, 0.0, 0.0));
+
+        let hit = world.raycast(&ray, 100.0).unwrap();
+        assert_eq!(hit.object, HitObject::Obstacle(near));
+        assert!((hit.distance - 4.5).abs() < 1e-9);
+        assert!((hit.normal - Vector3::new(-1.0, 0.0, 0.0)).magnitude() < 1e-9);
+
+        let past = Ray::new(Vector3::new(6.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        let hit = world.raycast(&past, 100.0).unwrap();
+        assert_eq!(hit.object, HitObject::Obstacle(far));
+        assert!(world.raycast(&past, 2.0).is_none());
+    }
+
+    #[test]
+    fn test_ray_starting_inside_faces_back() {
+        let direction = Vector3::new(0.6, 0.0, 0.8);
+        for shape in [
+            ObstacleShape::Capsule {
+                a: Vector3::new(0.0, -2.0, 0.0),
+                b: Vector3::new(0.0, 2.0, 0.0),
+                radius: 1.0,
+            },
+            ObstacleShape::Cylinder {
+                a: Vector3::new(0.0, -2.0, 0.0),
+                b: Vector3::new(0.0, 2.0, 0.0),
+                radius: 1.0,
+            },
+        ] {
+            let ray = Ray::new(Vector3::new(0.5, 0.0, 0.0), direction);
+            let (distance, normal) = shape.raycast(&ray).unwrap();
+            assert_eq!(distance, 0.0);
+            assert!((normal + direction).magnitude() < 1e-9, "{shape:?}");
+        }
+    }
+
+    #[test]
+    fn test_ray_hits_sloped_terrain() {
+        // Rises 1m for every 2m along x
+        let terrain =
+            Terrain::HeightMap(HeightMap::from_fn(1.0, (41, 41), |x, _| x / 2.0).unwrap());
+        let ray = Ray::new(Vector3::new(10.0, 20.0, 10.0), Vector3::new(0.0, -1.0, 0.0));
+
+        let (distance, normal) = terrain.raycast(&ray, 100.0).unwrap();
+        assert!((distance - 15.0).abs() < 1e-6);
+        assert!((normal - Vector3::new(-0.5, 1.0, 0.0).unit_vector()).magnitude() < 1e-9);
+
+        // With no limit the march still stops, level over the top misses...
+        let level = Ray::new(Vector3::new(-5.0, 25.0, 10.0), Vector3::new(1.0, 0.0, 0.0));
+        assert!(terrain.raycast(&level, f64::INFINITY).is_none());
+        // ...lower down it runs into the slope...
+        let level = Ray::new(Vector3::new(-5.0, 5.0, 10.0), Vector3::new(1.0, 0.0, 0.0));
+        let (distance, _) = terrain.raycast(&level, f64::INFINITY).unwrap();
+        assert!((distance - 15.0).abs() < 1e-6);
+        // ...and past the edge it comes down on the held edge height
+        let ray = Ray::new(Vector3::new(60.0, 30.0, 10.0), Vector3::new(0.0, -1.0, 0.0));
+        let (distance, _) = terrain.raycast(&ray, f64::INFINITY).unwrap();
+        assert!((distance - 10.0).abs() < 1e-6);
+        // ...as it does before reaching the grid
+        let ray = Ray::new(Vector3::new(-10.0, 5.0, 10.0), Vector3::new(1.0, -1.0, 0.0));
+        let (distance, _) = terrain.raycast(&ray, f64::INFINITY).unwrap();
+        assert!((distance - 5.0 * 2f64.sqrt()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_far_shallow_ray_skips_to_the_grid() {
+        // A 1m bump in the middle of a flat 4x4 map
+        let terrain = Terrain::HeightMap(
+            HeightMap::from_fn(1.0, (4, 4), |x, z| match (x, z) {
+                (1.0..=2.0, 1.0..=2.0) => 1.0,
+                _ => 0.0,
+            })
+            .unwrap(),
+        );
+        // Coming in from 10,000 km away, 0.4m up by the time it's over the
+        // grid, marching the whole way there would take ~4e7 steps
+        let ray = Ray::new(Vector3::new(-1e7, 0.9, 1.5), Vector3::new(1.0, -5e-8, 0.0));
+
+        let start = std::time::Instant::now();
+        let (distance, _) = terrain.raycast(&ray, f64::INFINITY).unwrap();
+        assert!(start.elapsed() < std::time::Duration::from_millis(100));
+        assert!((ray.at(distance).x - 0.4).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,474 @@
+//! # Overview
+//!
+//! The shape of the ground. Anything we can stand on implements [`Surface`],
+//! which answers two questions about any horizontal (`x`,`z`) location: how
+//! high is the ground here and which way is it facing.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use crate::{
+    physics::{math::GradientNoise, state::position::Position, util::vector::Vector3},
+    EleaError, Result,
+};
+
+use super::ray::Ray;
+
+/// How many times we halve the gap once a ray marching over a height map
+/// has stepped through the surface, ~1e-12 of a step is plenty.
+const RAY_BISECTIONS: usize = 40;
+
+pub trait Surface {
+    /// Height of the ground (world `y`) directly below or above `x`,`z`.
+    fn height_at(&self, x: f64, z: f64) -> f64;
+
+    /// Unit vector pointing up out of the ground at `x`,`z`.
+    fn normal_at(&self, x: f64, z: f64) -> Vector3;
+
+    /// Height of `position` above the ground directly beneath it, what a
+    /// downward facing rangefinder would read when flying level.
+    fn altitude_above(&self, position: Position) -> f64 {
+        position.y - self.height_at(position.x, position.z)
+    }
+}
+
+/// # Overview
+/// Ground heights sampled on a regular grid in the `x`,`z` plane, with
+/// bilinear interpolation between the four samples around a point.
+///
+/// Beyond the edges of the grid the edge height carries on forever.
+///
+/// # Normals
+/// Within a cell the surface is the bilinear patch through its corners, so
+/// the normal comes straight from the patch's slopes:
+/// `n = normalise(-∂h/∂x, 1, -∂h/∂z)`
+#[derive(Debug, Clone)]
+pub struct HeightMap {
+    /// World `x`,`z` of the first sample
+    origin: (f64, f64),
+    /// Distance between samples (m), the same along `x` and `z`
+    spacing: f64,
+    /// Number of samples along `x` and `z`
+    size: (usize, usize),
+    /// `x` varies fastest
+    heights: Vec<f64>,
+    /// Lowest and highest sample
+    range: (f64, f64),
+}
+
+impl HeightMap {
+    pub fn new(spacing: f64, size: (usize, usize), heights: Vec<f64>) -> Result<Self> {
+        if !spacing.is_finite() || spacing <= 0.0 {
+            return Err(EleaError::InvalidData(format!(
+                "height map spacing must be positive, got {spacing}"
+            )));
+        }
+        if !heights.iter().all(|height| height.is_finite()) {
+            return Err(EleaError::InvalidData(
+                "height map heights must be finite".into(),
+            ));
+        }
+        let points = map_points(size)?;
+        if points == 0 || heights.len() != points {
+            return Err(EleaError::InvalidData(format!(
+                "height map of {}x{} needs {points} heights, got {}",
+                size.0,
+                size.1,
+                heights.len()
+            )));
+        }
+        let range = heights
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), height| {
+                (low.min(*height), high.max(*height))
+            });
+        Ok(Self {
+            origin: (0.0, 0.0),
+            spacing,
+            size,
+            heights,
+            range,
+        })
+    }
+
+    /// Moves the grid so its first sample sits at world `x`,`z`.
+    pub fn with_origin(mut self, x: f64, z: f64) -> Self {
+        self.origin = (x, z);
+        self
+    }
+
+    /// Generates a height map by sampling `height(x, z)` at every grid point,
+    /// with `x` and `z` relative to the origin.
+    pub fn from_fn<F: Fn(f64, f64) -> f64>(
+        spacing: f64,
+        size: (usize, usize),
+        height: F,
+    ) -> Result<Self> {
+        map_points(size)?;
+        let heights = (0..size.1)
+            .flat_map(|k| (0..size.0).map(move |i| (i, k)))
+            .map(|(i, k)| height(i as f64 * spacing, k as f64 * spacing))
+            .collect();
+        Self::new(spacing, size, heights)
+    }
+
+    /// Rolling hills from fractal gradient noise. `feature_size` (m) is
+    /// roughly the width of the largest hills and heights stay within about
+    /// `±amplitude`.
+    pub fn procedural(
+        seed: u64,
+        spacing: f64,
+        size: (usize, usize),
+        amplitude: f64,
+        feature_size: f64,
+    ) -> Result<Self> {
+        let noise = GradientNoise::new(seed);
+        Self::from_fn(spacing, size, |x, z| {
+            amplitude * noise.fractal(x / feature_size, 0.5, z / feature_size, 4, 0.5, 2.0)
+        })
+    }
+
+    /// Reads a greyscale PGM image (`P2` text or `P5` binary), black is a
+    /// height of `0` and white is `max_height`. Image rows run along `z`.
+    pub fn from_pgm<R: BufRead>(mut reader: R, spacing: f64, max_height: f64) -> Result<Self> {
+        if !max_height.is_finite() {
+            return Err(EleaError::InvalidData(format!(
+                "PGM max height must be finite, got {max_height}"
+            )));
+        }
+        let mut header = Vec::new();
+        while header.len() < 4 {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(EleaError::InvalidData("truncated PGM header".into()));
+            }
+            let line = line.split('#').next().unwrap_or_default();
+            header.extend(line.split_whitespace().map(str::to_owned));
+        }
+        let number = |field: &str| {
+            field
+                .parse::<usize>()
+                .map_err(|e| EleaError::InvalidData(format!("bad PGM header value {field}: {e}")))
+        };
+        let (width, height, max_value) = (
+            number(&header[1])?,
+            number(&header[2])?,
+            number(&header[3])?,
+        );
+        if max_value == 0 || max_value > u16::MAX as usize {
+            return Err(EleaError::InvalidData(format!(
+                "PGM max value {max_value} out of range"
+            )));
+        }
+
+        let values = match header[0].as_str() {
+            "P2" => {
+                let mut text = header[4..].join(" ");
+                text.push(' ');
+                reader.read_to_string(&mut text)?;
+                text.split_whitespace()
+                    .map(number)
+                    .collect::<Result<Vec<_>>>()?
+            }
+            "P5" => {
+                let mut bytes = Vec::new();
+                reader.read_to_end(&mut bytes)?;
+                match max_value < 256 {
+                    true => bytes.into_iter().map(usize::from).collect(),
+                    false => bytes
+                        .chunks_exact(2)
+                        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]) as usize)
+                        .collect(),
+                }
+            }
+            magic => {
+                return Err(EleaError::InvalidData(format!(
+                    "unsupported PGM type {magic}, expected P2 or P5"
+                )))
+            }
+        };
+        let heights = values
+            .into_iter()
+            .take(map_points((width, height))?)
+            .map(|value| value as f64 / max_value as f64 * max_height)
+            .collect();
+        Self::new(spacing, (width, height), heights)
+    }
+
+    /// Reads comma separated heights in metres, one row of `x` samples per
+    /// line with successive lines stepping along `z`.
+    pub fn from_csv<R: BufRead>(reader: R, spacing: f64) -> Result<Self> {
+        let mut rows = Vec::new();
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            let row = line
+                .split(',')
+                .map(|value| value.trim().parse::<f64>())
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .map_err(|e| EleaError::InvalidData(format!("line {}: {e}", number + 1)))?;
+            rows.push(row);
+        }
+        let width = rows.first().map_or(0, Vec::len);
+        if rows.iter().any(|row| row.len() != width) {
+            return Err(EleaError::InvalidData(
+                "height map rows must all be the same length".into(),
+            ));
+        }
+        let size = (width, rows.len());
+        Self::new(spacing, size, rows.into_iter().flatten().collect())
+    }
+
+    pub fn load_pgm<P: AsRef<Path>>(path: P, spacing: f64, max_height: f64) -> Result<Self> {
+        Self::from_pgm(BufReader::new(File::open(path)?), spacing, max_height)
+    }
+
+    pub fn load_csv<P: AsRef<Path>>(path: P, spacing: f64) -> Result<Self> {
+        Self::from_csv(BufReader::new(File::open(path)?), spacing)
+    }
+
+    /// The stretch of `ray`, as distances along it, that could meet the
+    /// grid itself, `None` if it never comes low enough.
+    ///
+    /// It starts once the ray is below the highest sample and over the grid
+    /// on some axis it moves along, until then the held edge height under
+    /// it doesn't change. It ends once the ray is below the lowest sample,
+    /// or once it has left the grid on every axis it moves along, where the
+    /// height stops changing again.
+    fn span(&self, ray: &Ray, max_distance: f64) -> Option<(f64, f64)> {
+        let (low, high) = self.range;
+        let (y, dy) = (ray.origin.y, ray.direction.y);
+        let (start, below) = match dy {
+            dy if dy < 0.0 => (((high - y) / dy).max(0.0), (low - y) / dy),
+            _ if y > high => return None,
+            dy if dy > 0.0 => (0.0, (high - y) / dy),
+            _ => (0.0, f64::INFINITY),
+        };
+
+        let leaves = |origin: f64, direction: f64, first: f64, points: usize| {
+            let last = first + (points - 1) as f64 * self.spacing;
+            match direction {
+                d if d > 0.0 => ((last - origin) / d).max(0.0),
+                d if d < 0.0 => ((first - origin) / d).max(0.0),
+                _ => 0.0,
+            }
+        };
+        let enters = |origin: f64, direction: f64, first: f64, points: usize| {
+            let last = first + (points - 1) as f64 * self.spacing;
+            match direction {
+                d if d > 0.0 => Some(((first - origin) / d).max(0.0)),
+                d if d < 0.0 => Some(((last - origin) / d).max(0.0)),
+                _ => None,
+            }
+        };
+        let enter = [
+            enters(ray.origin.x, ray.direction.x, self.origin.0, self.size.0),
+            enters(ray.origin.z, ray.direction.z, self.origin.1, self.size.1),
+        ]
+        .into_iter()
+        .flatten()
+        .reduce(f64::min)
+        .unwrap_or(0.0);
+        let left = leaves(ray.origin.x, ray.direction.x, self.origin.0, self.size.0).max(leaves(
+            ray.origin.z,
+            ray.direction.z,
+            self.origin.1,
+            self.size.1,
+        ));
+
+        let end = below.min(left.max(start)).min(max_distance);
+        (start <= end).then_some((start.max(enter).min(end), end))
+    }
+
+    fn height(&self, i: usize, k: usize) -> f64 {
+        self.heights[i + self.size.0 * k]
+    }
+
+    /// The cell containing `x`,`z` as its lower sample indices and the
+    /// fractional position within it, clamped to the grid.
+    fn cell(&self, x: f64, z: f64) -> (usize, usize, f64, f64) {
+        let axis = |offset: f64, points: usize| {
+            let last = points - 1;
+            let fractional = (offset / self.spacing).clamp(0.0, last as f64);
+            let lower = (fractional.floor() as usize).min(last.saturating_sub(1));
+            (lower, fractional - lower as f64)
+        };
+        let (i, tx) = axis(x - self.origin.0, self.size.0);
+        let (k, tz) = axis(z - self.origin.1, self.size.1);
+        (i, k, tx, tz)
+    }
+
+    /// Heights at the four corners of a cell, `(i,k)`, `(i+1,k)`, `(i,k+1)`
+    /// and `(i+1,k+1)`. A single row or column grid repeats its edge.
+    fn corners(&self, i: usize, k: usize) -> [f64; 4] {
+        let i1 = (i + 1).min(self.size.0 - 1);
+        let k1 = (k + 1).min(self.size.1 - 1);
+        [
+            self.height(i, k),
+            self.height(i1, k),
+            self.height(i, k1),
+            self.height(i1, k1),
+        ]
+    }
+}
+
+/// Number of samples in a `size` grid, or an error if that doesn't fit in
+/// a `usize`.
+fn map_points(size: (usize, usize)) -> Result<usize> {
+    size.0.checked_mul(size.1).ok_or_else(|| {
+        EleaError::InvalidData(format!("height map of {}x{} is too big", size.0, size.1))
+    })
+}
+
+impl Surface for HeightMap {
+    fn height_at(&self, x: f64, z: f64) -> f64 {
+        let (i, k, tx, tz) = self.cell(x, z);
+        let [h00, h10, h01, h11] = self.corners(i, k);
+        let near = h00 + (h10 - h00) * tx;
+        let far = h01 + (h11 - h01) * tx;
+        near + (far - near) * tz
+    }
+
+    fn normal_at(&self, x: f64, z: f64) -> Vector3 {
+        let (i, k, tx, tz) = self.cell(x, z);
+        let [h00, h10, h01, h11] = self.corners(i, k);
+        let dx = ((h10 - h00) * (1.0 - tz) + (h11 - h01) * tz) / self.spacing;
+        let dz = ((h01 - h00) * (1.0 - tx) + (h11 - h10) * tx) / self.spacing;
+        Vector3::new(-dx, 1.0, -dz).unit_vector()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Terrain {
+    /// An infinite flat plane at a fixed height
+    Flat(f64),
+    HeightMap(HeightMap),
+}
+
+impl Default for Terrain {
+    fn default() -> Self {
+        Terrain::Flat(0.0)
+    }
+}
+
+impl Terrain {
+    /// Distance along `ray` to the ground and the ground's normal there. A
+    /// ray starting underground hits at distance `0`.
+    ///
+    /// A height map has no neat closed form, so we march along the ray in
+    /// steps smaller than a grid cell until we end up below the surface,
+    /// then bisect that last step to find the crossing. Only the stretch of
+    /// the ray between the highest and lowest samples and over the grid is
+    /// marched, before and past the grid the held edge is flat so we solve
+    /// for it directly.
+    pub fn raycast(&self, ray: &Ray, max_distance: f64) -> Option<(f64, Vector3)> {
+        let normal_at = |distance: f64| {
+            let point = ray.at(distance);
+            (distance, self.normal_at(point.x, point.z))
+        };
+        let below = |distance: f64| self.altitude_above(Position(ray.at(distance))) < 0.0;
+        if below(0.0) {
+            return Some(normal_at(0.0));
+        }
+
+        match self {
+            Terrain::Flat(height) => {
+                let distance = (height - ray.origin.y) / ray.direction.y;
+                (ray.direction.y < 0.0 && distance <= max_distance).then(|| normal_at(distance))
+            }
+            Terrain::HeightMap(map) => {
+                // Coming down onto the flat held edge from `from`
+                let onto_edge = |from: f64| {
+                    let point = ray.at(from);
+                    let distance =
+                        from + (point.y - map.height_at(point.x, point.z)) / -ray.direction.y;
+                    (ray.direction.y < 0.0 && distance <= max_distance).then(|| normal_at(distance))
+                };
+                let (start, end) = map.span(ray, max_distance)?;
+                // Already through the held edge before reaching the grid
+                if below(start) {
+                    return onto_edge(start);
+                }
+                let step = map.spacing / 4.0;
+                let mut previous = start;
+                while previous < end {
+                    let next = (previous + step).min(end);
+                    if below(next) {
+                        let (mut above, mut under) = (previous, next);
+                        for _ in 0..RAY_BISECTIONS {
+                            let middle = (above + under) / 2.0;
+                            match below(middle) {
+                                true => under = middle,
+                                false => above = middle,
+                            }
+                        }
+                        return Some(normal_at(under));
+                    }
+                    previous = next;
+                }
+                // Past the grid on every axis, heading down onto the edge
+                onto_edge(end)
+            }
+        }
+    }
+}
+
+impl Surface for Terrain {
+    fn height_at(&self, x: f64, z: f64) -> f64 {
+        match self {
+            Terrain::Flat(height) => *height,
+            Terrain::HeightMap(map) => map.height_at(x, z),
+        }
+    }
+
+    fn normal_at(&self, x: f64, z: f64) -> Vector3 {
+        match self {
+            Terrain::Flat(_) => Vector3::new(0.0, 1.0, 0.0),
+            Terrain::HeightMap(map) => map.normal_at(x, z),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slope_height_and_normal() {
+        // Rises 1m for every 2m along x
+        let map = HeightMap::from_fn(1.0, (11, 11), |x, _| x / 2.0).unwrap();
+
+        assert!((map.height_at(3.5, 4.2) - 1.75).abs() < 1e-9);
+        let normal = map.normal_at(3.5, 4.2);
+        let expected = Vector3::new(-0.5, 1.0, 0.0).unit_vector();
+        assert!((normal - expected).magnitude() < 1e-9);
+    }
+
+    #[test]
+    fn test_pgm_scales_to_max_height() {
+        let pgm = "P2\n# a ramp\n3 2\n255\n0 51 255\n0 51 255\n";
+        let map = HeightMap::from_pgm(pgm.as_bytes(), 5.0, 100.0).unwrap();
+
+        assert!((map.height_at(5.0, 0.0) - 20.0).abs() < 1e-9);
+        assert!((map.height_at(10.0, 5.0) - 100.0).abs() < 1e-9);
+
+        let huge = format!("P2\n{0} {0}\n255\n0\n", usize::MAX);
+        assert!(HeightMap::from_pgm(huge.as_bytes(), 5.0, 100.0).is_err());
+        assert!(HeightMap::from_pgm(pgm.as_bytes(), 5.0, f64::INFINITY).is_err());
+    }
+
+    #[test]
+    fn test_rejects_non_finite_maps() {
+        for spacing in [0.0, f64::NAN, f64::INFINITY] {
+            assert!(HeightMap::new(spacing, (1, 1), vec![0.0]).is_err());
+        }
+        assert!(HeightMap::new(1.0, (1, 1), vec![f64::NAN]).is_err());
+        assert!(HeightMap::from_csv("0,NaN\n0,0\n".as_bytes(), 1.0).is_err());
+        assert!(HeightMap::from_csv("0,inf\n0,0\n".as_bytes(), 1.0).is_err());
+    }
+}
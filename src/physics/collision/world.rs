@@ -0,0 +1,424 @@
+//! # Overview
+//!
+//! The static obstacles a drone can fly into, walls, racking, poles and the
+//! like. Obstacles never move, they only push back on bodies that hit them.
+//!
+//! # Signed distance
+//! Every obstacle shape can tell us the *signed distance* from any point to
+//! its surface: positive outside, negative inside, along with the direction
+//! straight out of the surface. Collision checks are then just a matter of
+//! asking "is any part of the body at a negative distance?".
+
+use crate::physics::{
+    body::RigidBody, math::Quaternion, state::position::Position, util::vector::Vector3,
+};
+
+use super::{
+    contact::respond,
+    ray::{self, HitObject, Ray, RayHit},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ObstacleId(pub usize);
+
+#[derive(Debug, Clone, Copy)]
+pub enum ObstacleShape {
+    /// Axis aligned box between two opposite corners
+    Aabb {
+        min: Vector3,
+        max: Vector3,
+    },
+    /// Box rotated by `orientation` about its centre
+    Obb {
+        centre: Vector3,
+        half_extents: Vector3,
+        orientation: Quaternion,
+    },
+    Sphere {
+        centre: Vector3,
+        radius: f64,
+    },
+    /// Flat ended cylinder whose axis runs from `a` to `b`
+    Cylinder {
+        a: Vector3,
+        b: Vector3,
+        radius: f64,
+    },
+    /// Every point within `radius` of the segment from `a` to `b`, a
+    /// cylinder with hemispheres on the ends
+    Capsule {
+        a: Vector3,
+        b: Vector3,
+        radius: f64,
+    },
+}
+
+impl ObstacleShape {
+    /// Distance from `point` to the surface (negative inside) and the unit
+    /// vector pointing out of the surface nearest to it.
+    pub fn signed_distance(&self, point: Vector3) -> (f64, Vector3) {
+        match *self {
+            ObstacleShape::Aabb { min, max } => {
+                let centre = (min + max).scalar_mul(0.5);
+                box_distance(point - centre, (max - min).scalar_mul(0.5))
+            }
+            ObstacleShape::Obb {
+                centre,
+                half_extents,
+                orientation,
+            } => {
+                let local = orientation.conjugate().rotate(point - centre);
+                let (distance, normal) = box_distance(local, half_extents);
+                (distance, orientation.rotate(normal))
+            }
+            ObstacleShape::Sphere { centre, radius } => {
+                let offset = point - centre;
+                (offset.magnitude() - radius, direction_or_up(offset))
+            }
+            ObstacleShape::Capsule { a, b, radius } => {
+                let offset = point - closest_on_segment(a, b, point);
+                (offset.magnitude() - radius, direction_or_up(offset))
+            }
+            ObstacleShape::Cylinder { a, b, radius } => {
+                let length = (b - a).magnitude();
+                let axis = direction_or_up(b - a);
+                let along = (point - a).dot(&axis);
+                let radial = point - a - axis.scalar_mul(along);
+                let radial_distance = radial.magnitude() - radius;
+                let axial_distance = (along - length / 2.0).abs() - length / 2.0;
+                let axial_normal = match along < length / 2.0 {
+                    true => -axis,
+                    false => axis,
+                };
+                let radial_normal = direction_or_up(radial);
+                match (radial_distance > 0.0, axial_distance > 0.0) {
+                    // Beyond the rim, nearest point is on the edge circle
+                    (true, true) => {
+                        let offset = radial_normal.scalar_mul(radial_distance)
+                            + axial_normal.scalar_mul(axial_distance);
+                        (offset.magnitude(), offset.unit_vector())
+                    }
+                    _ if radial_distance > axial_distance => (radial_distance, radial_normal),
+                    _ => (axial_distance, axial_normal),
+                }
+            }
+        }
+    }
+
+    /// Distance along `ray` to where it first enters the shape and the
+    /// surface normal there. A ray starting inside hits at distance `0`.
+    pub fn raycast(&self, ray: &Ray) -> Option<(f64, Vector3)> {
+        match *self {
+            ObstacleShape::Aabb { min, max } => {
+                let centre = (min + max).scalar_mul(0.5);
+                let local = Ray {
+                    origin: ray.origin - centre,
+                    direction: ray.direction,
+                };
+                ray::local_box(&local, (max - min).scalar_mul(0.5))
+            }
+            ObstacleShape::Obb {
+                centre,
+                half_extents,
+                orientation,
+            } => {
+                let inverse = orientation.conjugate();
+                let local = Ray {
+                    origin: inverse.rotate(ray.origin - centre),
+                    direction: inverse.rotate(ray.direction),
+                };
+                ray::local_box(&local, half_extents)
+                    .map(|(distance, normal)| (distance, orientation.rotate(normal)))
+            }
+            ObstacleShape::Sphere { centre, radius } => ray::sphere(ray, centre, radius),
+            ObstacleShape::Capsule { a, b, radius } => {
+                let length = (b - a).magnitude();
+                let axis = direction_or_up(b - a);
+                [
+                    ray::cylinder_side(ray, a, axis, length, radius),
+                    ray::sphere(ray, a, radius),
+                    ray::sphere(ray, b, radius),
+                ]
+                .into_iter()
+                .flatten()
+                .min_by(|x, y| x.0.total_cmp(&y.0))
+            }
+            ObstacleShape::Cylinder { a, b, radius } => {
+                if self.signed_distance(ray.origin).0 <= 0.0 {
+                    return Some((0.0, -ray.direction));
+                }
+                let length = (b - a).magnitude();
+                let axis = direction_or_up(b - a);
+                // The flat ends are discs, hit the plane then check we're
+                // within the radius
+                let cap = |centre: Vector3, normal: Vector3| {
+                    let facing = ray.direction.dot(&normal);
+                    if facing >= 0.0 {
+                        return None;
+                    }
+                    let t = (centre - ray.origin).dot(&normal) / facing;
+                    let on_disc = (ray.at(t) - centre).magnitude() <= radius;
+                    (t >= 0.0 && on_disc).then_some((t, normal))
+                };
+                [
+                    ray::cylinder_side(ray, a, axis, length, radius),
+                    cap(a, -axis),
+                    cap(b, axis),
+                ]
+                .into_iter()
+                .flatten()
+                .min_by(|x, y| x.0.total_cmp(&y.0))
+            }
+        }
+    }
+}
+
+/// Signed distance to a box centred on the origin, `point` in the box's frame.
+fn box_distance(point: Vector3, half_extents: Vector3) -> (f64, Vector3) {
+    let q = Vector3::new(
+        point.x.abs() - half_extents.x,
+        point.y.abs() - half_extents.y,
+        point.z.abs() - half_extents.z,
+    );
+    let outside = Vector3::new(q.x.max(0.0), q.y.max(0.0), q.z.max(0.0));
+    let sign = |value: f64| if value < 0.0 { -1.0 } else { 1.0 };
+    if outside.magnitude() > 0.0 {
+        let normal = Vector3::new(
+            outside.x * sign(point.x),
+            outside.y * sign(point.y),
+            outside.z * sign(point.z),
+        );
+        return (outside.magnitude(), normal.unit_vector());
+    }
+    // Inside, the nearest face is the one we're least deep behind
+    if q.x >= q.y && q.x >= q.z {
+        (q.x, Vector3::new(sign(point.x), 0.0, 0.0))
+    } else if q.y >= q.z {
+        (q.y, Vector3::new(0.0, sign(point.y), 0.0))
+    } else {
+        (q.z, Vector3::new(0.0, 0.0, sign(point.z)))
+    }
+}
+
+fn closest_on_segment(a: Vector3, b: Vector3, point: Vector3) -> Vector3 {
+    let segment = b - a;
+    let length_squared = segment.dot(&segment);
+    if length_squared < f64::EPSILON {
+        return a;
+    }
+    let t = ((point - a).dot(&segment) / length_squared).clamp(0.0, 1.0);
+    a + segment.scalar_mul(t)
+}
+
+/// Unit vector along `v`, or straight up if `v` has no length to speak of.
+fn direction_or_up(v: Vector3) -> Vector3 {
+    match v.magnitude() > f64::EPSILON {
+        true => v.unit_vector(),
+        false => Vector3::new(0.0, 1.0, 0.0),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Obstacle {
+    pub id: ObstacleId,
+    pub shape: ObstacleShape,
+    pub restitution: f64,
+    pub friction: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ObstacleContact {
+    pub obstacle: ObstacleId,
+    pub point: Position,
+    /// Unit vector out of the obstacle, the direction the body was pushed
+    pub normal: Vector3,
+    pub penetration: f64,
+    /// Speed the contact point was moving into the obstacle at, before the response
+    pub impact_speed: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct World {
+    obstacles: Vec<Obstacle>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an obstacle with a moderately bouncy, grippy surface.
+    pub fn add(&mut self, shape: ObstacleShape) -> ObstacleId {
+        self.add_with_material(shape, 0.3, 0.6)
+    }
+
+    pub fn add_with_material(
+        &mut self,
+        shape: ObstacleShape,
+        restitution: f64,
+        friction: f64,
+    ) -> ObstacleId {
+        let id = ObstacleId(self.obstacles.len());
+        self.obstacles.push(Obstacle {
+            id,
+            shape,
+            restitution,
+            friction,
+        });
+        id
+    }
+
+    pub fn obstacles(&self) -> &[Obstacle] {
+        &self.obstacles
+    }
+
+    pub fn get(&self, id: ObstacleId) -> Option<&Obstacle> {
+        self.obstacles.get(id.0)
+    }
+
+    /// The first obstacle `ray` hits within `max_distance`.
+    pub fn raycast(&self, ray: &Ray, max_distance: f64) -> Option<RayHit> {
+        self.obstacles
+            .iter()
+            .filter_map(|obstacle| {
+                let (distance, normal) = obstacle.shape.raycast(ray)?;
+                (distance <= max_distance)
+                    .then(|| RayHit::new(ray, distance, normal, HitObject::Obstacle(obstacle.id)))
+            })
+            .min_by(|a, b| a.distance.total_cmp(&b.distance))
+    }
+
+    /// Finds the deepest point of the body inside `obstacle`, if any.
+    ///
+    /// We check two things, either of which can be the deepest:
+    /// 1. the body's contact points poking into the obstacle, averaged
+    ///    together
+    /// 2. the obstacle's point nearest the body's centre poking into the
+    ///    body, which catches poles or spheres smaller than the body pressing
+    ///    into one of its faces.
+    fn detect(&self, obstacle: &Obstacle, body: &RigidBody) -> Option<(Position, Vector3, f64)> {
+        let centre = body.center_of_mass().0;
+        let (centre_distance, outward) = obstacle.shape.signed_distance(centre);
+
+        let points = body
+            .contact_points(-outward)
+            .into_iter()
+            .filter_map(|point| {
+                let (distance, normal) = obstacle.shape.signed_distance(point.0);
+                (distance < 0.0).then_some((point, normal, -distance))
+            })
+            .collect::<Vec<_>>();
+        // Several points in at once (a face flat against a wall) act at their
+        // average, so a square hit doesn't set the body spinning
+        let corner = points
+            .iter()
+            .max_by(|a, b| a.2.total_cmp(&b.2))
+            .map(|(_, normal, depth)| {
+                let sum = points
+                    .iter()
+                    .fold(Vector3::default(), |sum, point| sum + point.0 .0);
+                (
+                    Position(sum.scalar_div(points.len() as f64)),
+                    *normal,
+                    *depth,
+                )
+            });
+
+        let nearest = centre - outward.scalar_mul(centre_distance);
+        let face = (centre_distance < 0.0 || body.contains(Position(nearest))).then(|| {
+            // How far the body reaches from its centre along the normal, it
+            // has to be moved until that reach no longer crosses the surface
+            let reach = (centre - body.support(-outward).0).dot(&outward);
+            (Position(nearest), outward, reach - centre_distance)
+        });
+
+        corner
+            .into_iter()
+            .chain(face)
+            .max_by(|a, b| a.2.total_cmp(&b.2))
+    }
+
+    /// Checks `body` against every obstacle, pushing it out of any it has
+    /// flown into and applying the impact impulses.
+    pub fn resolve(&self, body: &mut RigidBody) -> Vec<ObstacleContact> {
+        let mut contacts = Vec::new();
+        for obstacle in &self.obstacles {
+            let Some((point, normal, penetration)) = self.detect(obstacle, body) else {
+                continue;
+            };
+            let (point, impact_speed) = respond(
+                body,
+                point,
+                normal,
+                penetration,
+                obstacle.restitution,
+                obstacle.friction,
+            );
+            contacts.push(ObstacleContact {
+                obstacle: obstacle.id,
+                point,
+                normal,
+                penetration,
+                impact_speed,
+            });
+        }
+        contacts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::state::linear_velocity::LinearVelocity;
+
+    #[test]
+    fn test_signed_distance_of_each_shape() {
+        let x = Vector3::new(3.0, 0.0, 0.0);
+        let shapes = [
+            ObstacleShape::Aabb {
+                min: Vector3::new(-1.0, -1.0, -1.0),
+                max: Vector3::new(1.0, 1.0, 1.0),
+            },
+            ObstacleShape::Sphere {
+                centre: Vector3::default(),
+                radius: 1.0,
+            },
+            ObstacleShape::Cylinder {
+                a: Vector3::new(0.0, -5.0, 0.0),
+                b: Vector3::new(0.0, 5.0, 0.0),
+                radius: 1.0,
+            },
+            ObstacleShape::Capsule {
+                a: Vector3::new(0.0, -5.0, 0.0),
+                b: Vector3::new(0.0, 5.0, 0.0),
+                radius: 1.0,
+            },
+        ];
+        for shape in shapes {
+            let (distance, normal) = shape.signed_distance(x);
+            assert!((distance - 2.0).abs() < 1e-9, "{shape:?}");
+            assert!((normal - Vector3::new(1.0, 0.0, 0.0)).magnitude() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_body_bounces_off_wall() {
+        let mut world = World::new();
+        let wall = world.add(ObstacleShape::Aabb {
+            min: Vector3::new(1.0, -5.0, -5.0),
+            max: Vector3::new(2.0, 5.0, 5.0),
+        });
+        let mut body = RigidBody::new(0.2, 0.4, 0.4);
+        body.mass = 1.0;
+        body.position.x = 0.85;
+        body.linear_velocity = LinearVelocity::new(3.0, 0.0, 0.0);
+
+        let contacts = world.resolve(&mut body);
+
+        assert_eq!(contacts.len(), 1);
+        assert_eq!(contacts[0].obstacle, wall);
+        assert!((contacts[0].impact_speed - 3.0).abs() < 1e-9);
+        assert!(body.linear_velocity.x < 0.0);
+        assert!(body.position.x <= 0.8 + 1e-9);
+    }
+}
@@ -0,0 +1,194 @@
+//! # Overview
+//!
+//! Tethers, ropes or rods tying the drone to a suspended payload or to a
+//! fixed anchor on the ground.
+//!
+//! # Rope vs rod
+//! A rope only pulls. While the two ends are closer than its length it's
+//! *slack* and does nothing at all, the payload swings freely. Once the ends
+//! reach its length it goes *taut* and stops them moving further apart.
+//!
+//! A rod holds the ends at exactly its length, it can push as well as pull.
+//!
+//! Like contacts these are solved with impulses, any velocity stretching the
+//! tether is removed at once and the stretch itself is corrected, split
+//! between the two ends by how light they are.
+
+use std::time::Duration;
+
+use crate::{
+    physics::{
+        body::RigidBody, collision::impulse_denominator, state::position::Position,
+        util::vector::Vector3,
+    },
+    EleaError, Result,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TetherKind {
+    #[default]
+    Rope,
+    Rod,
+}
+
+/// What the far end of a tether is tied to.
+#[derive(Debug, Clone, Copy)]
+pub enum TetherEnd {
+    /// A fixed point in the world, e.g. a ground station
+    Anchor(Position),
+    /// A free body, `index` into the simulator's bodies, tied on at
+    /// `attachment` in that body's frame
+    Body { index: usize, attachment: Vector3 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TetherState {
+    #[default]
+    Slack,
+    Taut,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TetherReport {
+    pub state: TetherState,
+    /// Force along the tether (N), a rod pushing shows as negative tension
+    pub tension: f64,
+    /// Current distance between the two ends (m)
+    pub distance: f64,
+    /// Unit vector from the drone's end towards the far end
+    pub direction: Vector3,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Tether {
+    /// Where the tether is tied on to the drone, in the drone's body frame
+    pub attachment: Vector3,
+    pub other: TetherEnd,
+    /// Length (m), the distance at which a rope goes taut
+    pub length: f64,
+    pub kind: TetherKind,
+}
+
+impl Tether {
+    pub fn rope(attachment: Vector3, other: TetherEnd, length: f64) -> Self {
+        Self {
+            attachment,
+            other,
+            length,
+            kind: TetherKind::Rope,
+        }
+    }
+
+    pub fn rod(attachment: Vector3, other: TetherEnd, length: f64) -> Self {
+        Self {
+            kind: TetherKind::Rod,
+            ..Self::rope(attachment, other, length)
+        }
+    }
+
+    /// Holds the drone and the far end to the tether's length, returning
+    /// the tension it took to do so over the step `dt`.
+    pub fn resolve(
+        &self,
+        drone: &mut RigidBody,
+        bodies: &mut [RigidBody],
+        dt: Duration,
+    ) -> Result<TetherReport> {
+        match self.other {
+            TetherEnd::Anchor(anchor) => Ok(self.solve(drone, None, anchor, dt)),
+            TetherEnd::Body { index, attachment } => {
+                let body = bodies.get_mut(index).ok_or_else(|| {
+                    EleaError::InvalidData(format!("tether tied to missing body {index}"))
+                })?;
+                let point = body.world_point(attachment);
+                Ok(self.solve(drone, Some(body), point, dt))
+            }
+        }
+    }
+
+    fn solve(
+        &self,
+        drone: &mut RigidBody,
+        mut other: Option<&mut RigidBody>,
+        other_point: Position,
+        dt: Duration,
+    ) -> TetherReport {
+        let point = drone.world_point(self.attachment);
+        let span = other_point.0 - point.0;
+        let distance = span.magnitude();
+        let stretch = distance - self.length;
+        let slack = TetherReport {
+            distance,
+            ..Default::default()
+        };
+        if distance < f64::EPSILON || (self.kind == TetherKind::Rope && stretch <= 0.0) {
+            return slack;
+        }
+        let direction = span.scalar_div(distance);
+
+        // Speed the ends are moving apart at, that's what the impulse removes
+        let other_velocity = other
+            .as_deref()
+            .map_or(Vector3::default(), |body| body.velocity_at(other_point).0);
+        let separating = (other_velocity - drone.velocity_at(point).0).dot(&direction);
+        let separating = match self.kind {
+            TetherKind::Rope => separating.max(0.0),
+            TetherKind::Rod => separating,
+        };
+        let denominator = impulse_denominator(drone, point, direction)
+            + other.as_deref().map_or(0.0, |body| {
+                impulse_denominator(body, other_point, direction)
+            });
+        let impulse = separating / denominator;
+        drone.apply_impulse(direction.scalar_mul(impulse), point);
+        if let Some(body) = other.as_deref_mut() {
+            body.apply_impulse(direction.scalar_mul(-impulse), other_point);
+        }
+
+        // Positional correction, the lighter end moves the most and an
+        // anchor doesn't move at all
+        let drone_share = 1.0 / drone.total_mass();
+        let other_share = other.as_deref().map_or(0.0, |body| 1.0 / body.total_mass());
+        let correction = direction.scalar_mul(stretch / (drone_share + other_share));
+        drone.position += Position(correction.scalar_mul(drone_share));
+        if let Some(body) = other {
+            body.position -= Position(correction.scalar_mul(other_share));
+        }
+
+        TetherReport {
+            state: TetherState::Taut,
+            tension: impulse / dt.as_secs_f64(),
+            distance,
+            direction,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::state::linear_velocity::LinearVelocity;
+
+    #[test]
+    fn test_hanging_payload_pulls_with_its_weight() {
+        let anchor = TetherEnd::Anchor(Position(Vector3::new(0.0, 10.0, 0.0)));
+        let tether = Tether::rope(Vector3::default(), anchor, 2.0);
+        let mut payload = RigidBody::new(0.2, 0.2, 0.2);
+        payload.mass = 2.0;
+        payload.position.y = 9.0;
+        let dt = Duration::from_millis(10);
+
+        let report = tether.resolve(&mut payload, &mut [], dt).unwrap();
+        assert_eq!(report.state, TetherState::Slack);
+
+        for _ in 0..300 {
+            payload.step(dt, LinearVelocity::default()).unwrap();
+            tether.resolve(&mut payload, &mut [], dt).unwrap();
+        }
+        payload.step(dt, LinearVelocity::default()).unwrap();
+        let report = tether.resolve(&mut payload, &mut [], dt).unwrap();
+        assert_eq!(report.state, TetherState::Taut);
+        assert!((report.tension - 2.0 * 9.81).abs() < 0.1);
+        assert!((payload.position.y - 8.0).abs() < 1e-6);
+    }
+}
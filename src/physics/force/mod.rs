@@ -1,4 +1,6 @@
+use crate::physics::state::linear_velocity::LinearVelocity;
 use crate::physics::util::types::{Kilograms, MetresPerSecondSquared};
+use crate::physics::AIR_DENSITY;
 
 use crate::{physics::util::vector::Vector3, vector3_newtype};
 
@@ -16,9 +18,12 @@ vector3_newtype!(ForceVector);
 /// Crucially this is **not** earths gravitational *force* it is
 /// it's gravitiational acceleration. We cannot know the force till we
 /// have the mass. If the mass was 1kg, the force is 9.81N.
-const EARTH_GRAVITY_ACCELERATION: MetresPerSecondSquared = 9.81;
+pub(crate) const EARTH_GRAVITY_ACCELERATION: MetresPerSecondSquared = 9.81;
 
-#[derive(Debug)]
+/// Dimensionless drag coefficient *C_d*, a cube sits at roughly 1.05.
+const DRAG_COEFFICIENT: f64 = 1.05;
+
+#[derive(Debug, Default)]
 pub struct Forces {
     // we may need to receive some input about propeller status
     // to determine thrust
@@ -26,14 +31,24 @@ pub struct Forces {
     /// An object of mass *m* will experience a gravitational force (weight)
     ///  of *F = mg* where *g* is earth's gravitational constant of about 9.81 m/s².
     weight: ForceVector,
+    /// Wind is not a force of its own, it shows up here as drag acting
+    /// on the velocity of the body *relative* to the moving air.
     drag: ForceVector,
-    // TODO add wind force!
+    /// Everything else pushing on the body this step, e.g. contact forces.
+    /// Cleared after every step as these are re-calculated each time.
+    external: ForceVector,
 }
 
 impl Forces {
-    pub fn calculate_forces(&mut self, mass: Kilograms) -> crate::Result<ForceVector> {
+    pub fn calculate_forces(
+        &mut self,
+        mass: Kilograms,
+        air_velocity: LinearVelocity,
+        reference_area: f64,
+    ) -> crate::Result<ForceVector> {
         // TODO calc other forces
         self.calculate_gravitational_force(mass);
+        self.calculate_drag_force(air_velocity, reference_area);
 
         Ok(self.net_force())
     }
@@ -50,22 +65,42 @@ impl Forces {
     /// The net force **must** be applied at the right point, with the correct associated torque, to replicate exactly the effects of the
     /// original forces
     pub fn net_force(&self) -> ForceVector {
-        self.thrust + self.weight + self.drag
+        self.thrust + self.weight + self.drag + self.external
+    }
+
+    pub fn thrust(&self) -> ForceVector {
+        self.thrust
+    }
+
+    /// Sets the rotors' thrust (world frame), held until set again.
+    pub fn set_thrust(&mut self, thrust: ForceVector) {
+        self.thrust = thrust;
+    }
+
+    pub fn add_external(&mut self, force: ForceVector) {
+        self.external += force;
+    }
+
+    pub fn clear_external(&mut self) {
+        self.external = ForceVector::default();
     }
 
     #[inline]
     fn calculate_gravitational_force(&mut self, mass: Kilograms) {
-        // This is calculated F = mg purely in the vertical dimension
-        self.weight.y = mass * EARTH_GRAVITY_ACCELERATION;
+        // This is calculated F = mg purely in the vertical dimension, `y` is
+        // up so gravity pulls towards negative `y`
+        self.weight.y = -mass * EARTH_GRAVITY_ACCELERATION;
     }
-}
 
-impl Default for Forces {
-    fn default() -> Self {
-        Self {
-            thrust: ForceVector::default(),
-            weight: ForceVector::default(),
-            drag: ForceVector::default(),
-        }
+    /// Drag is proportional to the velocity squared: *F = ½ρv²C_dA*, acting
+    /// directly against the direction of travel through the air.
+    #[inline]
+    fn calculate_drag_force(&mut self, air_velocity: LinearVelocity, reference_area: f64) {
+        let speed = air_velocity.magnitude();
+        self.drag = ForceVector(
+            air_velocity
+                .0
+                .scalar_mul(-0.5 * AIR_DENSITY * speed * DRAG_COEFFICIENT * reference_area),
+        );
     }
 }
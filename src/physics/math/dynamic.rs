@@ -0,0 +1,357 @@
+use std::ops::{Add, Index, IndexMut, Mul, Neg, Sub};
+
+/// # Overview
+/// A matrix of any size, stored row by row, for when [`Matrix3`](super::Matrix3)
+/// isn't big enough, e.g. the 12 state linear model of the whole drone.
+///
+/// Sizes are only known at runtime, so mixing up the dimensions in `+`, `-`
+/// or `*` is a bug in the caller and panics, the same as indexing a `Vec`
+/// out of bounds.
+///
+/// The sizes here are small (tens of rows), so everything is the plain
+/// textbook algorithm rather than anything clever.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Matrix {
+    rows: usize,
+    columns: usize,
+    data: Vec<f64>,
+}
+
+impl Matrix {
+    pub fn zeros(rows: usize, columns: usize) -> Self {
+        Self {
+            rows,
+            columns,
+            data: vec![0.0; rows * columns],
+        }
+    }
+
+    pub fn identity(size: usize) -> Self {
+        Self::diagonal(&vec![1.0; size])
+    }
+
+    pub fn diagonal(values: &[f64]) -> Self {
+        let mut matrix = Self::zeros(values.len(), values.len());
+        for (i, value) in values.iter().enumerate() {
+            matrix[(i, i)] = *value;
+        }
+        matrix
+    }
+
+    /// # Panics
+    /// If the rows aren't all the same length.
+    pub fn from_rows(rows: &[&[f64]]) -> Self {
+        let columns = rows.first().map_or(0, |row| row.len());
+        assert!(
+            rows.iter().all(|row| row.len() == columns),
+            "rows differ in length"
+        );
+        Self {
+            rows: rows.len(),
+            columns,
+            data: rows.concat(),
+        }
+    }
+
+    /// A single column, e.g. a state vector.
+    pub fn column_vector(values: &[f64]) -> Self {
+        Self {
+            rows: values.len(),
+            columns: 1,
+            data: values.to_vec(),
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// Every entry, row by row.
+    pub fn as_slice(&self) -> &[f64] {
+        &self.data
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut transposed = Self::zeros(self.columns, self.rows);
+        for i in 0..self.rows {
+            for j in 0..self.columns {
+                transposed[(j, i)] = self[(i, j)];
+            }
+        }
+        transposed
+    }
+
+    pub fn scalar_mul(&self, scalar: f64) -> Self {
+        Self {
+            data: self.data.iter().map(|value| value * scalar).collect(),
+            ..*self
+        }
+    }
+
+    /// The largest entry by size, a cheap norm for checking convergence.
+    pub fn max_abs(&self) -> f64 {
+        self.data
+            .iter()
+            .fold(0.0, |max, value| value.abs().max(max))
+    }
+
+    /// The ∞-norm, the largest sum of sizes along a row. Unlike
+    /// [`max_abs`](Self::max_abs) it bounds how much the matrix can grow a
+    /// vector, `|Mv|∞ ≤ |M|∞ |v|∞`.
+    pub fn norm_inf(&self) -> f64 {
+        if self.columns == 0 {
+            return 0.0;
+        }
+        self.data
+            .chunks_exact(self.columns)
+            .map(|row| row.iter().map(|value| value.abs()).sum::<f64>())
+            .fold(0.0, f64::max)
+    }
+
+    /// `(M + Mᵀ) / 2`, rounding error slowly makes matrices that should be
+    /// symmetric not quite so, this puts that right.
+    pub fn symmetrised(&self) -> Self {
+        (self + &self.transpose()).scalar_mul(0.5)
+    }
+
+    /// The `rows` × `columns` block starting at (`row`, `column`).
+    pub fn block(&self, row: usize, column: usize, rows: usize, columns: usize) -> Self {
+        let mut block = Self::zeros(rows, columns);
+        for i in 0..rows {
+            for j in 0..columns {
+                block[(i, j)] = self[(row + i, column + j)];
+            }
+        }
+        block
+    }
+
+    /// Overwrites the block starting at (`row`, `column`) with `block`.
+    pub fn set_block(&mut self, row: usize, column: usize, block: &Matrix) {
+        for i in 0..block.rows {
+            for j in 0..block.columns {
+                self[(row + i, column + j)] = block[(i, j)];
+            }
+        }
+    }
+
+    /// Solves `self * x = rhs` for `x`, `None` if `self` is singular.
+    ///
+    /// Gaussian elimination with partial pivoting, at each column we swap
+    /// the row with the biggest entry to the top, so we never divide by
+    /// something tiny and blow up the rounding error.
+    pub fn solve(&self, rhs: &Matrix) -> Option<Matrix> {
+        assert_eq!(self.rows, self.columns, "can only solve square systems");
+        assert_eq!(self.rows, rhs.rows, "right hand side has the wrong rows");
+        let n = self.rows;
+        let (mut a, mut x) = (self.clone(), rhs.clone());
+        let tolerance = f64::EPSILON * self.max_abs() * n as f64;
+
+        for k in 0..n {
+            let pivot = (k..n)
+                .max_by(|&i, &j| a[(i, k)].abs().total_cmp(&a[(j, k)].abs()))
+                .unwrap_or(k);
+            if a[(pivot, k)].abs() <= tolerance {
+                return None;
+            }
+            a.swap_rows(k, pivot);
+            x.swap_rows(k, pivot);
+            let (top, below) = a.data.split_at_mut((k + 1) * n);
+            let pivot_row = &top[k * n..];
+            let (x_top, x_below) = x.data.split_at_mut((k + 1) * x.columns);
+            let x_pivot_row = &x_top[k * x.columns..];
+            for (row, x_row) in below
+                .chunks_exact_mut(n)
+                .zip(x_below.chunks_exact_mut(x.columns))
+            {
+                let factor = row[k] / pivot_row[k];
+                if factor == 0.0 {
+                    continue;
+                }
+                for (value, pivot_value) in row[k..].iter_mut().zip(&pivot_row[k..]) {
+                    *value -= factor * pivot_value;
+                }
+                for (value, pivot_value) in x_row.iter_mut().zip(x_pivot_row) {
+                    *value -= factor * pivot_value;
+                }
+            }
+        }
+
+        // Back substitution, bottom row first
+        for i in (0..n).rev() {
+            for j in 0..x.columns {
+                let known: f64 = (i + 1..n).map(|k| a[(i, k)] * x[(k, j)]).sum();
+                x[(i, j)] = (x[(i, j)] - known) / a[(i, i)];
+            }
+        }
+        Some(x)
+    }
+
+    /// `None` when the matrix is singular (has no inverse).
+    pub fn inverse(&self) -> Option<Matrix> {
+        self.solve(&Self::identity(self.rows))
+    }
+
+    /// The matrix exponential `eᴹ = I + M + M²/2! + ...`
+    ///
+    /// The series only converges quickly for small `M`, so we *scale and
+    /// square*: halve `M` until its ∞-norm is small, sum the series, then
+    /// square the result back up again as `eᴹ = (e^(M/2))²`.
+    pub fn exp(&self) -> Matrix {
+        assert_eq!(
+            self.rows, self.columns,
+            "can only exponentiate square matrices"
+        );
+        let squarings = self.norm_inf().max(1.0).log2().ceil() as i32 + 1;
+        let scaled = self.scalar_mul(0.5f64.powi(squarings));
+
+        let mut result = Self::identity(self.rows);
+        let mut term = Self::identity(self.rows);
+        for k in 1..=12 {
+            term = (&term * &scaled).scalar_mul(1.0 / k as f64);
+            result = &result + &term;
+        }
+        for _ in 0..squarings {
+            result = &result * &result;
+        }
+        result
+    }
+
+    fn swap_rows(&mut self, a: usize, b: usize) {
+        for j in 0..self.columns {
+            self.data.swap(a * self.columns + j, b * self.columns + j);
+        }
+    }
+}
+
+impl Index<(usize, usize)> for Matrix {
+    type Output = f64;
+    fn index(&self, (row, column): (usize, usize)) -> &Self::Output {
+        assert!(
+            row < self.rows && column < self.columns,
+            "index out of bounds"
+        );
+        &self.data[row * self.columns + column]
+    }
+}
+
+impl IndexMut<(usize, usize)> for Matrix {
+    fn index_mut(&mut self, (row, column): (usize, usize)) -> &mut Self::Output {
+        assert!(
+            row < self.rows && column < self.columns,
+            "index out of bounds"
+        );
+        &mut self.data[row * self.columns + column]
+    }
+}
+
+impl Add for &Matrix {
+    type Output = Matrix;
+    fn add(self, rhs: &Matrix) -> Self::Output {
+        assert_eq!((self.rows, self.columns), (rhs.rows, rhs.columns));
+        Matrix {
+            data: self
+                .data
+                .iter()
+                .zip(&rhs.data)
+                .map(|(a, b)| a + b)
+                .collect(),
+            ..*self
+        }
+    }
+}
+
+impl Sub for &Matrix {
+    type Output = Matrix;
+    fn sub(self, rhs: &Matrix) -> Self::Output {
+        assert_eq!((self.rows, self.columns), (rhs.rows, rhs.columns));
+        Matrix {
+            data: self
+                .data
+                .iter()
+                .zip(&rhs.data)
+                .map(|(a, b)| a - b)
+                .collect(),
+            ..*self
+        }
+    }
+}
+
+impl Neg for &Matrix {
+    type Output = Matrix;
+    fn neg(self) -> Self::Output {
+        self.scalar_mul(-1.0)
+    }
+}
+
+impl Mul for &Matrix {
+    type Output = Matrix;
+    fn mul(self, rhs: &Matrix) -> Self::Output {
+        assert_eq!(self.columns, rhs.rows, "inner dimensions differ");
+        let mut product = Matrix::zeros(self.rows, rhs.columns);
+        if self.columns == 0 || rhs.columns == 0 {
+            return product;
+        }
+        // Row by row on the raw storage, this is the hot loop of every
+        // solver built on top
+        for (row, out) in self
+            .data
+            .chunks_exact(self.columns)
+            .zip(product.data.chunks_exact_mut(rhs.columns))
+        {
+            for (a, rhs_row) in row.iter().zip(rhs.data.chunks_exact(rhs.columns)) {
+                if *a == 0.0 {
+                    continue;
+                }
+                for (value, b) in out.iter_mut().zip(rhs_row) {
+                    *value += a * b;
+                }
+            }
+        }
+        product
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solve_and_inverse() {
+        // Needs a row swap, the first pivot is zero
+        let a = Matrix::from_rows(&[&[0.0, 2.0, 1.0], &[1.0, 1.0, 0.0], &[3.0, 0.0, 1.0]]);
+        let b = Matrix::column_vector(&[3.0, 2.0, 4.0]);
+
+        let x = a.solve(&b).unwrap();
+        assert!((&(&a * &x) - &b).max_abs() < 1e-12);
+        let identity = &a * &a.inverse().unwrap();
+        assert!((&identity - &Matrix::identity(3)).max_abs() < 1e-12);
+
+        let singular = Matrix::from_rows(&[&[1.0, 2.0], &[2.0, 4.0]]);
+        assert!(singular.inverse().is_none());
+    }
+
+    #[test]
+    fn test_exp_of_rotation_generator() {
+        // e^(θ [[0, -1], [1, 0]]) is the 2D rotation by θ
+        let theta = 2.5;
+        let generator = Matrix::from_rows(&[&[0.0, -theta], &[theta, 0.0]]);
+        let rotation =
+            Matrix::from_rows(&[&[theta.cos(), -theta.sin()], &[theta.sin(), theta.cos()]]);
+        assert!((&generator.exp() - &rotation).max_abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_exp_of_many_small_entries() {
+        // Every entry is small but together they add up, with `J` all ones
+        // `e^J = I + (eⁿ - 1)/n J`
+        let n = 20;
+        let ones = Matrix::from_rows(&vec![vec![1.0; n].as_slice(); n]);
+        let expected = &Matrix::identity(n) + &ones.scalar_mul((n as f64).exp_m1() / n as f64);
+        let error = (&ones.exp() - &expected).max_abs() / expected.max_abs();
+        assert!(error < 1e-12, "{error}");
+    }
+}
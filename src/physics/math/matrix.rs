@@ -0,0 +1,201 @@
+use std::ops::{Add, Mul, Sub};
+
+use crate::physics::util::vector::Vector3;
+
+use super::Quaternion;
+
+/// # Overview
+/// A 3x3 matrix, stored row by row.
+///
+/// We mostly use these for two things:
+/// - *inertia tensors*, rotation's version of mass. Unlike mass it depends
+///   on the axis you spin about, and spinning about one axis can even push
+///   the body to turn about another, hence needing a full matrix.
+/// - *rotation matrices*, whose columns are where the body's `x`, `y` and
+///   `z` axes point in the world.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Matrix3 {
+    pub rows: [[f64; 3]; 3],
+}
+
+impl Matrix3 {
+    pub const fn new(rows: [[f64; 3]; 3]) -> Self {
+        Self { rows }
+    }
+
+    pub const fn identity() -> Self {
+        Self::diagonal(Vector3 {
+            x: 1.0,
+            y: 1.0,
+            z: 1.0,
+        })
+    }
+
+    pub const fn diagonal(v: Vector3) -> Self {
+        Self::new([[v.x, 0.0, 0.0], [0.0, v.y, 0.0], [0.0, 0.0, v.z]])
+    }
+
+    /// Builds a matrix from its three columns.
+    pub fn from_columns(x: Vector3, y: Vector3, z: Vector3) -> Self {
+        Self::new([[x.x, y.x, z.x], [x.y, y.y, z.y], [x.z, y.z, z.z]])
+    }
+
+    /// `a bᵀ`, every component of `a` times every component of `b`.
+    pub fn outer(a: Vector3, b: Vector3) -> Self {
+        let (a, b) = ([a.x, a.y, a.z], [b.x, b.y, b.z]);
+        Self::new(std::array::from_fn(|i| {
+            std::array::from_fn(|j| a[i] * b[j])
+        }))
+    }
+
+    /// The matrix form of `v ×`, so `skew(v) * u == v.cross(&u)`.
+    pub fn skew(v: Vector3) -> Self {
+        Self::new([[0.0, -v.z, v.y], [v.z, 0.0, -v.x], [-v.y, v.x, 0.0]])
+    }
+
+    pub fn column(&self, index: usize) -> Vector3 {
+        Vector3::new(
+            self.rows[0][index],
+            self.rows[1][index],
+            self.rows[2][index],
+        )
+    }
+
+    pub fn transpose(&self) -> Self {
+        Self::new(std::array::from_fn(|i| {
+            std::array::from_fn(|j| self.rows[j][i])
+        }))
+    }
+
+    pub fn trace(&self) -> f64 {
+        self.rows[0][0] + self.rows[1][1] + self.rows[2][2]
+    }
+
+    pub fn scalar_mul(&self, scalar: f64) -> Self {
+        Self::new(self.rows.map(|row| row.map(|value| value * scalar)))
+    }
+
+    pub fn determinant(&self) -> f64 {
+        let m = &self.rows;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    /// `None` when the matrix is singular (has no inverse).
+    pub fn inverse(&self) -> Option<Self> {
+        let determinant = self.determinant();
+        if determinant.abs() < f64::EPSILON {
+            return None;
+        }
+        let m = &self.rows;
+        // Transposed matrix of cofactors
+        let cofactor = |r0: usize, r1: usize, c0: usize, c1: usize| {
+            m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+        };
+        let adjugate = [
+            [
+                cofactor(1, 2, 1, 2),
+                -cofactor(0, 2, 1, 2),
+                cofactor(0, 1, 1, 2),
+            ],
+            [
+                -cofactor(1, 2, 0, 2),
+                cofactor(0, 2, 0, 2),
+                -cofactor(0, 1, 0, 2),
+            ],
+            [
+                cofactor(1, 2, 0, 1),
+                -cofactor(0, 2, 0, 1),
+                cofactor(0, 1, 0, 1),
+            ],
+        ];
+        Some(Self::new(adjugate).scalar_mul(1.0 / determinant))
+    }
+
+    /// The rotation matrix turning the same way as a unit quaternion.
+    pub fn from_quaternion(q: &Quaternion) -> Self {
+        let (w, x, y, z) = (q.w(), q.x, q.y, q.z);
+        Self::new([
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+            ],
+        ])
+    }
+
+    /// Undoes [`Matrix3::skew`], pulling the vector back out of a skew
+    /// symmetric matrix.
+    pub fn vee(&self) -> Vector3 {
+        Vector3::new(self.rows[2][1], self.rows[0][2], self.rows[1][0])
+    }
+}
+
+impl Add for Matrix3 {
+    type Output = Matrix3;
+    fn add(self, rhs: Matrix3) -> Self::Output {
+        Matrix3::new(std::array::from_fn(|i| {
+            std::array::from_fn(|j| self.rows[i][j] + rhs.rows[i][j])
+        }))
+    }
+}
+
+impl Sub for Matrix3 {
+    type Output = Matrix3;
+    fn sub(self, rhs: Matrix3) -> Self::Output {
+        Matrix3::new(std::array::from_fn(|i| {
+            std::array::from_fn(|j| self.rows[i][j] - rhs.rows[i][j])
+        }))
+    }
+}
+
+impl Mul<Matrix3> for Matrix3 {
+    type Output = Matrix3;
+    fn mul(self, rhs: Matrix3) -> Self::Output {
+        Matrix3::new(std::array::from_fn(|i| {
+            std::array::from_fn(|j| (0..3).map(|k| self.rows[i][k] * rhs.rows[k][j]).sum())
+        }))
+    }
+}
+
+impl Mul<Vector3> for Matrix3 {
+    type Output = Vector3;
+    fn mul(self, rhs: Vector3) -> Self::Output {
+        let row = |r: [f64; 3]| r[0] * rhs.x + r[1] * rhs.y + r[2] * rhs.z;
+        Vector3::new(row(self.rows[0]), row(self.rows[1]), row(self.rows[2]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inverse_undoes_matrix() {
+        let m = Matrix3::new([[2.0, 1.0, 0.0], [0.0, 3.0, 1.0], [1.0, 0.0, 4.0]]);
+        let v = Vector3::new(1.0, -2.0, 0.5);
+
+        let back = m.inverse().unwrap() * (m * v);
+        assert!((back - v).magnitude() < 1e-12);
+    }
+
+    #[test]
+    fn test_rotation_matrix_matches_quaternion() {
+        let q = Quaternion::new(40.0, 0.0, 0.6, 0.8);
+        let v = Vector3::new(1.0, 2.0, 3.0);
+
+        let by_matrix = Matrix3::from_quaternion(&q) * v;
+        assert!((by_matrix - q.rotate(v)).magnitude() < 1e-12);
+    }
+}
@@ -1,2 +1,8 @@
+mod dynamic;
+mod matrix;
+mod noise;
 mod quaternion;
+pub use dynamic::Matrix;
+pub use matrix::Matrix3;
+pub use noise::GradientNoise;
 pub use quaternion::Quaternion;
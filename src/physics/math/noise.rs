@@ -0,0 +1,140 @@
+//! Gradient noise, smooth pseudo random values that vary continuously
+//! through space. Used anywhere we want natural looking variation that is
+//! still repeatable from a seed, like turbulence or rolling terrain.
+
+/// SplitMix64, a tiny deterministic generator so a seed always gives the
+/// same field. We don't need anything cryptographic for shuffling a table.
+fn split_mix(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// # Overview
+/// Ken Perlin's improved gradient noise in 3D.
+///
+/// Space is cut into a lattice of unit cubes, each lattice corner gets a
+/// pseudo random gradient (picked from a shuffled permutation table) and
+/// the value at a point is the smooth blend of the dot products between
+/// each corner's gradient and the offset to that corner.
+///
+/// The output is continuous, roughly within `[-1, 1]` and zero on every
+/// lattice corner.
+#[derive(Debug, Clone)]
+pub struct GradientNoise {
+    permutation: [u8; 512],
+}
+
+impl GradientNoise {
+    pub fn new(seed: u64) -> Self {
+        let mut table: [u8; 256] = std::array::from_fn(|i| i as u8);
+        let mut state = seed;
+        // Fisher-Yates shuffle
+        for i in (1..table.len()).rev() {
+            let j = (split_mix(&mut state) % (i as u64 + 1)) as usize;
+            table.swap(i, j);
+        }
+        Self {
+            permutation: std::array::from_fn(|i| table[i % 256]),
+        }
+    }
+
+    pub fn sample(&self, x: f64, y: f64, z: f64) -> f64 {
+        let (xi, yi, zi) = (lattice(x), lattice(y), lattice(z));
+        let (x, y, z) = (x - x.floor(), y - y.floor(), z - z.floor());
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+
+        let p = &self.permutation;
+        let a = p[xi] as usize + yi;
+        let aa = p[a] as usize + zi;
+        let ab = p[a + 1] as usize + zi;
+        let b = p[xi + 1] as usize + yi;
+        let ba = p[b] as usize + zi;
+        let bb = p[b + 1] as usize + zi;
+
+        lerp(
+            w,
+            lerp(
+                v,
+                lerp(u, grad(p[aa], x, y, z), grad(p[ba], x - 1.0, y, z)),
+                lerp(
+                    u,
+                    grad(p[ab], x, y - 1.0, z),
+                    grad(p[bb], x - 1.0, y - 1.0, z),
+                ),
+            ),
+            lerp(
+                v,
+                lerp(
+                    u,
+                    grad(p[aa + 1], x, y, z - 1.0),
+                    grad(p[ba + 1], x - 1.0, y, z - 1.0),
+                ),
+                lerp(
+                    u,
+                    grad(p[ab + 1], x, y - 1.0, z - 1.0),
+                    grad(p[bb + 1], x - 1.0, y - 1.0, z - 1.0),
+                ),
+            ),
+        )
+    }
+
+    /// Several octaves of noise layered on top of each other (fractal
+    /// Brownian motion). Each octave is `lacunarity` times finer and
+    /// `persistence` times weaker than the last, normalised so the octaves'
+    /// amplitudes sum to one.
+    pub fn fractal(
+        &self,
+        x: f64,
+        y: f64,
+        z: f64,
+        octaves: u32,
+        persistence: f64,
+        lacunarity: f64,
+    ) -> f64 {
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        let mut normaliser = 0.0;
+        for _ in 0..octaves.max(1) {
+            total += amplitude * self.sample(x * frequency, y * frequency, z * frequency);
+            normaliser += amplitude;
+            amplitude *= persistence;
+            frequency *= lacunarity;
+        }
+        total / normaliser
+    }
+}
+
+/// Which lattice cell (wrapped to the table size) a coordinate falls in.
+#[inline]
+fn lattice(value: f64) -> usize {
+    (value.floor() as i64).rem_euclid(256) as usize
+}
+
+/// `6t⁵ - 15t⁴ + 10t³`, eases the blend so the noise has no visible seams.
+#[inline]
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+#[inline]
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+/// Dot product of the offset with one of the 12 cube edge gradients picked
+/// by the low bits of `hash`.
+#[inline]
+fn grad(hash: u8, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = match h {
+        0..=3 => y,
+        12 | 14 => x,
+        _ => z,
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
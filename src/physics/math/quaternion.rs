@@ -1,4 +1,6 @@
-use crate::physics::util::types::Angle;
+use std::ops::Mul;
+
+use crate::physics::util::{types::Angle, vector::Vector3};
 
 /// # Overview
 /// A quaternion is used in this project to represent a rotation in 3D space.
@@ -69,7 +71,104 @@ impl Quaternion {
             z: z * half_angle.sin(),
         }
     }
+
+    /// Builds a quaternion straight from its four components, `w` being the
+    /// scalar (real) part.
+    pub fn from_components(w: f64, x: f64, y: f64, z: f64) -> Self {
+        Self {
+            angle: Angle(w),
+            x,
+            y,
+            z,
+        }
+    }
+
+    /// A rotation vector points along the axis of rotation and its
+    /// magnitude is the angle to rotate by, in radians.
+    pub fn from_rotation_vector(rotation: Vector3) -> Self {
+        let angle = rotation.magnitude();
+        if angle < f64::EPSILON {
+            return Self::default();
+        }
+        let half_angle = angle / 2.0;
+        let axis = rotation.scalar_div(angle).scalar_mul(half_angle.sin());
+        Self::from_components(half_angle.cos(), axis.x, axis.y, axis.z)
+    }
+
+    /// Undoes [`Quaternion::from_rotation_vector`], taking the short way
+    /// round so the angle is never more than half a turn.
+    pub fn to_rotation_vector(&self) -> Vector3 {
+        let sin_half = self.vector().magnitude();
+        if sin_half < f64::EPSILON {
+            return Vector3::default();
+        }
+        let direction = match self.w() < 0.0 {
+            true => -1.0,
+            false => 1.0,
+        };
+        let angle = 2.0 * sin_half.atan2(self.w().abs());
+        self.vector().scalar_mul(direction * angle / sin_half)
+    }
+
+    /// The scalar (real) part *ω*
+    #[inline]
+    pub fn w(&self) -> f64 {
+        self.angle.0
+    }
+
+    /// The vector (imaginary) part `(x,y,z)`
+    #[inline]
+    pub fn vector(&self) -> Vector3 {
+        Vector3::new(self.x, self.y, self.z)
+    }
+
+    /// For a unit quaternion this is the inverse rotation, we just flip the axis.
+    pub fn conjugate(&self) -> Self {
+        Self::from_components(self.w(), -self.x, -self.y, -self.z)
+    }
+
+    pub fn norm(&self) -> f64 {
+        (self.w() * self.w() + self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
+    }
+
+    /// Floating point error slowly drifts a quaternion away from unit
+    /// length as we integrate, this pulls it back.
+    pub fn normalised(&self) -> Self {
+        let norm = self.norm();
+        Self::from_components(self.w() / norm, self.x / norm, self.y / norm, self.z / norm)
+    }
+
+    /// Rotates `v` by this quaternion, the same as `q * v * q⁻¹` but cheaper:
+    ///
+    /// `v' = v + 2ω(u × v) + 2u × (u × v)` where `u` is the vector part.
+    pub fn rotate(&self, v: Vector3) -> Vector3 {
+        let u = self.vector();
+        let uv = u.cross(&v);
+        v + uv.scalar_mul(2.0 * self.w()) + u.cross(&uv).scalar_mul(2.0)
+    }
+
+    /// Advances an orientation by a body frame angular velocity (rad/s)
+    /// held for `dt` seconds.
+    ///
+    /// We rotate by the exact rotation `ω * dt` rather than adding the
+    /// derivative, so we only ever renormalise away rounding error.
+    pub fn integrate(&self, angular_velocity: Vector3, dt: f64) -> Self {
+        (*self * Self::from_rotation_vector(angular_velocity.scalar_mul(dt))).normalised()
+    }
 }
+
+/// The Hamilton product, `a * b` is the rotation `b` followed by `a`
+/// when rotating vectors in a fixed frame (or `a` then `b` in the body frame).
+impl Mul<Quaternion> for Quaternion {
+    type Output = Quaternion;
+    fn mul(self, rhs: Quaternion) -> Self::Output {
+        let (a, b) = (self.vector(), rhs.vector());
+        let w = self.w() * rhs.w() - a.dot(&b);
+        let v = b.scalar_mul(self.w()) + a.scalar_mul(rhs.w()) + a.cross(&b);
+        Quaternion::from_components(w, v.x, v.y, v.z)
+    }
+}
+
 impl Default for Quaternion {
     fn default() -> Self {
         Self {
@@ -86,6 +185,7 @@ mod tests {
     use super::*;
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_quaternion_90_degrees_z_axis() {
         let quaternion = Quaternion::new(90.0, 0.0, 0.0, 1.0);
 
@@ -94,4 +194,12 @@ mod tests {
         // Check z component
         assert!((quaternion.z - 0.7071).abs() < 0.001);
     }
+
+    #[test]
+    fn test_rotate_90_degrees_about_z() {
+        let quaternion = Quaternion::new(90.0, 0.0, 0.0, 1.0);
+        let rotated = quaternion.rotate(Vector3::new(1.0, 0.0, 0.0));
+
+        assert!((rotated - Vector3::new(0.0, 1.0, 0.0)).magnitude() < 1e-9);
+    }
 }
@@ -1,9 +1,12 @@
 pub mod body;
+pub mod collision;
+pub mod constraint;
 pub mod force;
 pub mod math;
+pub mod shape;
 pub mod state;
 pub mod torque;
-mod util;
+pub mod util;
 
 pub const AIR_DENSITY: f64 = 2.0;
 pub const WEIGHT: util::types::Kilograms = 200.0;
@@ -0,0 +1,594 @@
+//! # Overview
+//!
+//! The solid shapes a [`RigidBody`](crate::physics::body::RigidBody) can be
+//! made of, from simple primitives up to compounds of several parts (arms,
+//! a canopy and a payload pod, say) and convex hulls wrapped around a cloud
+//! of points.
+//!
+//! Every shape lives in its own frame, which is the body frame once attached
+//! to a body. Shapes are solids of uniform density, so the mass properties
+//! only need the body's total mass to work out.
+//!
+//! # Support function
+//! Collision leans on [`Shape::support`], the point of the shape furthest
+//! along a direction. It's cheap for every shape here and is all you need to
+//! know how far a convex shape reaches in any direction, e.g. its bounding
+//! box is just the support along `±x`, `±y` and `±z`.
+
+use crate::{
+    physics::{
+        math::{Matrix3, Quaternion},
+        util::vector::Vector3,
+    },
+    EleaError, Result,
+};
+
+/// How many points we sample around a cylinder's rim when it sits flat.
+const RIM_POINTS: usize = 8;
+
+#[derive(Debug, Clone)]
+pub enum Shape {
+    /// Box centred on the origin, `half_extents` from the centre to each face
+    Box {
+        half_extents: Vector3,
+    },
+    Sphere {
+        radius: f64,
+    },
+    /// Cylinder standing along `y`, `half_height` from the centre to each end
+    Cylinder {
+        radius: f64,
+        half_height: f64,
+    },
+    /// Cylinder along `y` with hemispheres on the ends, `half_height` is from
+    /// the centre to the middle of each hemisphere
+    Capsule {
+        radius: f64,
+        half_height: f64,
+    },
+    /// Several shapes stuck together, all of the same density
+    Compound(Vec<ShapePart>),
+    ConvexHull(ConvexHull),
+}
+
+impl Default for Shape {
+    fn default() -> Self {
+        Self::Box {
+            half_extents: Vector3::default(),
+        }
+    }
+}
+
+/// One part of a [`Shape::Compound`], placed and turned within the
+/// compound's frame.
+#[derive(Debug, Clone)]
+pub struct ShapePart {
+    pub shape: Shape,
+    pub offset: Vector3,
+    pub orientation: Quaternion,
+}
+
+impl ShapePart {
+    pub fn new(shape: Shape, offset: Vector3) -> Self {
+        Self {
+            shape,
+            offset,
+            orientation: Quaternion::default(),
+        }
+    }
+
+    pub fn rotated(mut self, orientation: Quaternion) -> Self {
+        self.orientation = orientation;
+        self
+    }
+
+    /// The part's centre of mass in the compound's frame.
+    pub fn centre_of_mass(&self) -> Vector3 {
+        self.to_compound(self.shape.centre_of_mass())
+    }
+
+    /// Inertia of `mass` kg of the part about `centre`, all in the
+    /// compound's frame.
+    pub fn inertia_about(&self, mass: f64, centre: Vector3) -> Matrix3 {
+        let rotation = Matrix3::from_quaternion(&self.orientation);
+        let own = rotation * self.shape.inertia(mass) * rotation.transpose();
+        parallel_axis(own, mass, self.centre_of_mass() - centre)
+    }
+
+    pub fn support(&self, direction: Vector3) -> Vector3 {
+        self.to_compound(self.shape.support(self.to_part(direction)))
+    }
+
+    pub fn contains(&self, point: Vector3) -> bool {
+        self.shape.contains(self.to_part(point - self.offset))
+    }
+
+    pub fn contact_points(&self, direction: Vector3) -> Vec<Vector3> {
+        self.shape
+            .contact_points(self.to_part(direction))
+            .into_iter()
+            .map(|point| self.to_compound(point))
+            .collect()
+    }
+
+    fn to_compound(&self, v: Vector3) -> Vector3 {
+        self.offset + self.orientation.rotate(v)
+    }
+
+    fn to_part(&self, direction: Vector3) -> Vector3 {
+        self.orientation.conjugate().rotate(direction)
+    }
+}
+
+impl Shape {
+    /// A box with the same arguments as
+    /// [`RigidBody::new`](crate::physics::body::RigidBody::new), `width`
+    /// along `x`, `height` along `y` and `depth` along `z`.
+    pub fn cuboid(height: f64, width: f64, depth: f64) -> Self {
+        Self::Box {
+            half_extents: Vector3::new(width, height, depth).scalar_mul(0.5),
+        }
+    }
+
+    /// The smallest convex shape containing every point, see
+    /// [`ConvexHull::new`].
+    pub fn convex_hull(points: &[Vector3]) -> Result<Self> {
+        ConvexHull::new(points).map(Self::ConvexHull)
+    }
+
+    /// Volume in m³.
+    pub fn volume(&self) -> f64 {
+        use std::f64::consts::PI;
+        match self {
+            Shape::Box { half_extents: h } => 8.0 * h.x * h.y * h.z,
+            Shape::Sphere { radius } => 4.0 / 3.0 * PI * radius.powi(3),
+            Shape::Cylinder {
+                radius,
+                half_height,
+            } => PI * radius * radius * 2.0 * half_height,
+            Shape::Capsule {
+                radius,
+                half_height,
+            } => PI * radius * radius * (2.0 * half_height + 4.0 / 3.0 * radius),
+            Shape::Compound(parts) => parts.iter().map(|part| part.shape.volume()).sum(),
+            Shape::ConvexHull(hull) => hull.mass_properties().0,
+        }
+    }
+
+    /// Centre of mass in the shape's frame. The primitives are all centred
+    /// on the origin, compounds and hulls needn't be.
+    pub fn centre_of_mass(&self) -> Vector3 {
+        match self {
+            Shape::Compound(parts) => {
+                let volume = self.volume();
+                if volume <= 0.0 {
+                    return Vector3::default();
+                }
+                parts
+                    .iter()
+                    .fold(Vector3::default(), |sum, part| {
+                        sum + part.centre_of_mass().scalar_mul(part.shape.volume())
+                    })
+                    .scalar_div(volume)
+            }
+            Shape::ConvexHull(hull) => hull.mass_properties().1,
+            _ => Vector3::default(),
+        }
+    }
+
+    /// Inertia tensor about the centre of mass for a solid of `mass` kg,
+    /// in the shape's frame.
+    ///
+    /// For the primitives this is diagonal, e.g. a box has
+    /// `I_x = m(h² + d²) / 12`. Compounds shift each part's inertia over to
+    /// the shared centre of mass with the parallel axis theorem, which is why
+    /// a drone's arms make it much harder to spin than its body alone.
+    pub fn inertia(&self, mass: f64) -> Matrix3 {
+        match self {
+            Shape::Box { half_extents: h } => {
+                let (x2, y2, z2) = (h.x * h.x, h.y * h.y, h.z * h.z);
+                Matrix3::diagonal(Vector3::new(y2 + z2, x2 + z2, x2 + y2).scalar_mul(mass / 3.0))
+            }
+            Shape::Sphere { radius } => {
+                Matrix3::identity().scalar_mul(0.4 * mass * radius * radius)
+            }
+            Shape::Cylinder {
+                radius,
+                half_height,
+            } => {
+                let (r2, h) = (radius * radius, 2.0 * half_height);
+                let across = mass * (3.0 * r2 + h * h) / 12.0;
+                Matrix3::diagonal(Vector3::new(across, 0.5 * mass * r2, across))
+            }
+            Shape::Capsule {
+                radius,
+                half_height,
+            } => {
+                // The cylinder plus two hemispheres, which together weigh
+                // the same as one sphere but sit out at the ends
+                let volume = self.volume();
+                if volume <= 0.0 {
+                    return Matrix3::default();
+                }
+                let (r, h) = (*radius, 2.0 * half_height);
+                let cylinder = mass * std::f64::consts::PI * r * r * h / volume;
+                let spheres = mass - cylinder;
+                let along = cylinder * r * r / 2.0 + spheres * 0.4 * r * r;
+                let across = cylinder * (h * h / 12.0 + r * r / 4.0)
+                    + spheres * (0.4 * r * r + h * h / 4.0 + 3.0 * h * r / 8.0);
+                Matrix3::diagonal(Vector3::new(across, along, across))
+            }
+            Shape::Compound(parts) => {
+                let volume = self.volume();
+                if volume <= 0.0 {
+                    return Matrix3::default();
+                }
+                let centre = self.centre_of_mass();
+                parts.iter().fold(Matrix3::default(), |sum, part| {
+                    sum + part.inertia_about(mass * part.shape.volume() / volume, centre)
+                })
+            }
+            Shape::ConvexHull(hull) => {
+                let (volume, _, covariance) = hull.mass_properties();
+                if volume <= 0.0 {
+                    return Matrix3::default();
+                }
+                (Matrix3::identity().scalar_mul(covariance.trace()) - covariance)
+                    .scalar_mul(mass / volume)
+            }
+        }
+    }
+
+    /// The point of the shape furthest along `direction`.
+    pub fn support(&self, direction: Vector3) -> Vector3 {
+        let sign = |value: f64| if value < 0.0 { -1.0 } else { 1.0 };
+        match self {
+            Shape::Box { half_extents: h } => Vector3::new(
+                sign(direction.x) * h.x,
+                sign(direction.y) * h.y,
+                sign(direction.z) * h.z,
+            ),
+            Shape::Sphere { radius } => unit_or_zero(direction).scalar_mul(*radius),
+            Shape::Cylinder {
+                radius,
+                half_height,
+            } => {
+                let radial = unit_or_zero(Vector3::new(direction.x, 0.0, direction.z));
+                radial.scalar_mul(*radius) + Vector3::new(0.0, sign(direction.y) * half_height, 0.0)
+            }
+            Shape::Capsule {
+                radius,
+                half_height,
+            } => {
+                Vector3::new(0.0, sign(direction.y) * half_height, 0.0)
+                    + unit_or_zero(direction).scalar_mul(*radius)
+            }
+            Shape::Compound(parts) => {
+                furthest(parts.iter().map(|part| part.support(direction)), direction)
+            }
+            Shape::ConvexHull(hull) => furthest(hull.vertices.iter().copied(), direction),
+        }
+    }
+
+    /// Axis aligned bounding box in the shape's frame, as `(min, max)`.
+    pub fn bounds(&self) -> (Vector3, Vector3) {
+        bounds_from_support(|direction| self.support(direction))
+    }
+
+    /// Whether `point` (in the shape's frame) is inside or on the shape.
+    pub fn contains(&self, point: Vector3) -> bool {
+        match self {
+            Shape::Box { half_extents: h } => {
+                point.x.abs() <= h.x && point.y.abs() <= h.y && point.z.abs() <= h.z
+            }
+            Shape::Sphere { radius } => point.magnitude() <= *radius,
+            Shape::Cylinder {
+                radius,
+                half_height,
+            } => {
+                point.y.abs() <= *half_height
+                    && point.x * point.x + point.z * point.z <= radius * radius
+            }
+            Shape::Capsule {
+                radius,
+                half_height,
+            } => {
+                let along = point.y.clamp(-half_height, *half_height);
+                (point - Vector3::new(0.0, along, 0.0)).magnitude() <= *radius
+            }
+            Shape::Compound(parts) => parts.iter().any(|part| part.contains(point)),
+            Shape::ConvexHull(hull) => hull
+                .planes()
+                .all(|(normal, on_plane)| normal.dot(&(point - on_plane)) <= 0.0),
+        }
+    }
+
+    /// The points that could touch a surface lying in `direction` from the
+    /// shape, which collision averages over whichever have gone through.
+    ///
+    /// For shapes with corners that's the corners, so a box landing flat
+    /// touches on all four and doesn't get knocked spinning. Round shapes
+    /// only ever touch at their support point.
+    pub fn contact_points(&self, direction: Vector3) -> Vec<Vector3> {
+        match self {
+            Shape::Box { half_extents: h } => (0..8)
+                .map(|i| {
+                    let sign = |bit: usize| if i & bit == 0 { -1.0 } else { 1.0 };
+                    Vector3::new(sign(1) * h.x, sign(2) * h.y, sign(4) * h.z)
+                })
+                .collect(),
+            Shape::Sphere { .. } => vec![self.support(direction)],
+            Shape::Cylinder {
+                radius,
+                half_height,
+            } => {
+                // The whole rim, for when it's sat flat on an end, plus the
+                // exact point of each rim furthest along `direction`
+                let radial = Vector3::new(direction.x, 0.0, direction.z);
+                let furthest = (radial.magnitude() > f64::EPSILON)
+                    .then(|| radial.unit_vector().scalar_mul(*radius));
+                [-half_height, *half_height]
+                    .into_iter()
+                    .flat_map(|y| {
+                        (0..RIM_POINTS)
+                            .map(|k| {
+                                let angle = std::f64::consts::TAU * k as f64 / RIM_POINTS as f64;
+                                Vector3::new(radius * angle.cos(), 0.0, radius * angle.sin())
+                            })
+                            .chain(furthest)
+                            .map(move |rim| rim + Vector3::new(0.0, y, 0.0))
+                    })
+                    .collect()
+            }
+            Shape::Capsule {
+                radius,
+                half_height,
+            } => {
+                let out = unit_or_zero(direction).scalar_mul(*radius);
+                vec![
+                    Vector3::new(0.0, -half_height, 0.0) + out,
+                    Vector3::new(0.0, *half_height, 0.0) + out,
+                ]
+            }
+            Shape::Compound(parts) => parts
+                .iter()
+                .flat_map(|part| part.contact_points(direction))
+                .collect(),
+            Shape::ConvexHull(hull) => hull.vertices.clone(),
+        }
+    }
+}
+
+/// # Overview
+/// The smallest convex shape wrapped around a cloud of points, like
+/// stretching cling film around them. Handy for odd shaped parts like a
+/// canopy, given the points off a model.
+///
+/// Stored as the outer points and the triangles between them, each wound
+/// anticlockwise seen from outside.
+#[derive(Debug, Clone)]
+pub struct ConvexHull {
+    vertices: Vec<Vector3>,
+    faces: Vec<[usize; 3]>,
+}
+
+impl ConvexHull {
+    /// Builds the hull incrementally: start from a tetrahedron of four far
+    /// apart points, then for each remaining point outside the hull so far,
+    /// remove the faces it can see and join the edge of that hole up to it.
+    ///
+    /// Fails unless there are four points that don't all lie on one plane.
+    pub fn new(points: &[Vector3]) -> Result<Self> {
+        let degenerate = || {
+            EleaError::InvalidData("a convex hull needs points that aren't all on one plane".into())
+        };
+        let furthest = |score: &dyn Fn(Vector3) -> f64| {
+            (0..points.len())
+                .max_by(|&a, &b| score(points[a]).total_cmp(&score(points[b])))
+                .ok_or_else(degenerate)
+        };
+
+        let p0 = points.first().copied().ok_or_else(degenerate)?;
+        let i1 = furthest(&|p| (p - p0).magnitude())?;
+        let p1 = points[i1];
+        let size = (p1 - p0).magnitude();
+        let tolerance = 1e-9 * size;
+        let i2 = furthest(&|p| (p - p0).cross(&(p1 - p0)).magnitude())?;
+        let plane = (p1 - p0).cross(&(points[i2] - p0));
+        let i3 = furthest(&|p| (p - p0).dot(&plane).abs())?;
+        if size < f64::EPSILON
+            || plane.magnitude() < tolerance * size
+            || (points[i3] - p0).dot(&plane).abs() < tolerance * size * size
+        {
+            return Err(degenerate());
+        }
+
+        let normal = |face: &[usize; 3]| {
+            let [a, b, c] = face.map(|i| points[i]);
+            (b - a).cross(&(c - a)).unit_vector()
+        };
+        let start = [0, i1, i2, i3];
+        let inside = start
+            .iter()
+            .fold(Vector3::default(), |sum, &i| sum + points[i])
+            .scalar_div(4.0);
+        let mut faces = [[0, i1, i2], [0, i3, i1], [i1, i3, i2], [0, i2, i3]]
+            .map(
+                |[a, b, c]| match normal(&[a, b, c]).dot(&(inside - points[a])) > 0.0 {
+                    true => [a, c, b],
+                    false => [a, b, c],
+                },
+            )
+            .to_vec();
+
+        for (index, &point) in points.iter().enumerate() {
+            if start.contains(&index) {
+                continue;
+            }
+            let (visible, hidden): (Vec<_>, Vec<_>) = faces
+                .into_iter()
+                .partition(|face| normal(face).dot(&(point - points[face[0]])) > tolerance);
+            faces = hidden;
+            // The edge of the hole is every edge of a visible face whose
+            // neighbour across it isn't visible
+            let edges = visible
+                .iter()
+                .flat_map(|&[a, b, c]| [(a, b), (b, c), (c, a)])
+                .collect::<Vec<_>>();
+            for &(a, b) in &edges {
+                if !edges.contains(&(b, a)) {
+                    faces.push([a, b, index]);
+                }
+            }
+        }
+
+        // Only keep the points that ended up on the hull
+        let mut used = faces.iter().flatten().copied().collect::<Vec<_>>();
+        used.sort_unstable();
+        used.dedup();
+        let remap = |i: usize| used.binary_search(&i).unwrap_or_default();
+        Ok(Self {
+            vertices: used.iter().map(|&i| points[i]).collect(),
+            faces: faces.iter().map(|face| face.map(remap)).collect(),
+        })
+    }
+
+    pub fn vertices(&self) -> &[Vector3] {
+        &self.vertices
+    }
+
+    pub fn faces(&self) -> &[[usize; 3]] {
+        &self.faces
+    }
+
+    /// Each face as its outward unit normal and a point on it.
+    fn planes(&self) -> impl Iterator<Item = (Vector3, Vector3)> + '_ {
+        self.faces.iter().map(|face| {
+            let [a, b, c] = face.map(|i| self.vertices[i]);
+            ((b - a).cross(&(c - a)).unit_vector(), a)
+        })
+    }
+
+    /// Volume, centre of mass and the covariance `∫ r rᵀ dV` about the
+    /// centre of mass.
+    ///
+    /// The hull is cut into tetrahedra, one from a point inside to each face,
+    /// which all have simple closed form integrals that we add up.
+    fn mass_properties(&self) -> (f64, Vector3, Matrix3) {
+        let inside = self
+            .vertices
+            .iter()
+            .fold(Vector3::default(), |sum, v| sum + *v)
+            .scalar_div(self.vertices.len().max(1) as f64);
+
+        let mut volume = 0.0;
+        let mut moment = Vector3::default();
+        let mut covariance = Matrix3::default();
+        for face in &self.faces {
+            let [a, b, c] = face.map(|i| self.vertices[i] - inside);
+            let v = a.dot(&b.cross(&c)) / 6.0;
+            let sum = a + b + c;
+            volume += v;
+            moment += sum.scalar_mul(v / 4.0);
+            let outer = Matrix3::outer(a, a)
+                + Matrix3::outer(b, b)
+                + Matrix3::outer(c, c)
+                + Matrix3::outer(sum, sum);
+            covariance = covariance + outer.scalar_mul(v / 20.0);
+        }
+        if volume <= 0.0 {
+            return (0.0, inside, Matrix3::default());
+        }
+        let centre = moment.scalar_div(volume);
+        let covariance = covariance - Matrix3::outer(centre, centre).scalar_mul(volume);
+        (volume, inside + centre, covariance)
+    }
+}
+
+/// Axis aligned bounding box of a convex shape, given its support function.
+pub(crate) fn bounds_from_support(support: impl Fn(Vector3) -> Vector3) -> (Vector3, Vector3) {
+    let axis = |x: f64, y: f64, z: f64| support(Vector3::new(x, y, z));
+    let min = Vector3::new(
+        axis(-1.0, 0.0, 0.0).x,
+        axis(0.0, -1.0, 0.0).y,
+        axis(0.0, 0.0, -1.0).z,
+    );
+    let max = Vector3::new(
+        axis(1.0, 0.0, 0.0).x,
+        axis(0.0, 1.0, 0.0).y,
+        axis(0.0, 0.0, 1.0).z,
+    );
+    (min, max)
+}
+
+/// Moves an inertia tensor about a body's own centre of mass to one about
+/// a point `offset` away, the parallel axis theorem `I + m(|d|²𝟙 - d dᵀ)`.
+pub(crate) fn parallel_axis(inertia: Matrix3, mass: f64, offset: Vector3) -> Matrix3 {
+    let shift =
+        Matrix3::identity().scalar_mul(offset.dot(&offset)) - Matrix3::outer(offset, offset);
+    inertia + shift.scalar_mul(mass)
+}
+
+/// Whichever of `points` is furthest along `direction`.
+pub(crate) fn furthest(points: impl Iterator<Item = Vector3>, direction: Vector3) -> Vector3 {
+    points
+        .max_by(|a, b| a.dot(&direction).total_cmp(&b.dot(&direction)))
+        .unwrap_or_default()
+}
+
+fn unit_or_zero(v: Vector3) -> Vector3 {
+    match v.magnitude() > f64::EPSILON {
+        true => v.unit_vector(),
+        false => Vector3::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Matrix3, b: Matrix3) {
+        let difference = a - b;
+        let largest = difference
+            .rows
+            .iter()
+            .flatten()
+            .fold(0.0_f64, |max, value| max.max(value.abs()));
+        assert!(largest < 1e-9, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn test_hull_of_cube_matches_box() {
+        let cube = Shape::cuboid(2.0, 2.0, 2.0);
+        let mut points = cube.contact_points(Vector3::default());
+        // Points inside shouldn't change anything
+        points.push(Vector3::new(0.1, 0.2, -0.3));
+        let hull = Shape::convex_hull(&points).unwrap();
+
+        assert!((hull.volume() - 8.0).abs() < 1e-9);
+        assert!(hull.centre_of_mass().magnitude() < 1e-9);
+        assert_close(hull.inertia(3.0), cube.inertia(3.0));
+        assert!(hull.contains(Vector3::new(0.9, -0.9, 0.9)));
+        assert!(!hull.contains(Vector3::new(1.1, 0.0, 0.0)));
+        assert!(Shape::convex_hull(&[Vector3::default(); 4]).is_err());
+    }
+
+    #[test]
+    fn test_compound_uses_parallel_axis_theorem() {
+        // Two unit spheres 2m either side of the origin, like a dumbbell
+        let sphere = Shape::Sphere { radius: 1.0 };
+        let dumbbell = Shape::Compound(vec![
+            ShapePart::new(sphere.clone(), Vector3::new(2.0, 0.0, 0.0)),
+            ShapePart::new(sphere.clone(), Vector3::new(-2.0, 0.0, 0.0)),
+        ]);
+
+        // Per kilogram, each sphere's own `2r²/5` plus `d²` for the offset
+        let own = 0.4;
+        let expected = Matrix3::diagonal(Vector3::new(own, own + 4.0, own + 4.0)).scalar_mul(4.0);
+        assert_close(dumbbell.inertia(4.0), expected);
+        assert!(dumbbell.centre_of_mass().magnitude() < 1e-12);
+        let (min, max) = dumbbell.bounds();
+        assert!((min - Vector3::new(-3.0, -1.0, -1.0)).magnitude() < 1e-12);
+        assert!((max - Vector3::new(3.0, 1.0, 1.0)).magnitude() < 1e-12);
+    }
+}
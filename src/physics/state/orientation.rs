@@ -1,6 +1,6 @@
 use crate::physics::math::Quaternion;
 
-//// # Overview
+/// # Overview
 /// This refers to the rotation that is needed to
 /// move the object from a reference placement to
 /// its current placement.
@@ -8,12 +8,26 @@ use crate::physics::math::Quaternion;
 /// We have implemented this using an orientation quaternion
 /// (like a normal quaternion but it is rotation relative to a
 /// reference coordinate system)
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Default)]
 #[repr(transparent)]
 pub struct Orientation(Quaternion);
 
-impl Default for Orientation {
-    fn default() -> Self {
-        Self(Quaternion::default())
+impl Orientation {
+    pub fn new(quaternion: Quaternion) -> Self {
+        Self(quaternion)
+    }
+}
+
+impl std::ops::Deref for Orientation {
+    type Target = Quaternion;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl std::ops::DerefMut for Orientation {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
     }
 }
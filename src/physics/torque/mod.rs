@@ -6,7 +6,7 @@ use crate::{physics::util::vector::Vector3, vector3_newtype};
 /// The key components we need are:
 /// - a [Force Vector *F*](crate::physics::force::ForceVector)
 /// - a [Position Vector *r*](crate::physics::state::Position). This is a vector about which the torque is being measured to the point the
-/// force is being applied.
+///   force is being applied.
 ///
 /// Any forces applied at the center of mass will **always** be a *zero vector* - `(0,0,0)`, because our *r* vector goes from the pivot
 /// point to the point where the force is applied, which would be the same point if at center-of-mass.
@@ -146,6 +146,22 @@ impl Vector3 {
             z: self.z / scalar,
         }
     }
+
+    /// Sum of the component-wise product, `|a||b|cos(θ)`.
+    #[inline]
+    pub fn dot(&self, rhs: &Vector3) -> f64 {
+        (self.x * rhs.x) + (self.y * rhs.y) + (self.z * rhs.z)
+    }
+
+    /// Vector perpendicular to both, following the right hand rule.
+    #[inline]
+    pub fn cross(&self, rhs: &Vector3) -> Self {
+        Vector3 {
+            x: (self.y * rhs.z) - (self.z * rhs.y),
+            y: (self.z * rhs.x) - (self.x * rhs.z),
+            z: (self.x * rhs.y) - (self.y * rhs.x),
+        }
+    }
 }
 
 impl std::fmt::Debug for Vector3 {
@@ -260,4 +276,12 @@ mod tests {
         let axis = Vector3::new(6.0, 8.0, 0.0);
         assert_eq!(axis.magnitude(), 10.0);
     }
+
+    #[test]
+    fn test_cross_follows_right_hand_rule() {
+        let x = Vector3::new(1.0, 0.0, 0.0);
+        let y = Vector3::new(0.0, 1.0, 0.0);
+        assert_eq!(x.cross(&y), Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(x.dot(&y), 0.0);
+    }
 }
@@ -0,0 +1,349 @@
+//! Discrete weather events that can be scheduled into a scenario.
+//!
+//! These are the repeatable, worst-case style disturbances used for
+//! certification testing: the same scenario always produces the same wind.
+//! Every event has a `start` time and is silent outside its active window.
+
+use std::{f64::consts::PI, time::Duration};
+
+use crate::{
+    physics::{
+        state::{linear_velocity::LinearVelocity, position::Position},
+        util::vector::Vector3,
+    },
+    EleaError, Result,
+};
+
+use super::WindField;
+
+/// Seconds since `start` if `time` falls within the event, `None` otherwise.
+/// A `duration` of `None` means the event never ends.
+fn active_time(start: Duration, duration: Option<Duration>, time: Duration) -> Option<f64> {
+    let elapsed = time.checked_sub(start)?;
+    match duration {
+        Some(duration) if elapsed > duration => None,
+        _ => Some(elapsed.as_secs_f64()),
+    }
+}
+
+/// Horizontal (`x`,`z`) distance from the vertical axis through `centre` and
+/// the unit vector pointing away from it. The direction is zero on the axis.
+fn radial(centre: Position, position: Position) -> (f64, Vector3) {
+    let offset = Vector3::new(position.x - centre.x, 0.0, position.z - centre.z);
+    let distance = offset.magnitude();
+    match distance > f64::EPSILON {
+        true => (distance, offset.scalar_div(distance)),
+        false => (0.0, Vector3::default()),
+    }
+}
+
+/// # Overview
+/// The classic discrete gust from the certification standards. The wind
+/// smoothly rises from nothing to `peak` and falls back again over `length`:
+///
+/// `v(t) = peak * ½(1 - cos(2πt / length))`
+///
+/// So the gust is at its strongest exactly halfway through.
+#[derive(Debug, Clone, Copy)]
+pub struct OneMinusCosineGust {
+    start: Duration,
+    length: Duration,
+    /// Both the direction and the strongest velocity of the gust (m/s)
+    peak: Vector3,
+}
+
+impl OneMinusCosineGust {
+    pub fn new(start: Duration, length: Duration, peak: Vector3) -> Result<Self> {
+        if length.is_zero() {
+            return Err(EleaError::InvalidData(
+                "one minus cosine gust needs a length".to_string(),
+            ));
+        }
+        Ok(Self {
+            start,
+            length,
+            peak,
+        })
+    }
+}
+
+impl WindField for OneMinusCosineGust {
+    fn wind_at(&self, _position: Position, time: Duration) -> LinearVelocity {
+        let Some(t) = active_time(self.start, Some(self.length), time) else {
+            return LinearVelocity::default();
+        };
+        let shape = 0.5 * (1.0 - (2.0 * PI * t / self.length.as_secs_f64()).cos());
+        LinearVelocity(self.peak.scalar_mul(shape))
+    }
+}
+
+/// # Overview
+/// A sudden change in wind. The velocity ramps linearly up to `velocity`
+/// over `rise` (a zero `rise` is a true step) and then holds.
+///
+/// If `duration` is set the wind cuts out again once it has passed,
+/// measured from `start`.
+#[derive(Debug, Clone, Copy)]
+pub struct StepGust {
+    pub start: Duration,
+    pub rise: Duration,
+    pub duration: Option<Duration>,
+    pub velocity: Vector3,
+}
+
+impl WindField for StepGust {
+    fn wind_at(&self, _position: Position, time: Duration) -> LinearVelocity {
+        let Some(t) = active_time(self.start, self.duration, time) else {
+            return LinearVelocity::default();
+        };
+        let rise = self.rise.as_secs_f64();
+        let shape = match rise > 0.0 {
+            true => (t / rise).min(1.0),
+            false => 1.0,
+        };
+        LinearVelocity(self.velocity.scalar_mul(shape))
+    }
+}
+
+/// # Overview
+/// A rising column of warm air. The column is vertical, centred on
+/// `centre` and extends from the ground, the `y` of `centre`, up to
+/// `ceiling` metres above it.
+///
+/// # Updraft profile
+/// With `r` as the horizontal distance from the centre and `R` the `radius`:
+///
+/// `w(r) = peak_updraft * e^(-(r/R)²) * (1 - (r/R)²)`
+///
+/// This is strongest in the core, crosses zero at the radius and then
+/// becomes a weak sink around the outside, the air that went up has to
+/// come back down somewhere!
+///
+/// The updraft fades out linearly over the top tenth of the column.
+#[derive(Debug, Clone, Copy)]
+pub struct Thermal {
+    start: Duration,
+    duration: Option<Duration>,
+    centre: Position,
+    radius: f64,
+    peak_updraft: f64,
+    ceiling: f64,
+}
+
+impl Thermal {
+    pub fn new(
+        start: Duration,
+        duration: Option<Duration>,
+        centre: Position,
+        radius: f64,
+        peak_updraft: f64,
+        ceiling: f64,
+    ) -> Result<Self> {
+        if !(radius > 0.0 && ceiling > 0.0) {
+            return Err(EleaError::InvalidData(format!(
+                "thermal radius ({radius}) and ceiling ({ceiling}) must be positive"
+            )));
+        }
+        Ok(Self {
+            start,
+            duration,
+            centre,
+            radius,
+            peak_updraft,
+            ceiling,
+        })
+    }
+}
+
+impl WindField for Thermal {
+    fn wind_at(&self, position: Position, time: Duration) -> LinearVelocity {
+        let height = position.y - self.centre.y;
+        if active_time(self.start, self.duration, time).is_none()
+            || height < 0.0
+            || height > self.ceiling
+        {
+            return LinearVelocity::default();
+        }
+        let (distance, _) = radial(self.centre, position);
+        let ratio = (distance / self.radius).powi(2);
+        let fade = ((self.ceiling - height) / (0.1 * self.ceiling)).min(1.0);
+
+        LinearVelocity::new(
+            0.0,
+            self.peak_updraft * (-ratio).exp() * (1.0 - ratio) * fade,
+            0.0,
+        )
+    }
+}
+
+/// # Overview
+/// A microburst is a column of sinking air that hits the ground and spreads
+/// out in every direction. Flying through one you first get a headwind,
+/// then a strong downdraft, then a tailwind, the worst possible order!
+///
+/// # Model
+/// Inside the core (`r < radius`) air sinks at `downdraft` m/s, slowing
+/// linearly to zero at the ground over the bottom `outflow_depth` metres.
+/// That air must go somewhere so it flows outwards, within the outflow
+/// layer, at a speed that keeps mass conserved:
+///
+/// - inside the core `u = downdraft * r / (2 * outflow_depth)`
+/// - outside the core `u = downdraft * radius² / (2 * r * outflow_depth)`
+///
+/// The ground is the `y` of `centre`.
+#[derive(Debug, Clone, Copy)]
+pub struct Microburst {
+    start: Duration,
+    duration: Option<Duration>,
+    centre: Position,
+    radius: f64,
+    downdraft: f64,
+    outflow_depth: f64,
+}
+
+impl Microburst {
+    pub fn new(
+        start: Duration,
+        duration: Option<Duration>,
+        centre: Position,
+        radius: f64,
+        downdraft: f64,
+        outflow_depth: f64,
+    ) -> Result<Self> {
+        if !(radius > 0.0 && outflow_depth > 0.0) {
+            return Err(EleaError::InvalidData(format!(
+                "microburst radius ({radius}) and outflow depth ({outflow_depth}) must be positive"
+            )));
+        }
+        Ok(Self {
+            start,
+            duration,
+            centre,
+            radius,
+            downdraft,
+            outflow_depth,
+        })
+    }
+}
+
+impl WindField for Microburst {
+    fn wind_at(&self, position: Position, time: Duration) -> LinearVelocity {
+        let height = position.y - self.centre.y;
+        if active_time(self.start, self.duration, time).is_none() || height < 0.0 {
+            return LinearVelocity::default();
+        }
+        let (distance, direction) = radial(self.centre, position);
+        let in_core = distance < self.radius;
+
+        let vertical = match in_core {
+            true => -self.downdraft * (height / self.outflow_depth).min(1.0),
+            false => 0.0,
+        };
+        let outflow = match (height < self.outflow_depth, in_core) {
+            (false, _) => 0.0,
+            (true, true) => self.downdraft * distance / (2.0 * self.outflow_depth),
+            (true, false) => {
+                self.downdraft * self.radius.powi(2) / (2.0 * distance * self.outflow_depth)
+            }
+        };
+
+        LinearVelocity(direction.scalar_mul(outflow) + Vector3::new(0.0, vertical, 0.0))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum WeatherEvent {
+    OneMinusCosineGust(OneMinusCosineGust),
+    StepGust(StepGust),
+    Thermal(Thermal),
+    Microburst(Microburst),
+}
+
+impl WindField for WeatherEvent {
+    fn wind_at(&self, position: Position, time: Duration) -> LinearVelocity {
+        match self {
+            WeatherEvent::OneMinusCosineGust(gust) => gust.wind_at(position, time),
+            WeatherEvent::StepGust(gust) => gust.wind_at(position, time),
+            WeatherEvent::Thermal(thermal) => thermal.wind_at(position, time),
+            WeatherEvent::Microburst(microburst) => microburst.wind_at(position, time),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_one_minus_cosine_gust_peaks_halfway() {
+        let peak = Vector3::new(10.0, 0.0, 0.0);
+        let gust =
+            OneMinusCosineGust::new(Duration::from_secs(2), Duration::from_secs(4), peak).unwrap();
+        let origin = Position::default();
+
+        assert_eq!(gust.wind_at(origin, Duration::from_secs(1)).x, 0.0);
+        assert!((gust.wind_at(origin, Duration::from_secs(4)).x - 10.0).abs() < 1e-9);
+        assert_eq!(gust.wind_at(origin, Duration::from_secs(7)).x, 0.0);
+
+        assert!(OneMinusCosineGust::new(Duration::ZERO, Duration::ZERO, peak).is_err());
+    }
+
+    #[test]
+    fn test_microburst_outflow_conserves_mass() {
+        let microburst = Microburst::new(
+            Duration::ZERO,
+            None,
+            Position::default(),
+            500.0,
+            20.0,
+            100.0,
+        )
+        .unwrap();
+        // All the air coming down through the core must leave through the
+        // side of a cylinder of the outflow layer at any larger radius.
+        let down_flux = PI * 500.0_f64.powi(2) * 20.0;
+        let outflow = microburst.wind_at(Position::new(1000.0, 50.0, 0.0), Duration::ZERO);
+        let out_flux = 2.0 * PI * 1000.0 * 100.0 * outflow.x;
+
+        assert!((down_flux - out_flux).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_step_gust_ramps_and_cuts_out() {
+        let gust = StepGust {
+            start: Duration::from_secs(1),
+            rise: Duration::from_secs(2),
+            duration: Some(Duration::from_secs(5)),
+            velocity: Vector3::new(0.0, 0.0, 8.0),
+        };
+        let origin = Position::default();
+
+        assert_eq!(gust.wind_at(origin, Duration::ZERO).z, 0.0);
+        assert!((gust.wind_at(origin, Duration::from_secs(2)).z - 4.0).abs() < 1e-9);
+        assert_eq!(gust.wind_at(origin, Duration::from_secs(4)).z, 8.0);
+        assert_eq!(gust.wind_at(origin, Duration::from_secs(7)).z, 0.0);
+    }
+
+    #[test]
+    fn test_thermal_rises_from_its_centre() {
+        let thermal = Thermal::new(
+            Duration::ZERO,
+            None,
+            Position::new(0.0, 100.0, 0.0),
+            50.0,
+            4.0,
+            1000.0,
+        )
+        .unwrap();
+        let wind = |x, y| thermal.wind_at(Position::new(x, y, 0.0), Duration::ZERO).y;
+
+        // Full strength in the core, sinking outside the radius
+        assert!((wind(0.0, 200.0) - 4.0).abs() < 1e-9);
+        assert!(wind(80.0, 200.0) < 0.0);
+        // Nothing below the ground it starts from, or over the top
+        assert_eq!(wind(0.0, 50.0), 0.0);
+        assert_eq!(wind(0.0, 1150.0), 0.0);
+
+        assert!(Thermal::new(Duration::ZERO, None, Position::default(), 0.0, 4.0, 100.0).is_err());
+    }
+}
@@ -0,0 +1,450 @@
+//! # Overview
+//!
+//! A precomputed wind field sampled on a regular 3D grid, for example an
+//! export from a CFD run around buildings. The field may have several time
+//! frames, between which we interpolate linearly.
+//!
+//! # Interpolation
+//! Inside a grid cell we use *trilinear* interpolation: blend along `x`,
+//! then along `y`, then along `z` between the eight corners of the cell.
+//! Between two time frames we do the same again in time. Outside the grid
+//! (in space or time) the value at the nearest edge is held.
+//!
+//! # File formats
+//!
+//! ## CSV
+//! One sample per line as `t,x,y,z,u,v,w` where `t` is seconds, `x,y,z` the
+//! grid point in metres and `u,v,w` the wind velocity in m/s. A header line
+//! and lines beginning with `#` are skipped. Every time frame must cover the
+//! full regular grid, in any order.
+//!
+//! ## Binary
+//! Little endian, laid out as:
+//!
+//! | Field | Type |
+//! |-------|------|
+//! | magic `ELWG` | 4 bytes |
+//! | version (`1`) | `u32` |
+//! | `nx`, `ny`, `nz`, `nt` | `u32` each |
+//! | origin `x,y,z` | `f64` each |
+//! | spacing `x,y,z` | `f64` each |
+//! | frame times | `nt` × `f64` |
+//! | samples `u,v,w` | `nt·nz·ny·nx` × 3 × `f64`, `x` varying fastest |
+
+use std::{
+    cmp::Ordering,
+    fs::File,
+    io::{BufRead, BufReader, Read, Write},
+    path::Path,
+    time::Duration,
+};
+
+use crate::{
+    physics::{
+        state::{linear_velocity::LinearVelocity, position::Position},
+        util::vector::Vector3,
+    },
+    EleaError, Result,
+};
+
+use super::WindField;
+
+const MAGIC: &[u8; 4] = b"ELWG";
+const VERSION: u32 = 1;
+
+/// Tolerance used when checking the CSV points really are evenly spaced.
+const SPACING_TOLERANCE: f64 = 1e-6;
+
+#[derive(Debug, Clone)]
+pub struct WindGrid {
+    origin: Vector3,
+    spacing: Vector3,
+    /// Number of points along `x`, `y` and `z`
+    size: [usize; 3],
+    /// Time of each frame in seconds, strictly increasing
+    times: Vec<f64>,
+    /// One entry per time frame, each `nx·ny·nz` long with `x` varying fastest
+    frames: Vec<Vec<Vector3>>,
+}
+
+impl WindGrid {
+    pub fn new(
+        origin: Vector3,
+        spacing: Vector3,
+        size: [usize; 3],
+        times: Vec<f64>,
+        frames: Vec<Vec<Vector3>>,
+    ) -> Result<Self> {
+        let points = grid_points(size)?;
+        if points == 0 {
+            return Err(EleaError::InvalidData("wind grid has no points".into()));
+        }
+        if ![spacing.x, spacing.y, spacing.z]
+            .iter()
+            .all(|step| step.is_finite() && *step > 0.0)
+        {
+            return Err(EleaError::InvalidData(format!(
+                "wind grid spacing must be positive, got {spacing:?}"
+            )));
+        }
+        if ![origin.x, origin.y, origin.z].iter().all(|v| v.is_finite()) {
+            return Err(EleaError::InvalidData(format!(
+                "wind grid origin must be finite, got {origin:?}"
+            )));
+        }
+        if !times.iter().all(|time| time.is_finite()) {
+            return Err(EleaError::InvalidData(
+                "wind grid frame times must be finite".into(),
+            ));
+        }
+        if times.is_empty() || times.len() != frames.len() {
+            return Err(EleaError::InvalidData(format!(
+                "wind grid has {} frame times but {} frames",
+                times.len(),
+                frames.len()
+            )));
+        }
+        if times.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(EleaError::InvalidData(
+                "wind grid frame times must be strictly increasing".into(),
+            ));
+        }
+        if let Some(frame) = frames.iter().find(|frame| frame.len() != points) {
+            return Err(EleaError::InvalidData(format!(
+                "wind grid frame has {} samples, expected {points}",
+                frame.len()
+            )));
+        }
+        Ok(Self {
+            origin,
+            spacing,
+            size,
+            times,
+            frames,
+        })
+    }
+
+    /// Loads a grid, picking the format from the file extension: `.csv` is
+    /// read as CSV and anything else as the binary format.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let reader = BufReader::new(File::open(path)?);
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => Self::from_csv(reader),
+            _ => Self::from_binary(reader),
+        }
+    }
+
+    pub fn from_csv<R: BufRead>(reader: R) -> Result<Self> {
+        let mut samples = Vec::new();
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let values = line
+                .split(',')
+                .map(|value| value.trim().parse::<f64>())
+                .collect::<std::result::Result<Vec<_>, _>>();
+            match values {
+                Ok(values) if values.iter().any(|value| !value.is_finite()) => {
+                    return Err(EleaError::InvalidData(format!(
+                        "line {}: values must be finite",
+                        number + 1
+                    )))
+                }
+                Ok(values) if values.len() == 7 => samples.push(values),
+                Ok(values) => {
+                    return Err(EleaError::InvalidData(format!(
+                        "line {}: expected 7 columns (t,x,y,z,u,v,w), found {}",
+                        number + 1,
+                        values.len()
+                    )))
+                }
+                // Headers are the only non numeric line we accept
+                Err(_) if samples.is_empty() && number == 0 => continue,
+                Err(e) => return Err(EleaError::InvalidData(format!("line {}: {e}", number + 1))),
+            }
+        }
+
+        let times = regular_axis(samples.iter().map(|s| s[0]), None)?;
+        let axes = [1, 2, 3].map(|column| samples.iter().map(move |s| s[column]));
+        let [xs, ys, zs] = axes.map(|axis| regular_axis(axis, Some("grid")));
+        let (xs, ys, zs) = (xs?, ys?, zs?);
+        let size = [xs.len(), ys.len(), zs.len()];
+        let spacing = Vector3::new(step(&xs), step(&ys), step(&zs));
+        let origin = Vector3::new(xs[0], ys[0], zs[0]);
+
+        let points = grid_points(size)?;
+        // Catch a sparse scatter of samples before allocating a grid for it
+        if points.checked_mul(times.len()) != Some(samples.len()) {
+            return Err(EleaError::InvalidData(
+                "wind grid is missing samples for some points".into(),
+            ));
+        }
+        let mut frames = vec![vec![None; points]; times.len()];
+        for sample in &samples {
+            let frame = index_of(&times, sample[0])?;
+            let [i, j, k] =
+                [(1, &xs), (2, &ys), (3, &zs)].map(|(column, axis)| index_of(axis, sample[column]));
+            let (i, j, k) = (i?, j?, k?);
+            let slot = &mut frames[frame][i + size[0] * (j + size[1] * k)];
+            if slot.is_some() {
+                return Err(EleaError::InvalidData(format!(
+                    "duplicate wind sample at t={} ({},{},{})",
+                    sample[0], sample[1], sample[2], sample[3]
+                )));
+            }
+            *slot = Some(Vector3::new(sample[4], sample[5], sample[6]));
+        }
+
+        let frames = frames
+            .into_iter()
+            .map(|frame| frame.into_iter().collect::<Option<Vec<_>>>())
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| {
+                EleaError::InvalidData("wind grid is missing samples for some points".into())
+            })?;
+
+        Self::new(origin, spacing, size, times, frames)
+    }
+
+    pub fn from_binary<R: Read>(mut reader: R) -> Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(EleaError::InvalidData("not a wind grid file".into()));
+        }
+        let version = read_u32(&mut reader)?;
+        if version != VERSION {
+            return Err(EleaError::InvalidData(format!(
+                "unsupported wind grid version {version}"
+            )));
+        }
+        let [nx, ny, nz, nt] = [(); 4].map(|_| read_u32(&mut reader).map(|n| n as usize));
+        let (size, nt) = ([nx?, ny?, nz?], nt?);
+        let origin = read_vector(&mut reader)?;
+        let spacing = read_vector(&mut reader)?;
+        let times = (0..nt)
+            .map(|_| read_f64(&mut reader))
+            .collect::<Result<Vec<_>>>()?;
+        let points = grid_points(size)?;
+        let frames = (0..nt)
+            .map(|_| {
+                (0..points)
+                    .map(|_| read_vector(&mut reader))
+                    .collect::<Result<Vec<_>>>()
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Self::new(origin, spacing, size, times, frames)
+    }
+
+    pub fn write_binary<W: Write>(&self, mut writer: W) -> Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        for n in self.size.iter().chain(std::iter::once(&self.times.len())) {
+            let n = u32::try_from(*n).map_err(|_| {
+                EleaError::InvalidData(format!("wind grid axis of {n} is too long to write"))
+            })?;
+            writer.write_all(&n.to_le_bytes())?;
+        }
+        write_vector(&mut writer, self.origin)?;
+        write_vector(&mut writer, self.spacing)?;
+        for time in &self.times {
+            writer.write_all(&time.to_le_bytes())?;
+        }
+        for sample in self.frames.iter().flatten() {
+            write_vector(&mut writer, *sample)?;
+        }
+        Ok(())
+    }
+
+    /// Trilinear interpolation within a single time frame.
+    fn sample_frame(&self, frame: &[Vector3], position: Vector3) -> Vector3 {
+        let cells = [
+            cell(position.x - self.origin.x, self.spacing.x, self.size[0]),
+            cell(position.y - self.origin.y, self.spacing.y, self.size[1]),
+            cell(position.z - self.origin.z, self.spacing.z, self.size[2]),
+        ];
+        let index = |i: usize, j: usize, k: usize| i + self.size[0] * (j + self.size[1] * k);
+        let [(i0, i1, tx), (j0, j1, ty), (k0, k1, tz)] = cells;
+
+        let along_x = |j, k| lerp(frame[index(i0, j, k)], frame[index(i1, j, k)], tx);
+        let along_y = |k| lerp(along_x(j0, k), along_x(j1, k), ty);
+        lerp(along_y(k0), along_y(k1), tz)
+    }
+}
+
+impl WindField for WindGrid {
+    fn wind_at(&self, position: Position, time: Duration) -> LinearVelocity {
+        let t = time.as_secs_f64();
+        let next = self.times.partition_point(|frame| *frame <= t);
+        let wind = match next {
+            0 => self.sample_frame(&self.frames[0], position.0),
+            n if n == self.times.len() => self.sample_frame(&self.frames[n - 1], position.0),
+            n => {
+                let blend = (t - self.times[n - 1]) / (self.times[n] - self.times[n - 1]);
+                lerp(
+                    self.sample_frame(&self.frames[n - 1], position.0),
+                    self.sample_frame(&self.frames[n], position.0),
+                    blend,
+                )
+            }
+        };
+        LinearVelocity(wind)
+    }
+}
+
+#[inline]
+fn lerp(a: Vector3, b: Vector3, t: f64) -> Vector3 {
+    a + (b - a).scalar_mul(t)
+}
+
+/// The two grid indices either side of `offset` along an axis and how far
+/// between them we are, clamped to the edges of the grid.
+fn cell(offset: f64, spacing: f64, points: usize) -> (usize, usize, f64) {
+    let last = points - 1;
+    let fractional = (offset / spacing).clamp(0.0, last as f64);
+    let lower = (fractional.floor() as usize).min(last.saturating_sub(1));
+    let upper = (lower + 1).min(last);
+    (lower, upper, fractional - lower as f64)
+}
+
+/// Sorted unique values along an axis, checking they're evenly spaced when
+/// `regular` names the axis.
+fn regular_axis<I: Iterator<Item = f64>>(values: I, regular: Option<&str>) -> Result<Vec<f64>> {
+    let mut values = values.collect::<Vec<_>>();
+    if values.iter().any(|v| !v.is_finite()) {
+        return Err(EleaError::InvalidData(
+            "wind grid contains non finite coordinates".into(),
+        ));
+    }
+    values.sort_by(f64::total_cmp);
+    values.dedup();
+    if values.is_empty() {
+        return Err(EleaError::InvalidData(
+            "wind grid file has no samples".into(),
+        ));
+    }
+    if let Some(name) = regular {
+        let spacing = step(&values);
+        if values.windows(2).any(|pair| {
+            ((pair[1] - pair[0]) - spacing).abs() > SPACING_TOLERANCE * spacing.max(1.0)
+        }) {
+            return Err(EleaError::InvalidData(format!(
+                "wind {name} points are not evenly spaced"
+            )));
+        }
+    }
+    Ok(values)
+}
+
+/// Where `value` sits in a sorted `axis`.
+fn index_of(axis: &[f64], value: f64) -> Result<usize> {
+    axis.binary_search_by(|v| v.partial_cmp(&value).unwrap_or(Ordering::Less))
+        .map_err(|_| EleaError::InvalidData(format!("wind sample at {value} is off the grid")))
+}
+
+/// `nx·ny·nz`, or an error if that doesn't fit in a `usize`.
+fn grid_points(size: [usize; 3]) -> Result<usize> {
+    size.iter()
+        .try_fold(1usize, |points, n| points.checked_mul(*n))
+        .ok_or_else(|| EleaError::InvalidData(format!("wind grid of {size:?} points is too big")))
+}
+
+/// Spacing of an already regular axis, a single point gets a spacing of 1 so
+/// it never divides by zero.
+fn step(axis: &[f64]) -> f64 {
+    match axis {
+        [first, second, ..] => second - first,
+        _ => 1.0,
+    }
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_f64<R: Read>(reader: &mut R) -> Result<f64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(f64::from_le_bytes(bytes))
+}
+
+fn write_vector<W: Write>(writer: &mut W, vector: Vector3) -> Result<()> {
+    for value in [vector.x, vector.y, vector.z] {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn read_vector<R: Read>(reader: &mut R) -> Result<Vector3> {
+    Ok(Vector3::new(
+        read_f64(reader)?,
+        read_f64(reader)?,
+        read_f64(reader)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CSV: &str = "t,x,y,z,u,v,w
+0,0,0,0,0,0,0
+0,2,0,0,4,0,0
+0,0,2,0,0,0,0
+0,2,2,0,4,0,0
+10,0,0,0,0,0,2
+10,2,0,0,4,0,2
+10,0,2,0,0,0,2
+10,2,2,0,4,0,2
+";
+
+    #[test]
+    fn test_csv_grid_interpolates_in_space_and_time() {
+        let grid = WindGrid::from_csv(CSV.as_bytes()).unwrap();
+
+        let wind = grid.wind_at(Position::new(1.0, 1.0, 0.0), Duration::from_secs(5));
+        assert!((wind.0 - Vector3::new(2.0, 0.0, 1.0)).magnitude() < 1e-9);
+
+        // Outside the grid the edge value is held
+        let wind = grid.wind_at(Position::new(50.0, -3.0, 0.0), Duration::from_secs(60));
+        assert!((wind.0 - Vector3::new(4.0, 0.0, 2.0)).magnitude() < 1e-9);
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let grid = WindGrid::from_csv(CSV.as_bytes()).unwrap();
+        let mut bytes = Vec::new();
+        grid.write_binary(&mut bytes).unwrap();
+        let loaded = WindGrid::from_binary(bytes.as_slice()).unwrap();
+
+        let position = Position::new(0.5, 1.5, 0.0);
+        let time = Duration::from_secs(3);
+        assert_eq!(grid.wind_at(position, time), loaded.wind_at(position, time));
+    }
+
+    #[test]
+    fn test_rejects_bad_grids() {
+        let nan = CSV.replace("10,2,2,0,4,0,2", "10,2,2,0,NaN,0,2");
+        assert!(WindGrid::from_csv(nan.as_bytes()).is_err());
+
+        let infinite = Vector3::new(f64::INFINITY, 1.0, 1.0);
+        let frames = vec![vec![Vector3::default()]];
+        assert!(WindGrid::new(Vector3::default(), infinite, [1; 3], vec![0.0], frames).is_err());
+
+        // A header promising more points than a usize can count
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        for _ in 0..4 {
+            bytes.extend(u32::MAX.to_le_bytes());
+        }
+        bytes.extend([0; 48]);
+        assert!(WindGrid::from_binary(bytes.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,69 @@
+//! # Overview
+//!
+//! Everything the air around the drone is doing. We model weather purely as
+//! the velocity of the air mass at a point in space and time, the drone then
+//! feels it through drag on its velocity *relative* to that air.
+//!
+//! Every source of wind implements [`WindField`], and the [`Weather`] the
+//! simulator flies through is just the sum of all of them.
+//!
+//! ## Axes
+//! As with the rest of the physics `y` is up, so an updraft is positive `y`
+//! and the ground plane is spanned by `x` and `z`.
+pub mod events;
+pub mod grid;
+pub mod turbulence;
+
+use std::time::Duration;
+
+use crate::physics::state::{linear_velocity::LinearVelocity, position::Position};
+
+pub use events::{Microburst, OneMinusCosineGust, StepGust, Thermal, WeatherEvent};
+pub use grid::WindGrid;
+pub use turbulence::NoiseTurbulence;
+
+/// A source of wind that can be sampled anywhere in the simulated world.
+pub trait WindField {
+    /// Velocity of the air at `position`, `time` into the simulation.
+    fn wind_at(&self, position: Position, time: Duration) -> LinearVelocity;
+}
+
+/// All the wind sources active in a simulation, their contributions are
+/// summed together (superposition) so a steady wind plus a gust is simply
+/// both added.
+#[derive(Default)]
+pub struct Weather {
+    fields: Vec<Box<dyn WindField>>,
+}
+
+impl Weather {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add<W: WindField + 'static>(&mut self, field: W) {
+        self.fields.push(Box::new(field));
+    }
+
+    pub fn with<W: WindField + 'static>(mut self, field: W) -> Self {
+        self.add(field);
+        self
+    }
+}
+
+impl WindField for Weather {
+    fn wind_at(&self, position: Position, time: Duration) -> LinearVelocity {
+        self.fields
+            .iter()
+            .fold(LinearVelocity::default(), |total, field| {
+                total + field.wind_at(position, time)
+            })
+    }
+}
+
+/// The simplest weather there is, a constant wind blowing everywhere.
+impl WindField for LinearVelocity {
+    fn wind_at(&self, _position: Position, _time: Duration) -> LinearVelocity {
+        *self
+    }
+}
@@ -0,0 +1,175 @@
+//! # Overview
+//!
+//! Spatially coherent turbulence built from gradient (Perlin) noise.
+//!
+//! Unlike a purely time based disturbance, the wind here is a property of
+//! *where* you are. Two drones flying a few metres apart sample nearly the
+//! same part of the field, so they feel correlated gusts, just like in real
+//! air.
+//!
+//! # Frozen turbulence
+//! To make the field change over time we use Taylor's frozen turbulence
+//! hypothesis: the eddies don't evolve, the whole pattern is simply carried
+//! along by the mean wind. So sampling at time `t` is sampling the field at
+//! `position - mean_wind * t`.
+
+use std::time::Duration;
+
+use crate::{
+    physics::{
+        math::GradientNoise,
+        state::{linear_velocity::LinearVelocity, position::Position},
+        util::vector::Vector3,
+    },
+    EleaError, Result,
+};
+
+use super::WindField;
+
+/// Offsets into the noise space so each wind component is uncorrelated
+/// with the others, otherwise x, y and z would gust in lockstep.
+const COMPONENT_OFFSETS: [f64; 3] = [0.0, 137.31, 271.77];
+
+/// # Overview
+/// A 3D wind perturbation field made of several octaves of
+/// [`GradientNoise`] (fractal Brownian motion).
+///
+/// - `scale` is the size, in metres, of the largest eddies.
+/// - Each extra octave adds eddies `lacunarity` times smaller, at
+///   `persistence` times the strength of the previous one, `2` and `0.5`
+///   unless set otherwise.
+/// - `intensity` is the peak amplitude (m/s) of each wind component, the
+///   noise stays roughly within `±intensity`.
+/// - `mean_wind` carries the field along over time, it is **not** added to
+///   the output, add a steady wind to the [`Weather`](super::Weather) for that.
+#[derive(Debug, Clone)]
+pub struct NoiseTurbulence {
+    noise: GradientNoise,
+    scale: f64,
+    /// At least one
+    octaves: u32,
+    persistence: f64,
+    lacunarity: f64,
+    intensity: f64,
+    mean_wind: Vector3,
+}
+
+impl NoiseTurbulence {
+    /// Errors unless `scale` is above zero and both are finite.
+    pub fn new(seed: u64, scale: f64, octaves: u32, intensity: f64) -> Result<Self> {
+        if !scale.is_finite() || scale <= 0.0 || !intensity.is_finite() {
+            return Err(EleaError::InvalidData(format!(
+                "turbulence needs a positive scale and finite intensity, got {scale} and {intensity}"
+            )));
+        }
+        Ok(Self {
+            noise: GradientNoise::new(seed),
+            scale,
+            octaves: octaves.max(1),
+            persistence: 0.5,
+            lacunarity: 2.0,
+            intensity,
+            mean_wind: Vector3::default(),
+        })
+    }
+
+    pub fn octaves(&self) -> u32 {
+        self.octaves
+    }
+
+    pub fn with_octaves(mut self, octaves: u32) -> Self {
+        self.octaves = octaves.max(1);
+        self
+    }
+
+    pub fn with_persistence(mut self, persistence: f64) -> Self {
+        self.persistence = persistence;
+        self
+    }
+
+    pub fn with_lacunarity(mut self, lacunarity: f64) -> Self {
+        self.lacunarity = lacunarity;
+        self
+    }
+
+    pub fn with_mean_wind(mut self, mean_wind: Vector3) -> Self {
+        self.mean_wind = mean_wind;
+        self
+    }
+}
+
+impl WindField for NoiseTurbulence {
+    fn wind_at(&self, position: Position, time: Duration) -> LinearVelocity {
+        let advected = position.0 - self.mean_wind.scalar_mul(time.as_secs_f64());
+        let point = advected.scalar_div(self.scale);
+        let [x, y, z] = COMPONENT_OFFSETS.map(|offset| {
+            let point = point + Vector3::new(offset, offset, offset);
+            self.intensity
+                * self.noise.fractal(
+                    point.x,
+                    point.y,
+                    point.z,
+                    self.octaves,
+                    self.persistence,
+                    self.lacunarity,
+                )
+        });
+        LinearVelocity::new(x, y, z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_noise_is_repeatable_for_a_seed() {
+        let a = NoiseTurbulence::new(42, 20.0, 4, 3.0).unwrap();
+        let b = NoiseTurbulence::new(42, 20.0, 4, 3.0).unwrap();
+        let position = Position::new(12.3, 45.6, 78.9);
+
+        assert_eq!(
+            a.wind_at(position, Duration::from_secs(3)),
+            b.wind_at(position, Duration::from_secs(3))
+        );
+    }
+
+    #[test]
+    fn test_nearby_points_are_correlated() {
+        let turbulence = NoiseTurbulence::new(7, 50.0, 3, 5.0).unwrap();
+        let here = turbulence.wind_at(Position::new(100.3, 20.1, 40.7), Duration::ZERO);
+        let near = turbulence.wind_at(Position::new(100.8, 20.1, 40.7), Duration::ZERO);
+
+        assert!((here - near).magnitude() < 0.5);
+    }
+
+    #[test]
+    fn test_field_is_carried_by_mean_wind() {
+        let turbulence = NoiseTurbulence::new(3, 30.0, 2, 2.0)
+            .unwrap()
+            .with_mean_wind(Vector3::new(5.0, 0.0, 0.0));
+        let upwind = turbulence.wind_at(Position::new(10.3, 5.5, 1.7), Duration::ZERO);
+        let downwind = turbulence.wind_at(Position::new(20.3, 5.5, 1.7), Duration::from_secs(2));
+
+        assert!((upwind - downwind).magnitude() < 1e-9);
+    }
+
+    #[test]
+    fn test_needs_at_least_one_octave() {
+        let turbulence = NoiseTurbulence::new(5, 10.0, 3, 1.0)
+            .unwrap()
+            .with_octaves(0);
+        assert_eq!(turbulence.octaves(), 1);
+
+        let wind = turbulence.wind_at(Position::new(1.3, 2.7, 3.1), Duration::ZERO);
+        assert!(wind.magnitude().is_finite());
+    }
+
+    #[test]
+    fn test_rejects_bad_scales() {
+        for scale in [0.0, -10.0, f64::NAN, f64::INFINITY] {
+            assert!(NoiseTurbulence::new(5, scale, 3, 1.0).is_err());
+        }
+        assert!(NoiseTurbulence::new(5, 10.0, 3, f64::NAN).is_err());
+    }
+}